@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::iter;
+use std::process::{ Command, Stdio };
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+
+use failure::Error;
+use itertools::Itertools;
+use serenity::prelude::Mutex;
+use typemap::Key;
+
+use uwin::Course;
+
+/// Seconds a rendered card is reused before the course is rendered again, so
+/// availability doesn't go stale.
+const CACHE_SECS: u64 = 60 * 60;
+
+/// Program that turns the HTML of a card into an image.
+const RASTERIZER: &str = "wkhtmltoimage";
+
+/// Stylesheet of a card.
+const STYLE: &str = "body { font-family: sans-serif; margin: 24px; color: #222; } \
+    h1 { margin: 0; color: #005596; } \
+    h2 { margin: 4px 0 16px; font-weight: normal; } \
+    .note { font-style: italic; } \
+    th { text-align: left; vertical-align: top; padding: 4px 16px 4px 0; white-space: nowrap; } \
+    td { white-space: pre-line; padding: 4px 0; }";
+
+/// The rasterizer couldn't be run or didn't produce an image.
+#[derive(Debug, Fail)]
+#[fail(display = "Couldn't render course card: {}", _0)]
+pub struct RenderError(String);
+
+/// Rendered cards by term and course code, with when they were rendered.
+pub type Cards = Mutex<HashMap<(String, String), (Instant, Vec<u8>)>>;
+
+pub struct CardCache;
+
+impl Key for CardCache {
+    type Value = Arc<Cards>;
+}
+
+/// Returns the cached card for a course if it's recent enough.
+pub fn cached(cache: &Cards, term: &str, code: &str) -> Option<Vec<u8>> {
+    let cache = cache.lock();
+
+    cache.get(&(term.to_owned(), code.to_owned()))
+        .filter(|&&(rendered, _)| rendered.elapsed() < Duration::from_secs(CACHE_SECS))
+        .map(|&(_, ref png)| png.clone())
+}
+
+/// Keep a rendered card, dropping any that have expired.
+pub fn store(cache: &Cards, term: &str, code: &str, png: Vec<u8>) {
+    let mut cache = cache.lock();
+
+    cache.retain(|_, &mut (rendered, _)| rendered.elapsed() < Duration::from_secs(CACHE_SECS));
+    cache.insert((term.to_owned(), code.to_owned()), (Instant::now(), png));
+}
+
+/// Render a course to a PNG image.
+pub fn render(course: &Course) -> Result<Vec<u8>, Error> {
+    let mut child = Command::new(RASTERIZER)
+        .args(&["--quiet", "--format", "png", "--width", "600", "-", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| RenderError(format!("couldn't run {}: {}", RASTERIZER, e)))?;
+
+    child.stdin.take()
+        .ok_or_else(|| RenderError("no input to write to".to_owned()))?
+        .write_all(html(course).as_bytes())?;
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() || output.stdout.is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(RenderError(stderr.trim().to_owned()).into());
+    }
+
+    Ok(output.stdout)
+}
+
+/// The HTML page for a course card.
+pub fn html(course: &Course) -> String {
+    let exams = course.exams.iter()
+        .map(|exam| {
+            iter::once(exam.ty.clone())
+                .chain(exam.date_string())
+                .chain(exam.time_string())
+                .chain(exam.building.clone())
+                .chain(exam.room.clone())
+                .join(" ")
+        })
+        .join("\n");
+
+    // Rows with nothing to show are left out.
+    let rows = vec![
+            ("Meets", course.meets.iter().join("\n")),
+            ("Dates", format!("{} to {}", course.starts, course.ends)),
+            ("Campus", course.campus.clone()),
+            ("Delivery", course.delivery.clone().unwrap_or_default()),
+            ("Availability", course.availability.to_string()),
+            ("Course Value", course.course_value.clone()),
+            ("Drops Close", course.date_drops_close.clone()),
+            ("Instructors", course.instructors.iter().map(|ins| &ins.name).join("\n")),
+            ("Restrictions", course.restrictions.join("\n")),
+            ("Prerequisites", course.prereqs.join("\n")),
+            ("Antirequisites", course.antireqs.join("\n")),
+            ("Materials", course.materials.join("\n")),
+            ("Breadth", course.breadth.join(", ")),
+            ("Exams", exams),
+        ]
+        .into_iter()
+        .filter(|&(_, ref value)| !value.is_empty())
+        .map(|(name, value)| format!("<tr><th>{}</th><td>{}</td></tr>", name, escape(&value)))
+        .join("");
+
+    let note = course.note.as_ref()
+        .map(|note| format!("<p class=\"note\">{}</p>", escape(note)))
+        .unwrap_or_default();
+
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><style>{}</style></head><body>\
+        <h1>{}</h1><h2>{}</h2><p>{}</p>{}<table>{}</table></body></html>\n",
+        STYLE,
+        escape(&course.code),
+        escape(&course.title),
+        escape(&course.description),
+        note,
+        rows,
+    )
+}
+
+/// Escape text for use in HTML.
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_owned(),
+            '<' => "&lt;".to_owned(),
+            '>' => "&gt;".to_owned(),
+            '"' => "&quot;".to_owned(),
+            '\'' => "&#39;".to_owned(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uwin::tests::course;
+
+    #[test]
+    fn card_shows_the_course_with_text_escaped() {
+        let mut course = course("COMP100001");
+        course.title = "Data <Structures> & Algorithms".to_owned();
+        course.note = Some("Majors \"only\"".to_owned());
+        course.prereqs = vec!["COMP-1000".to_owned(), "MATH-1720".to_owned()];
+
+        let html = html(&course);
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h1>COMP100001</h1><h2>Data &lt;Structures&gt; &amp; Algorithms</h2>"));
+        assert!(html.contains("<p class=\"note\">Majors &quot;only&quot;</p>"));
+        assert!(html.contains("<tr><th>Prerequisites</th><td>COMP-1000\nMATH-1720</td></tr>"));
+        assert!(html.contains("<tr><th>Dates</th><td>Sep 6, 2018 to Dec 4, 2018</td></tr>"));
+
+        // Rows with nothing in them are left out.
+        assert!(!html.contains("Antirequisites"));
+        assert!(!html.contains("Delivery"));
+    }
+}
@@ -0,0 +1,171 @@
+use std::env;
+use std::fs;
+use std::str::FromStr;
+
+use failure::Error;
+use typemap::Key;
+
+const CONFIG_PATH: &str = "./config.toml";
+
+/// A setting has a value the bot can't run with.
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid config: {}", _0)]
+pub struct ConfigError(String);
+
+/// How the bot is run, read from `config.toml` when the bot starts. Each
+/// setting can be overridden by an environment variable, so a deployment
+/// can be changed without rebuilding or editing the file.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The Discord bot token, or `DISCORD_TOKEN`.
+    pub token: Option<String>,
+    /// What commands start with, or `BOT_PREFIX`. It can't be empty or have
+    /// whitespace, since commands are split on whitespace.
+    pub prefix: Option<String>,
+    /// The directory the course index is kept in, or `INDEX_PATH`. A new
+    /// index is built next to it before replacing it.
+    pub index_path: Option<String>,
+    /// The term searched when none is given, or `DEFAULT_TERM`. The newest
+    /// indexed term is used when it isn't set.
+    pub default_term: Option<String>,
+    /// Results listed at once for a search, or `LIST_LIMIT`.
+    pub list_limit: Option<usize>,
+    /// Characters of a single course's description, or `DESCRIPTION_LIMIT`.
+    pub description_limit: Option<usize>,
+    /// Characters of each field of a single course, or `FIELD_LIMIT`.
+    pub field_limit: Option<usize>,
+    /// Requests made at once while indexing, or `SCRAPE_CONCURRENCY`.
+    pub scrape_concurrency: Option<usize>,
+    /// Minutes a scraped course is reused, or `COURSE_CACHE_MINUTES`.
+    pub cache_minutes: Option<u64>,
+    /// Seconds a user waits between searches, or `COURSE_COOLDOWN_SECS`.
+    pub course_cooldown_secs: Option<u64>,
+    /// Minutes between checks of watched sections, or `WATCH_CHECK_MINUTES`.
+    pub watch_minutes: Option<u64>,
+    /// Whether the most looked up courses are scraped again after a reindex
+    /// so the first lookups of them are quick, or `PREFETCH`. Off unless set.
+    pub prefetch: Option<bool>,
+    /// Most courses scraped ahead of time after a reindex, or `PREFETCH_MAX`.
+    pub prefetch_max: Option<usize>,
+    /// Hours between checks for terms newer than the index, or
+    /// `STALE_CHECK_HOURS`.
+    pub stale_check_hours: Option<u64>,
+    /// Newer live terms it takes for the index to count as stale, or
+    /// `STALE_TERMS`.
+    pub stale_terms: Option<usize>,
+    /// The id of a channel told when the index is stale, or
+    /// `STALE_NOTIFY_CHANNEL`. A stale index is only logged unless set.
+    pub stale_notify_channel: Option<u64>,
+    /// Term codes to index, or the comma separated `INDEX_TERMS`. Every
+    /// term is indexed when it isn't set.
+    pub index_terms: Option<Vec<String>>,
+    /// Subject codes to index, or the comma separated `INDEX_SUBJECTS`.
+    /// Every subject is indexed when it isn't set.
+    pub index_subjects: Option<Vec<String>>,
+    /// The course search page of the portal, or `SEARCH_URL`.
+    pub search_url: Option<String>,
+    /// The staff directory instructors are linked to, or `DIRECTORY_URL`.
+    pub directory_url: Option<String>,
+    /// The staff directory search instructors are looked up with, or
+    /// `DIRECTORY_SEARCH_URL`.
+    pub directory_search_url: Option<String>,
+    /// Times a portal request that failed on the portal's end is tried
+    /// again, or `SCRAPE_RETRIES`.
+    pub scrape_retries: Option<u32>,
+    /// Milliseconds before the first retry of a portal request, doubling for
+    /// each one after, or `SCRAPE_RETRY_DELAY_MS`.
+    pub scrape_retry_delay_ms: Option<u64>,
+    /// Seconds a portal request can take before it's tried again, or
+    /// `SCRAPE_TIMEOUT_SECS`.
+    pub scrape_timeout_secs: Option<u64>,
+    /// Whether to skip checking the index can be queried when the bot
+    /// starts, or `SKIP_SELF_TEST`. The check runs unless set.
+    pub skip_self_test: Option<bool>,
+}
+
+impl Key for Config {
+    type Value = Config;
+}
+
+impl Config {
+    /// Load the config file with any environment variables applied over it.
+    /// Without a file every setting comes from the environment or its
+    /// default.
+    pub fn load() -> Result<Config, Error> {
+        let mut config: Config = match fs::read_to_string(CONFIG_PATH) {
+            Ok(text) => ::toml::from_str(&text)?,
+            Err(ref e) if e.kind() == ::std::io::ErrorKind::NotFound => Config::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        override_with(&mut config.token, "DISCORD_TOKEN");
+        override_with(&mut config.prefix, "BOT_PREFIX");
+        override_with(&mut config.index_path, "INDEX_PATH");
+        override_with(&mut config.default_term, "DEFAULT_TERM");
+        override_with(&mut config.list_limit, "LIST_LIMIT");
+        override_with(&mut config.description_limit, "DESCRIPTION_LIMIT");
+        override_with(&mut config.field_limit, "FIELD_LIMIT");
+        override_with(&mut config.scrape_concurrency, "SCRAPE_CONCURRENCY");
+        override_with(&mut config.cache_minutes, "COURSE_CACHE_MINUTES");
+        override_with(&mut config.course_cooldown_secs, "COURSE_COOLDOWN_SECS");
+        override_with(&mut config.watch_minutes, "WATCH_CHECK_MINUTES");
+        override_with(&mut config.prefetch, "PREFETCH");
+        override_with(&mut config.prefetch_max, "PREFETCH_MAX");
+        override_with(&mut config.stale_check_hours, "STALE_CHECK_HOURS");
+        override_with(&mut config.stale_terms, "STALE_TERMS");
+        override_with(&mut config.stale_notify_channel, "STALE_NOTIFY_CHANNEL");
+        override_with(&mut config.skip_self_test, "SKIP_SELF_TEST");
+        override_with(&mut config.search_url, "SEARCH_URL");
+        override_with(&mut config.directory_url, "DIRECTORY_URL");
+        override_with(&mut config.directory_search_url, "DIRECTORY_SEARCH_URL");
+        override_with(&mut config.scrape_retries, "SCRAPE_RETRIES");
+        override_with(&mut config.scrape_retry_delay_ms, "SCRAPE_RETRY_DELAY_MS");
+        override_with(&mut config.scrape_timeout_secs, "SCRAPE_TIMEOUT_SECS");
+        override_list_with(&mut config.index_terms, "INDEX_TERMS");
+        override_list_with(&mut config.index_subjects, "INDEX_SUBJECTS");
+
+        if let Some(ref prefix) = config.prefix {
+            if prefix.is_empty() || prefix.chars().any(char::is_whitespace) {
+                let reason = format!("prefix must not be empty or contain whitespace, got {:?}", prefix);
+                return Err(ConfigError(reason).into());
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Replace a setting with an environment variable if it's set and valid.
+fn override_with<T: FromStr>(setting: &mut Option<T>, name: &str) {
+    if let Some(value) = env::var(name).ok().and_then(|value| value.parse().ok()) {
+        *setting = Some(value);
+    }
+}
+
+/// Replace a list with a comma separated environment variable if it's set.
+fn override_list_with(setting: &mut Option<Vec<String>>, name: &str) {
+    if let Ok(value) = env::var(name) {
+        *setting = Some(split_list(&value));
+    }
+}
+
+/// The items of a comma separated list, leaving out blank ones.
+fn split_list(text: &str) -> Vec<String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_are_split_on_commas() {
+        assert_eq!(split_list("20185, 20191,,"), vec!["20185", "20191"]);
+        assert!(split_list(" ").is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+use std::path::{ Path, PathBuf };
+
+use failure::Error;
+use typemap::Key;
+
+use json_store;
+
+const FEEDBACK_PATH: &str = "./feedback.json";
+
+/// A report sent with `~feedback`.
+#[derive(Serialize, Deserialize)]
+pub struct Feedback {
+    pub id: u64,
+    pub author: String,
+    pub text: String,
+    pub resolved: bool,
+}
+
+/// Every report sent with `~feedback`, kept across restarts.
+#[derive(Default, Serialize, Deserialize)]
+pub struct FeedbackStore {
+    next_id: u64,
+    items: Vec<Feedback>,
+    /// Where the feedback is saved.
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Key for FeedbackStore {
+    type Value = FeedbackStore;
+}
+
+impl FeedbackStore {
+    /// Load the feedback file, starting fresh if there isn't one yet.
+    pub fn load() -> Result<FeedbackStore, Error> {
+        FeedbackStore::load_from(Path::new(FEEDBACK_PATH))
+    }
+
+    /// Load feedback kept somewhere other than the usual file.
+    pub fn load_from(path: &Path) -> Result<FeedbackStore, Error> {
+        let mut loaded: FeedbackStore = json_store::load(path)?;
+        loaded.path = path.to_owned();
+        Ok(loaded)
+    }
+
+    /// Write the feedback file.
+    fn save(&self) -> Result<(), Error> {
+        json_store::save(&self.path, self)
+    }
+
+    /// Record a new report and save it, returning its id.
+    pub fn add(&mut self, author: String, text: String) -> Result<u64, Error> {
+        self.next_id += 1;
+
+        self.items.push(Feedback {
+            id: self.next_id,
+            author: author,
+            text: text,
+            resolved: false,
+        });
+
+        self.save()?;
+        Ok(self.next_id)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Feedback> {
+        self.items.iter()
+            .find(|item| item.id == id)
+    }
+
+    /// Reports that haven't been resolved yet, oldest first.
+    pub fn open(&self) -> Vec<&Feedback> {
+        self.items.iter()
+            .filter(|item| !item.resolved)
+            .collect()
+    }
+
+    /// Mark a report resolved and save the change. Returns false if there
+    /// is no report with the id.
+    pub fn resolve(&mut self, id: u64) -> Result<bool, Error> {
+        match self.items.iter_mut().find(|item| item.id == id) {
+            Some(item) => item.resolved = true,
+            None => return Ok(false),
+        }
+
+        self.save()?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uwin::tests::temp_dir;
+
+    fn ids(items: Vec<&Feedback>) -> Vec<u64> {
+        items.into_iter().map(|item| item.id).collect()
+    }
+
+    #[test]
+    fn reports_are_open_until_resolved() {
+        let path = temp_dir("feedback").join("feedback.json");
+        let mut store = FeedbackStore::load_from(&path).unwrap();
+
+        assert_eq!(store.add("a#1".to_owned(), "Search is slow".to_owned()).unwrap(), 1);
+        assert_eq!(store.add("b#2".to_owned(), "Wrong room".to_owned()).unwrap(), 2);
+        assert_eq!(ids(store.open()), vec![1, 2]);
+
+        assert!(store.resolve(1).unwrap());
+        assert_eq!(ids(store.open()), vec![2]);
+        assert!(store.get(1).unwrap().resolved);
+
+        // Resolving twice changes nothing, and a missing report is reported.
+        assert!(store.resolve(1).unwrap());
+        assert!(!store.resolve(3).unwrap());
+        assert_eq!(ids(store.open()), vec![2]);
+
+        // The changes are saved, and ids keep counting up after a restart.
+        let mut store = FeedbackStore::load_from(&path).unwrap();
+        assert_eq!(ids(store.open()), vec![2]);
+        assert_eq!(store.add("c#3".to_owned(), "Thanks".to_owned()).unwrap(), 3);
+    }
+}
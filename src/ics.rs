@@ -0,0 +1,152 @@
+use std::iter;
+
+use chrono::{ NaiveDateTime, Utc };
+use itertools::Itertools;
+
+use uwin::Course;
+
+/// Time zone the portal's exam times are in. Times are written without a
+/// zone and calendars are told to read them in this one.
+const TIME_ZONE: &str = "America/Toronto";
+
+/// Longest line in octets before it has to be folded onto the next.
+const LINE_LEN: usize = 75;
+
+/// A calendar of the exams of courses in a term, with the number of exams
+/// left out because their date or time isn't known yet.
+pub fn exams(term: &str, courses: &[Course]) -> (String, usize) {
+    let stamp = Utc::now().naive_utc();
+
+    let mut events = vec![];
+    let mut skipped = 0;
+
+    for course in courses {
+        for (i, exam) in course.exams.iter().enumerate() {
+            let (start, end) = match exam.start_end() {
+                Some(times) => times,
+                None => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+
+            let location = exam.building.iter()
+                .chain(exam.room.iter())
+                .join(" ");
+
+            let mut lines = vec![
+                "BEGIN:VEVENT".to_owned(),
+                format!("UID:{}-{}-{}@uwinsearch", term, course.code, i),
+                format!("DTSTAMP:{}Z", format_time(stamp)),
+                format!("DTSTART:{}", format_time(start)),
+                format!("DTEND:{}", format_time(end)),
+                format!("SUMMARY:{}", escape(&format!("{} {}", course.code, exam.ty))),
+                format!("DESCRIPTION:{}", escape(&course.title)),
+            ];
+
+            if !location.is_empty() {
+                lines.push(format!("LOCATION:{}", escape(&location)));
+            }
+
+            lines.push("END:VEVENT".to_owned());
+            events.extend(lines);
+        }
+    }
+
+    let calendar = vec![
+            "BEGIN:VCALENDAR".to_owned(),
+            "VERSION:2.0".to_owned(),
+            "PRODID:-//uwinsearch//Exams//EN".to_owned(),
+            "CALSCALE:GREGORIAN".to_owned(),
+            format!("X-WR-TIMEZONE:{}", TIME_ZONE),
+        ]
+        .into_iter()
+        .chain(events)
+        .chain(iter::once("END:VCALENDAR".to_owned()))
+        .map(|line| fold(&line))
+        .join("");
+
+    (calendar, skipped)
+}
+
+/// A date and time as iCalendar writes them, like `20181212T190000`.
+fn format_time(time: NaiveDateTime) -> String {
+    time.format("%Y%m%dT%H%M%S").to_string()
+}
+
+/// Escape text for use in a property value.
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\\' => "\\\\".to_owned(),
+            ';' => "\\;".to_owned(),
+            ',' => "\\,".to_owned(),
+            '\n' => "\\n".to_owned(),
+            '\r' => String::new(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// End a line, folding it onto continuation lines that start with a space
+/// if it's too long. Lines are only split between characters.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut len = 0;
+
+    for c in line.chars() {
+        if len + c.len_utf8() > LINE_LEN {
+            folded.push_str("\r\n ");
+            len = 1;
+        }
+
+        folded.push(c);
+        len += c.len_utf8();
+    }
+
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{ NaiveDate, NaiveTime };
+    use uwin::tests::{ course, exam };
+
+    #[test]
+    fn exams_with_a_time_become_events() {
+        let mut comp = course("COMP100001");
+        comp.title = "Networks; Protocols, and Security".to_owned();
+        comp.exams = vec![
+            exam("Final", NaiveDate::from_ymd_opt(2018, 12, 15), Some((NaiveTime::from_hms(19, 0, 0), NaiveTime::from_hms(22, 0, 0)))),
+            exam("Midterm", NaiveDate::from_ymd_opt(2018, 10, 15), None),
+        ];
+
+        let (calendar, skipped) = exams("20185", &[comp]);
+        let lines = calendar.split("\r\n").collect::<Vec<_>>();
+
+        assert_eq!(skipped, 1);
+        assert_eq!(lines.iter().filter(|line| **line == "BEGIN:VEVENT").count(), 1);
+        assert!(lines.contains(&"UID:20185-COMP100001-0@uwinsearch"));
+        assert!(lines.contains(&"DTSTART:20181215T190000"));
+        assert!(lines.contains(&"DTEND:20181215T220000"));
+        assert!(lines.contains(&"SUMMARY:COMP100001 Final"));
+        assert!(lines.contains(&r"DESCRIPTION:Networks\; Protocols\, and Security"));
+        assert!(lines.contains(&"LOCATION:Erie Hall 1120"));
+        assert_eq!(lines[0], "BEGIN:VCALENDAR");
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn long_lines_are_folded() {
+        let line = format!("DESCRIPTION:{}", "é".repeat(100));
+        let folded = fold(&line);
+
+        for part in folded.trim_end_matches("\r\n").split("\r\n") {
+            assert!(part.len() <= LINE_LEN);
+        }
+
+        assert_eq!(folded.replace("\r\n ", "").trim_end_matches("\r\n"), line);
+    }
+}
@@ -0,0 +1,31 @@
+use std::fs::{ self, File };
+use std::io::{ BufWriter, ErrorKind, Write };
+use std::path::Path;
+
+use failure::Error;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Load something kept in a JSON file, starting fresh if there isn't one yet.
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> Result<T, Error> {
+    match File::open(path) {
+        Ok(file) => Ok(::serde_json::from_reader(file)?),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write something to a JSON file, replacing the file only once the new one
+/// is complete and on disk, so a crash can't leave half of it behind.
+pub fn save<T: Serialize>(path: &Path, value: &T) -> Result<(), Error> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    ::serde_json::to_writer_pretty(&mut writer, value)?;
+    writer.flush()?;
+
+    writer.get_ref().sync_all()?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
@@ -1,352 +1,3051 @@
+extern crate chrono;
 extern crate failure;
 #[macro_use] extern crate failure_derive;
 extern crate flexi_logger;
 extern crate itertools;
 #[macro_use] extern crate lazy_static;
+extern crate libc;
 #[macro_use] extern crate log;
 extern crate rayon;
 extern crate regex;
 extern crate reqwest;
 extern crate select;
+extern crate serde;
+#[macro_use] extern crate serde_derive;
+extern crate serde_json;
 extern crate serenity;
 extern crate tantivy;
+extern crate toml;
 extern crate typemap;
 
-use std::{ fs, thread };
+use std::{
+    collections::{ BTreeMap, HashMap },
+    io::Read,
+    path::Path,
+    process,
+    sync::{ Arc, atomic::{ AtomicBool, Ordering } },
+    thread,
+    time::{ Duration, Instant },
+};
 
-use failure::{ Error, SyncFailure };
+use failure::Error;
 use itertools::Itertools;
-use regex::Regex;
 use serenity::{
     CACHE,
+    Result as SerenityResult,
+    client::bridge::gateway::ShardManager,
+    http::StatusCode,
     prelude::*,
     model::{
-        channel::Message,
+        channel::{ Message, Reaction, ReactionType },
+        gateway::{ Game, Ready },
+        user::OnlineStatus,
         guild::Member,
-        id::ChannelId,
+        id::{ ChannelId, MessageId, UserId },
+        ModelError,
         permissions::Permissions,
     },
 };
+use typemap::{ Key, ShareMap };
 
 static IMAGE_DATA: &[u8] = include_bytes!("../uw_logo.png");
 const EMBED_COLOR: u32 = 0x00005696;
-const DEFAULT_TERM: &str = "20185";
-
-lazy_static! {
-    static ref REGEX: Regex = Regex::new(r"([fsw])(\d\d)").unwrap();
+/// What commands start with unless `BOT_PREFIX` says otherwise.
+const DEFAULT_PREFIX: &str = "~";
+/// Times a Discord request is tried before giving up on a passing failure.
+const SEND_ATTEMPTS: u64 = 3;
+/// Number of courses scraped by `~reindex --validate`.
+const VALIDATE_SAMPLE: usize = 5;
+/// Most semesters `--term-range` will search at once.
+const MAX_TERM_RANGE: usize = 6;
+/// Reactions that turn the page of a list of results.
+const PREV_PAGE_EMOJI: &str = "⬅️";
+const NEXT_PAGE_EMOJI: &str = "➡️";
+/// Most result lists that can be paged through at once. The oldest lists
+/// stop responding first.
+const MAX_PAGED_LISTS: usize = 100;
+/// Minutes a list of results can be paged through before its page reactions
+/// are taken off.
+const PAGE_MINUTES: u64 = 5;
+/// Courses shown by `~course --help-examples`.
+const HELP_EXAMPLES: usize = 3;
+/// Most results `~course -n` can list at once.
+const MAX_RESULTS: usize = 25;
+/// Most courses `~schedule` will fit into a week at once.
+const MAX_SCHEDULE_COURSES: usize = 6;
+/// Seconds a user waits between `~course` searches unless the config says
+/// otherwise.
+const COURSE_COOLDOWN_SECS: u64 = 3;
+/// Most courses `~matrix` will scrape at once.
+const MAX_MATRIX_COURSES: usize = 10;
+/// A command listed by `~help`.
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    /// Only listed to admins with `~help -v`.
+    pub admin: bool,
 }
 
+const COMMANDS: &[Command] = &[
+    Command { name: "course", usage: "course [OPTION] <QUERY>", description: "Search for courses. Add `-h` to see the options.", admin: false },
+    Command { name: "mirror", usage: "mirror <CODE>", description: "List the antirequisites of a course.", admin: false },
+    Command { name: "subjects", usage: "subjects [TEXT]", description: "List subject codes and names, or only those mentioning the text.", admin: false },
+    Command { name: "prereqs", usage: "prereqs <CODE>", description: "Show the prerequisites of a course and of the courses they name.", admin: false },
+    Command { name: "terms", usage: "terms [--current]", description: "List the indexed terms.", admin: false },
+    Command { name: "prof", usage: "prof <NAME>", description: "List the courses of every instructor with a name, grouped by instructor.", admin: false },
+    Command { name: "instructor", usage: "instructor <NAME>", description: "List the courses an instructor teaches this term.", admin: false },
+    Command { name: "card", usage: "card <CODE>", description: "Show a course as an image that's easy to share.", admin: false },
+    Command { name: "matrix", usage: "matrix [--csv] <CODE>...", description: "Compare several courses in a table attached as a file.", admin: false },
+    Command { name: "compare", usage: "compare [-s <TERM>] <CODE> <CODE>", description: "Show two courses side by side.", admin: false },
+    Command { name: "schedule", usage: "schedule <CODE>...", description: "Show when courses meet in a week and whether any overlap.", admin: false },
+    Command { name: "exams", usage: "exams [-s <TERM>] <CODE>...", description: "Get the exams of courses as a calendar file to import.", admin: false },
+    Command { name: "examson", usage: "examson <DATE> <CODE>...", description: "Show which of the courses have an exam on a date.", admin: false },
+    Command { name: "watch", usage: "watch [CODE]", description: "Get a message when a full section has a seat open up, or if its instructor changes meanwhile. Without a code, list what you're watching.", admin: false },
+    Command { name: "unwatch", usage: "unwatch <CODE>", description: "Stop watching a section.", admin: false },
+    Command { name: "status", usage: "status", description: "Show the state of the course index.", admin: false },
+    Command { name: "feedback", usage: "feedback <TEXT>", description: "Report a problem or suggest something.", admin: false },
+    Command { name: "whatsnew", usage: "whatsnew", description: "List recently added features.", admin: false },
+    Command { name: "help", usage: "help [-v]", description: "Show this help.", admin: false },
+    Command { name: "reindex", usage: "reindex [--validate|--incremental]", description: "Rebuild the course index, only add and remove what changed with `--incremental`, or check the portal layout with `--validate`.", admin: true },
+    Command { name: "enable", usage: "enable <COMMAND>", description: "Turn a command back on in this server.", admin: true },
+    Command { name: "disable", usage: "disable <COMMAND>", description: "Turn a command off in this server.", admin: true },
+    Command { name: "feedback-resolve", usage: "feedback-resolve [show|resolve <ID>]", description: "List open feedback, show a report, or mark one resolved.", admin: true },
+    Command { name: "maintenance", usage: "maintenance <on|off>", description: "Turn user commands off everywhere while keeping admin commands.", admin: true },
+];
+
+/// Recently added features shown by `~whatsnew`, newest first. Add an entry
+/// tagged with the upcoming version when a feature lands. Each has the usage
+/// of the command it's about without the prefix, or nothing if it isn't
+/// about one, and what's new.
+const WHATS_NEW: &[(&str, &str, &str)] = &[
+    ("0.25.0", "watch <CODE>", "also messages you if the section's instructor changes, like when TBA is assigned."),
+    ("0.24.0", "exams <CODE>...", "sends your exams as a calendar file to import."),
+    ("0.23.0", "course --sections", "lists every section of a course, which are otherwise listed once."),
+    ("0.22.0", "compare <CODE> <CODE>", "shows two courses side by side."),
+    ("0.21.0", "prereqs <CODE>", "shows a tree of a course's prerequisites."),
+    ("0.20.0", "course -n <COUNT>", "lists up to 25 results at once."),
+    ("0.19.0", "subjects [TEXT]", "lists subject codes to use with `--subject`."),
+    ("0.18.0", "schedule <CODE>...", "lays courses out in a week and warns about time conflicts."),
+    ("0.17.0", "course --open", "only shows sections that had open seats at the last reindex."),
+    ("0.16.0", "course -f", "skips the few minutes courses are reused for, to check the latest seats."),
+    ("0.15.0", "instructor <NAME>", "lists the courses an instructor teaches this term."),
+    ("0.14.0", "watch <CODE>", "messages you when a seat opens up in a full section."),
+    ("0.13.0", "course --subject <SUBJECT>", "without a query lists the whole subject."),
+    ("0.12.0", "course --help-examples", "shows searches that find courses this term."),
+    ("0.11.0", "course --exclude <WORD>", "leaves out courses mentioning a word."),
+    ("0.10.0", "prof <NAME>", "lists the courses an instructor teaches."),
+    ("0.9.0", "examson <DATE> <CODE>...", "finds which courses have an exam on a date."),
+    ("0.8.0", "feedback <TEXT>", "sends a report to the maintainers."),
+    ("0.7.0", "course --eligible <YEAR>", "hides courses restricted to other years."),
+    ("0.6.0", "", "Courses show their required textbooks and materials."),
+    ("0.5.0", "course --count", "tells you how many courses match."),
+    ("0.4.0", "", "React with ⬅️ and ➡️ to a list of results to page through it."),
+    ("0.3.0", "matrix <CODE>...", "compares courses in a table."),
+    ("0.2.0", "course --delivery <MODE>", "finds online, hybrid, or in person sections."),
+];
+
+/// Number of `WHATS_NEW` entries shown at once.
+const WHATS_NEW_SHOWN: usize = 5;
+
+/// Hours between checks for terms newer than the index, unless
+/// `stale_check_hours` is set in the config.
+const STALE_CHECK_HOURS: u64 = 24;
+/// Newer live terms it takes for the index to count as stale, unless
+/// `stale_terms` is set in the config.
+const STALE_TERMS: usize = 1;
+
+/// Most sections a user can `~watch` at once.
+const MAX_WATCHES: usize = 10;
+/// Minutes between checks of watched sections, unless `watch_minutes` is set
+/// in the config.
+const WATCH_CHECK_MINUTES: u64 = 10;
+
+/// Most courses scraped ahead of time after a reindex, unless `prefetch_max`
+/// is set in the config.
+const PREFETCH_MAX: usize = 50;
+
+/// Whether `~reindex --incremental` is running.
+static UPDATING_INDEX: AtomicBool = AtomicBool::new(false);
+
+/// Seconds a shutdown waits for scrapes and index writes to finish.
+const SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+/// Whether SIGINT or SIGTERM was received.
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// Commands server admins can turn off with `~disable`.
+const TOGGLEABLE_COMMANDS: &[&str] = &["course", "mirror", "prereqs", "prof", "instructor", "card", "watch", "unwatch", "matrix", "compare", "schedule", "exams", "examson", "subjects", "terms", "status", "feedback"];
+
+#[cfg(feature = "card")]
+mod card;
+mod config;
+mod feedback;
+mod ics;
+mod json_store;
+mod render;
+mod settings;
 mod uwin;
+mod watch;
+
+/// A search whose list of results can be paged through.
+#[derive(Clone)]
+struct Search {
+    term: String,
+    query: String,
+    options: uwin::QueryOptions,
+    /// Page being shown, starting from 1.
+    page: usize,
+    /// Results listed on each page.
+    per_page: usize,
+    /// Number of results on all of the pages.
+    total: usize,
+    footer: Option<String>,
+    /// Only the user who searched can turn the page.
+    author: UserId,
+    chan: ChannelId,
+    sent: Instant,
+}
+
+impl Search {
+    /// Turn the page by a number of pages, giving the options to query the
+    /// new page with. Nothing changes past the first or last page.
+    fn turn(&mut self, step: isize) -> Option<uwin::QueryOptions> {
+        let pages = page_count(self.total, self.per_page);
+        let page = self.page as isize + step;
+
+        if page < 1 || page > pages as isize {
+            return None;
+        }
+
+        self.page = page as usize;
+
+        Some(on_page(&self.options, self.page, self.per_page))
+    }
+}
+
+/// Number of pages it takes to list some results.
+fn page_count(results: usize, per_page: usize) -> usize {
+    (results + per_page - 1) / per_page
+}
+
+/// Options to query a page of results with, counting pages from 1.
+fn on_page(options: &uwin::QueryOptions, page: usize, per_page: usize) -> uwin::QueryOptions {
+    uwin::QueryOptions {
+        limit: per_page,
+        offset: (page - 1) * per_page,
+        ..options.clone()
+    }
+}
+
+/// What `~course` was asked to do.
+struct CourseArgs {
+    help: bool,
+    /// Show examples made from courses in the index.
+    help_examples: bool,
+    terms: Vec<String>,
+    /// A term picked by when it is, which replaces `terms` once it's
+    /// looked up in the index.
+    relative_term: Option<uwin::RelativeTerm>,
+    count: bool,
+    /// Page of results to show, starting from 1.
+    page: usize,
+    /// Results to list on a page instead of the usual number.
+    per_page: Option<usize>,
+    /// Scrape a single course again even if it was scraped recently.
+    fresh: bool,
+    options: uwin::QueryOptions,
+    query: String,
+}
+
+/// Searches that can be paged through, by the message listing them.
+struct PagedLists;
+
+impl Key for PagedLists {
+    type Value = Arc<Mutex<BTreeMap<MessageId, Search>>>;
+}
 
 fn main() {
     flexi_logger::Logger::with_str("uwinsearch")
         .start()
         .expect("Couldn't initialize logger.");
 
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Couldn't load config: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let prefix = command_prefix(&config);
+
     info!("Initializing course index...");
 
-    let index = uwin::CourseIndex::open()
-        .expect("Couldn't open index and courses.");
+    let index_path = config.index_path.clone()
+        .unwrap_or_else(|| uwin::INDEX_PATH.to_owned());
+
+    let index = match uwin::CourseIndex::open(Path::new(&index_path), &portal(&config), &index_filter(&config)) {
+        Ok(index) => index,
+        Err(e) => {
+            error!("Couldn't open or build the course index at {}: {}", index_path, e);
+            process::exit(1);
+        }
+    };
+
+    let default_term = config.default_term.clone()
+        .unwrap_or_else(|| index.newest_term());
+    info!("Using term {} when none is given.", default_term);
+
+    if !config.skip_self_test.unwrap_or(false) {
+        self_test(&index, &default_term, &prefix);
+    }
 
     info!("Starting Discord bot...");
 
-    let token = env!("DISCORD_TOKEN");
+    let token = config.token.clone()
+        .expect("A token is needed in config.toml or DISCORD_TOKEN.");
 
-    let mut client = Client::new(token, Handler)
+    let handler = Handler {
+        prefix: prefix,
+        default_term: default_term,
+        cooldown: Duration::from_secs(config.course_cooldown_secs.unwrap_or(COURSE_COOLDOWN_SECS)),
+        last_search: Mutex::new(HashMap::new()),
+    };
+
+    let mut client = Client::new(&token, handler)
         .expect("Error creating discord client.");
 
-    client.data.lock().insert::<uwin::CourseIndex>(index);
+    let settings = settings::Settings::load()
+        .expect("Couldn't load settings.");
+
+    let feedback = feedback::FeedbackStore::load()
+        .expect("Couldn't load feedback.");
+
+    let watches = watch::WatchList::load()
+        .expect("Couldn't load watches.");
+
+    {
+        let mut data = client.data.lock();
+        data.insert::<uwin::CourseIndex>(Arc::new(index));
+        data.insert::<settings::Settings>(settings);
+        data.insert::<feedback::FeedbackStore>(feedback);
+        data.insert::<watch::WatchList>(watches);
+        data.insert::<PagedLists>(Arc::new(Mutex::new(BTreeMap::new())));
+        data.insert::<render::Limits>(render_limits(&config));
+        data.insert::<config::Config>(config);
+
+        #[cfg(feature = "card")]
+        data.insert::<card::CardCache>(Arc::new(Mutex::new(Default::default())));
+    }
+
+    let data = client.data.clone();
+    thread::spawn(move || watch_staleness(&data));
+
+    let data = client.data.clone();
+    thread::spawn(move || watch_seats(&data));
+
+    let data = client.data.clone();
+    thread::spawn(move || expire_paged_lists(&data));
+
+    // Signals only set a flag, which this thread watches to stop the bot.
+    unsafe {
+        libc::signal(libc::SIGINT, on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+
+    let shard_manager = client.shard_manager.clone();
+    thread::spawn(move || watch_signals(&shard_manager));
 
     if let Err(e) = client.start() {
         error!("Error running Discord bot: {}", e);
     }
+
+    // A reindex stops at the next course without committing, so only work
+    // that's nearly done is waited for.
+    info!("Waiting for scrapes and index writes to finish...");
+
+    if uwin::shut_down(Duration::from_secs(SHUTDOWN_TIMEOUT_SECS)) {
+        info!("Shut down cleanly.");
+    } else {
+        warn!("Shutting down with work still underway after {} seconds.", SHUTDOWN_TIMEOUT_SECS);
+    }
 }
 
-struct Handler;
+extern "C" fn on_signal(_: libc::c_int) {
+    SIGNALLED.store(true, Ordering::SeqCst);
+}
 
-impl Handler {
+/// Disconnect from Discord once a signal asks the bot to stop, which lets
+/// `main` finish shutting down.
+fn watch_signals(shard_manager: &Arc<Mutex<ShardManager>>) {
+    while !SIGNALLED.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(200));
+    }
+
+    info!("Shutting down...");
+    shard_manager.lock().shutdown_all();
+}
+
+/// What the startup self-test found in the index.
+#[derive(Debug, PartialEq)]
+enum SelfTest {
+    /// Courses for the default term.
+    Found(usize),
+    /// No courses for the default term, out of this many indexed.
+    NoneInTerm(u64),
+    Empty,
+    Failed,
+}
+
+/// Check that the index can be queried and has courses for the default term
+/// so a broken index shows up in the logs rather than in user complaints.
+fn self_test(index: &uwin::CourseIndex, default_term: &str, prefix: &str) -> SelfTest {
+    let total = index.num_docs();
+
+    match index.count(default_term) {
+        Ok(0) if total > 0 => {
+            warn!("Self-test found no courses for term {} out of {} indexed courses. \
+                   The default term may be out of date or the index may need a {}reindex.", default_term, total, prefix);
+            SelfTest::NoneInTerm(total)
+        }
+        Ok(0) => {
+            warn!("Self-test found an empty index. Run {}reindex to rebuild it.", prefix);
+            SelfTest::Empty
+        }
+        Ok(count) => {
+            info!("Self-test found {} courses for term {}.", count, default_term);
+            SelfTest::Found(count)
+        }
+        Err(e) => {
+            error!("Self-test couldn't query the index, run {}reindex to rebuild it: {}", prefix, e);
+            SelfTest::Failed
+        }
+    }
+}
+
+/// The code of a term written like `f18`, `current`, or `next`, if it's
+/// indexed.
+fn resolve_term(index: &uwin::CourseIndex, text: &str) -> Option<String> {
+    let code = match uwin::RelativeTerm::from_name(text) {
+        Some(relative) => index.relative_term(relative)?,
+        None => uwin::TermCode::from_short(text)?.code(),
+    };
+
+    if index.terms().contains(&code) {
+        Some(code)
+    } else {
+        None
+    }
+}
+
+/// Parse the options and query given to `~course`, or explain what's wrong
+/// with them. Options come before the query.
+fn parse_course_args<'a, A>(args: A, default_term: &str, prefix: &str) -> Result<CourseArgs, String>
+    where A: Iterator<Item = &'a str>
+{
+    let mut args = args.peekable();
+
+    let mut parsed = CourseArgs {
+        help: false,
+        help_examples: false,
+        terms: vec![default_term.to_owned()],
+        relative_term: None,
+        count: false,
+        page: 1,
+        per_page: None,
+        fresh: false,
+        options: uwin::QueryOptions::default(),
+        query: String::new(),
+    };
+
+    let mut words = vec![];
 
-    fn fetch_course<'a, A>(&self, ctx: Context, mut args: A, chan: ChannelId) -> Result<(), Error>
+    // The value of an option, which has to be there.
+    fn value<'a, A>(args: &mut A, missing: &str) -> Result<String, String>
         where A: Iterator<Item = &'a str>
     {
-        let (term, query) = match args.next() {
-            Some("-h") => {
-                chan.send_message(|m| m.embed(|e| {
-                        e.color(EMBED_COLOR)
-                            .field("Usage", "~course [OPTION] <QUERY>", false)
-                            .field("Options", "`-h` View the command help.\n`-s <[fsw]XX>` Select a semester where f (Fall) s (Summer) w (Winter) and XX is the year", false)
-                            .field("Examples", "~course 60100\n~course graph theory\n~course -s f18 graph theory", false)
-                    }))
-                    .map_err(SyncFailure::new)?;
+        args.next()
+            .map(str::to_owned)
+            .ok_or_else(|| missing.to_owned())
+    }
 
-                return Ok(());
+    loop {
+        match args.peek().cloned() {
+            Some("-h") => {
+                parsed.help = true;
+                return Ok(parsed);
+            }
+            Some("--help-examples") => {
+                parsed.help_examples = true;
+                return Ok(parsed);
             }
             Some("-s") => {
-                // Term codes are in the form [YEAR][CODE] where year is XXXX and
-                // code is 1 (Winder) 2 (Summer) or 5 (Fall)
-                // The bot will allow a user to enter [wWsSfF]XX
+                args.next();
+
                 let term = args.next()
-                    .and_then(|s| REGEX.captures(s))
-                    .and_then(|c| {
-                        let term = match c.get(1)?.as_str() {
-                            "w" | "W" => 1,
-                            "s" | "S" => 2,
-                            "f" | "F" => 5,
-                            _ => return None,
-                        };
-                        let year = c.get(2)?.as_str();
-                        Some(format!("20{}{}", year, term))
-                    });
+                    .ok_or("Semester selection is invalid.")?;
 
-                if let Some(term) = term {
-                    (term, args.join(" "))
+                if let Some(relative) = uwin::RelativeTerm::from_name(term) {
+                    parsed.relative_term = Some(relative);
                 } else {
-                    chan.send_message(|m| {
-                            m.content("Semester selection is invalid.")
-                        })
-                        .map_err(SyncFailure::new)?;
+                    let term = uwin::TermCode::from_short(term)
+                        .ok_or("Semester selection is invalid.")?;
 
-                    return Ok(());
+                    parsed.terms = vec![term.code()];
+                    parsed.relative_term = None;
                 }
             }
-            s => {
-                // Because we popped off the first term attempting to match a
-                // command we have to make sure to join that back with the
-                // rest of the arguments for the query.
-                let query = s.into_iter()
-                    .chain(args)
-                    .join(" ");
+            Some("--term-range") => {
+                args.next();
+
+                let range = args.next()
+                    .and_then(uwin::TermCode::range)
+                    .ok_or("Term range is invalid.")?;
+
+                if range.len() > MAX_TERM_RANGE {
+                    return Err(format!("A term range can span at most {} semesters.", MAX_TERM_RANGE));
+                }
 
-                (DEFAULT_TERM.to_owned(), query)
+                parsed.terms = range.iter()
+                    .map(uwin::TermCode::code)
+                    .collect();
+                parsed.relative_term = None;
             }
-        };
+            Some("--note") => {
+                args.next();
+                parsed.options.note = Some(value(&mut args, "Note text is missing.")?);
+            }
+            Some("--instructor") => {
+                args.next();
+                parsed.options.instructor = Some(value(&mut args, "Instructor name is missing.")?);
+            }
+            Some("--breadth") => {
+                args.next();
+                parsed.options.breadth = Some(value(&mut args, "Breadth category is missing.")?);
+            }
+            Some("--count") => {
+                args.next();
+                parsed.count = true;
+            }
+            Some("-n") => {
+                args.next();
 
-        // The course index may not exist if we are reindexing.
-        let data = ctx.data.lock();
-        let index = match data.get::<uwin::CourseIndex>() {
-            Some(index) => index,
-            _ => return Ok(()),
-        };
+                parsed.per_page = args.next()
+                    .and_then(|count| count.parse().ok())
+                    .filter(|&count| count >= 1 && count <= MAX_RESULTS)
+                    .map(Some)
+                    .ok_or_else(|| format!("The number of results has to be from 1 to {}, like `{}course -n 20 programming`.", MAX_RESULTS, prefix))?;
+            }
+            Some("-f") | Some("--fresh") => {
+                args.next();
+                parsed.fresh = true;
+            }
+            Some("--page") => {
+                args.next();
 
-        // Make the bot seem like it's typing just in case this query
-        // takes longer than expected.
-        chan.broadcast_typing()
-            .map_err(SyncFailure::new)?;
+                parsed.page = args.next()
+                    .and_then(|page| page.parse().ok())
+                    .filter(|&page| page >= 1)
+                    .ok_or("Page number is invalid.")?;
+            }
+            Some("-o") | Some("--open") => {
+                args.next();
+                parsed.options.open = true;
+            }
+            Some("--sections") => {
+                args.next();
+                parsed.options.sections = true;
+            }
+            Some("--enrollable") => {
+                args.next();
+                parsed.options.enrollable = true;
+            }
+            Some("--eligible") => {
+                args.next();
 
-        let mut courses = match index.query(&term, &query) {
-            Ok(courses) => courses,
-            Err(e) => {
-                return match e.downcast::<uwin::QueryError>() {
-                    Ok(e) => {
-                        // If the error is a query error we want to send a message in chat
-                        // telling the user the query was invalid.
-                        warn!("{}", e);
-
-                        chan.send_message(|m| {
-                                m.content(&format_args!("Query `\"{}\"` is invalid.", query))
-                            })
-                            .map_err(SyncFailure::new)?;
+                let year = args.next()
+                    .and_then(|year| year.parse().ok())
+                    .filter(|&year| year >= 1 && year <= 5)
+                    .ok_or("Year of study is invalid.")?;
 
-                        Ok(())
-                    }
-                    Err(e) => Err(e),
-                };
+                parsed.options.eligible = Some(year);
             }
-        };
+            Some("--min-score") => {
+                args.next();
 
-        // Sort the courses in order by code.
-        courses.sort_by(|c, other| c.code.cmp(&other.code));
+                let min = args.next()
+                    .and_then(|min| min.parse::<f32>().ok())
+                    .filter(|min| min.is_finite() && *min >= 0.0)
+                    .ok_or("Minimum score is invalid, like `--min-score 1.5`.")?;
 
-        match courses.as_slice() {
-            [] => {
-                chan.send_message(|m| {
-                        m.content(format!("No course found for query `\"{}\"`.", query))
-                    })
-                    .map_err(SyncFailure::new)?;
+                parsed.options.min_score = Some(min);
             }
-            [course] => {
-                let uwin::Course {
-                    title,
-                    description,
-                    note,
-                    meets,
-                    instructors,
-                    availability,
-                    prereqs,
-                    exams,
-                    ..
-                } = course.scrape()?;
-
-                let description = description
-                    .chars()
-                    .take(200)
-                    .chain("...\n\n".chars())
-                    .join("");
-
-                let mut fields = vec![];
-
-                if let Some(note) = note {
-                    fields.push(("Note", note, false));
-                }
+            Some("--delivery") => {
+                args.next();
+                parsed.options.delivery = Some(value(&mut args, "Delivery mode is missing.")?);
+            }
+            Some("--subject") => {
+                args.next();
+                parsed.options.subject = Some(value(&mut args, "Subject is missing.")?);
+            }
+            Some("--exclude") => {
+                args.next();
+                parsed.options.exclude.push(value(&mut args, "Excluded word is missing.")?);
+            }
+            // Options can come after the query too, like
+            // `~course programming --exclude lab`.
+            Some(word) => {
+                args.next();
+                words.push(word);
+            }
+            None => break,
+        }
+    }
 
-                fields.push(("Meets", meets, false));
+    parsed.query = words.join(" ");
 
-                if !instructors.is_empty() {
-                    let instructors = instructors
-                        .into_iter()
-                        .format_with("\n", |ins, f| {
-                            if let Some(url) = ins.directory_url() {
-                                f(&format_args!("[{}]({})", ins.name, url))
-                            } else {
-                                f(&format_args!("{}", ins.name))
-                            }
-                        })
-                        .to_string();
+    // Only a subject can be browsed without a query.
+    if parsed.query.is_empty() && parsed.options.subject.is_none() {
+        return Err(format!("A search query is missing, like `{0}course -s f18 graph theory`. See `{0}course -h` for options.", prefix));
+    }
 
-                    fields.push(("Instructors", instructors, true));
-                }
+    Ok(parsed)
+}
 
-                fields.push(("Availability", availability, true));
+/// Show whether the bot is in maintenance mode in its presence.
+fn set_presence(ctx: &Context, prefix: &str, maintenance: bool) {
+    if maintenance {
+        ctx.set_presence(Some(Game::playing("Under maintenance")), OnlineStatus::DoNotDisturb);
+    } else {
+        ctx.set_presence(Some(Game::playing(&format!("{}help", prefix))), OnlineStatus::Online);
+    }
+}
 
-                if !prereqs.is_empty() {
-                    let prereqs = prereqs
-                        .into_iter()
-                        .join("\n");
+/// The prefix of commands from the config, which was checked when it was
+/// loaded.
+fn command_prefix(config: &config::Config) -> String {
+    config.prefix.clone()
+        .unwrap_or_else(|| DEFAULT_PREFIX.to_owned())
+}
 
-                    fields.push(("Prerequisites", prereqs, false));
-                }
+/// Rendering limits from the config, kept within what Discord allows.
+fn render_limits(config: &config::Config) -> render::Limits {
+    let defaults = render::Limits::default();
 
-                if !exams.is_empty() {
-                    let exams = exams
-                        .into_iter()
-                        .format_with("\n", |ex, f| {
-                            f(&format_args!("**{}**", ex.ty))?;
+    render::Limits {
+        description: config.description_limit.unwrap_or(defaults.description),
+        field: config.field_limit.unwrap_or(defaults.field),
+        list: config.list_limit.unwrap_or(defaults.list),
+    }.clamped()
+}
 
-                            if let Some(date) = ex.date {
-                                f(&format_args!(" on {}", date))?;
-                            }
+/// Where to scrape from and how hard to try, from the config. It can point
+/// the scraper somewhere other than the university, and retries keep the
+/// portal's maintenance windows from ending a reindex.
+fn portal(config: &config::Config) -> uwin::Portal {
+    let defaults = uwin::Portal::default();
 
-                            if let Some(time) = ex.time {
-                                f(&format_args!(" at {}", time))?;
-                            }
+    uwin::Portal {
+        search_url: config.search_url.clone().unwrap_or(defaults.search_url),
+        directory_url: config.directory_url.clone().unwrap_or(defaults.directory_url),
+        directory_search_url: config.directory_search_url.clone().unwrap_or(defaults.directory_search_url),
+        retries: config.scrape_retries.unwrap_or(defaults.retries),
+        retry_delay_ms: config.scrape_retry_delay_ms.unwrap_or(defaults.retry_delay_ms),
+        cache_minutes: config.cache_minutes.unwrap_or(defaults.cache_minutes),
+        max_requests: config.scrape_concurrency.unwrap_or(defaults.max_requests),
+        timeout_secs: config.scrape_timeout_secs.unwrap_or(defaults.timeout_secs),
+    }
+}
 
-                            if let Some(building) = ex.building {
-                                f(&format_args!(" in {}", building))?;
-                            }
+/// Where to scrape from, with the config kept in the shared data.
+fn data_portal(data: &Arc<Mutex<ShareMap>>) -> uwin::Portal {
+    let config = data.lock()
+        .get::<config::Config>()
+        .cloned()
+        .unwrap_or_default();
 
-                            if let Some(room) = ex.room {
-                                f(&format_args!(" room {}", room))?;
-                            }
+    portal(&config)
+}
 
-                            Ok(())
-                        })
-                        .to_string();
+/// The course index out of the shared data, so it can be used without
+/// holding the lock while scraping or sending. There's none while the index
+/// is being rebuilt.
+fn data_index(data: &Arc<Mutex<ShareMap>>) -> Option<Arc<uwin::CourseIndex>> {
+    data.lock()
+        .get::<uwin::CourseIndex>()
+        .cloned()
+}
 
-                    fields.push(("Exams", exams, false));
-                }
+/// How much of a course to show, from the shared data.
+fn data_limits(data: &Arc<Mutex<ShareMap>>) -> render::Limits {
+    data.lock()
+        .get::<render::Limits>()
+        .cloned()
+        .unwrap_or_default()
+}
 
-                let files = vec![(IMAGE_DATA, "icon.png")];
-                chan.send_files(files, |m| m.embed(|e| {
-                        e.color(EMBED_COLOR)
-                            .thumbnail("attachment://icon.png")
-                            .title(title)
-                            .description(description)
-                            .fields(fields)
-                    }))
-                    .map_err(SyncFailure::new)?;
-            }
-            courses => {
-                let courses = courses
-                    .iter()
-                    .format_with("\n", |course, f| {
-                        f(&format_args!("`{}` {}", course.code, course.title))
-                    });
+/// The terms and subjects to index from the config. Everything is indexed
+/// when they aren't set.
+fn index_filter(config: &config::Config) -> uwin::IndexFilter {
+    uwin::IndexFilter {
+        terms: config.index_terms.clone().unwrap_or_default(),
+        subjects: config.index_subjects.iter()
+            .flat_map(|subjects| subjects.iter())
+            .map(|subject| subject.to_uppercase())
+            .collect(),
+    }
+}
 
-                let files = vec![(IMAGE_DATA, "icon.png")];
-                chan.send_files(files, |m| m.embed(|e| {
-                        e.color(EMBED_COLOR)
-                            .thumbnail("attachment://icon.png")
-                            .title("Top 10 Results")
-                            .description(courses)
-                    }))
-                    .map_err(SyncFailure::new)?;
+/// The terms and subjects to index, with the config kept in the shared data.
+fn data_index_filter(data: &Arc<Mutex<ShareMap>>) -> uwin::IndexFilter {
+    let config = data.lock()
+        .get::<config::Config>()
+        .cloned()
+        .unwrap_or_default();
+
+    index_filter(&config)
+}
+
+/// The month and day at the start of `~examson`'s arguments, with the codes
+/// after it. Dates can be one word like 12/15 or two like Dec 15.
+fn split_exam_date<'a, 'b>(args: &'b [&'a str]) -> Option<((u32, u32), &'b [&'a str])> {
+    if let Some(date) = args.get(0).and_then(|word| uwin::parse_month_day(word)) {
+        return Some((date, &args[1..]));
+    }
+
+    if args.len() < 2 {
+        return None;
+    }
+
+    uwin::parse_month_day(&args[..2].join(" "))
+        .map(|date| (date, &args[2..]))
+}
+
+/// A line for each exam of a course on a month and day.
+fn exams_on_date(course: &uwin::Course, date: (u32, u32)) -> Vec<String> {
+    course.exams.iter()
+        .filter(|exam| exam.month_day() == Some(date))
+        .map(|exam| {
+            let time = exam.time_string()
+                .map(|time| format!(" at {}", time))
+                .unwrap_or_default();
+
+            format!("`{}` {}: **{}**{}", course.code, course.title, exam.ty, time)
+        })
+        .collect()
+}
+
+/// The live terms newer than every indexed term, if there are enough of them
+/// for the index to count as stale. Terms left out of the index on purpose,
+/// when only some are indexed, don't count.
+fn stale_terms(indexed: &[String], live: Vec<String>, only: &[String], threshold: usize) -> Vec<String> {
+    let live = live.into_iter()
+        .filter(|code| only.is_empty() || only.contains(code))
+        .collect::<Vec<_>>();
+
+    let newer = uwin::newer_terms(indexed, &live);
+
+    if !newer.is_empty() && newer.len() >= threshold {
+        newer
+    } else {
+        vec![]
+    }
+}
+
+/// Periodically compare the indexed terms to the live term list, starting
+/// right away. A stale index is logged and, if `stale_notify_channel` is set
+/// in the config, reported there so admins know to `~reindex`.
+fn watch_staleness(data: &Arc<Mutex<ShareMap>>) {
+    let portal = data_portal(data);
+    let config = data.lock()
+        .get::<config::Config>()
+        .cloned()
+        .unwrap_or_default();
+    let prefix = command_prefix(&config);
+    let hours = config.stale_check_hours.unwrap_or(STALE_CHECK_HOURS);
+    let threshold = config.stale_terms.unwrap_or(STALE_TERMS);
+    let notify = config.stale_notify_channel.map(ChannelId);
+
+    // Terms left out on purpose don't make the index stale.
+    let filter = index_filter(&config);
+
+    loop {
+        // Don't hold the lock while scraping, and skip the check while
+        // reindexing.
+        let indexed = data.lock()
+            .get::<uwin::CourseIndex>()
+            .map(|index| index.terms());
+
+        if let Some(indexed) = indexed {
+            match uwin::Scraper::new(portal.clone()).scrape_terms() {
+                Ok(live) => {
+                    let live = live.into_iter()
+                        .map(|(code, _)| code)
+                        .collect();
+
+                    let newer = stale_terms(&indexed, live, &filter.terms, threshold);
+
+                    if !newer.is_empty() {
+                        let names = newer.iter()
+                            .map(|code| uwin::TermCode::from_code(code).map_or(code.clone(), |term| term.to_string()))
+                            .join(", ");
+
+                        warn!("Index is stale, these terms are live but not indexed: {}", names);
+
+                        if let Some(chan) = notify {
+                            let content = format!("The course index is missing {}. Run `{}reindex` to update it.", names, prefix);
+
+                            if let Err(e) = send_reply(chan, &content) {
+                                error!("Couldn't report stale index: {}", e);
+                            }
+                        }
+                    }
+                }
+                Err(e) => warn!("Couldn't check whether the index is stale: {}", e),
             }
         }
 
-        Ok(())
+        thread::sleep(Duration::from_secs(hours * 60 * 60));
     }
+}
 
-    fn reindex(&self, ctx: Context, member: Option<Member>) -> Result<(), Error> {
-        // We want to reindex if a person from a channel is an administrator.
-        if let Some(member) = member {
-            let is_admin = member.permissions()
-                .map(|perm| perm.administrator())
-                .unwrap_or(false);
+/// Scrape the most looked up courses into the cache of a new index if the
+/// config asks for it, so the first lookups after a reindex are quick.
+fn prefetch_popular(data: &Arc<Mutex<ShareMap>>, index: &uwin::CourseIndex) {
+    let config = data.lock()
+        .get::<config::Config>()
+        .cloned()
+        .unwrap_or_default();
 
-            if is_admin {
-                // Remove current course index.
-                let mut data = ctx.data.lock();
+    if !config.prefetch.unwrap_or(false) {
+        return;
+    }
 
-                let already_reindexing = data.remove::<uwin::CourseIndex>()
-                    .is_none();
+    let popular = index.scraper().popular(config.prefetch_max.unwrap_or(PREFETCH_MAX));
 
-                if already_reindexing {
-                    return Ok(());
+    info!("Prefetching {} popular courses...", popular.len());
+
+    let count = index.scraper().prefetch(popular);
+    info!("Prefetched {} courses.", count);
+}
+
+/// Bring the index up to date with the portal without rebuilding it. The
+/// index is only locked to read its codes and to write the changes, so it
+/// keeps answering queries while the portal is scraped. Gives nothing if
+/// there's no index because a full reindex is underway.
+fn update_index(data: &Arc<Mutex<ShareMap>>) -> Result<Option<uwin::IndexChanges>, Error> {
+    let (indexed, path) = match data_index(data) {
+        Some(index) => (index.indexed_codes()?, index.path().to_owned()),
+        None => return Ok(None),
+    };
+
+    let changes = uwin::Scraper::for_index(data_portal(data), &path)
+        .scrape_changes(&data_index_filter(data), &indexed)?;
+
+    match data_index(data) {
+        Some(index) => index.apply(&changes)?,
+        None => return Ok(None),
+    }
+
+    Ok(Some(changes))
+}
+
+/// Whether a command was built in. Some need optional features.
+fn is_available(name: &str) -> bool {
+    name != "card" || cfg!(feature = "card")
+}
+
+/// Periodically scrape every watched section, messaging the users watching
+/// one once it has open seats and dropping their watch. The sections of a
+/// term are scraped in a batch, no more at once than while indexing.
+fn watch_seats(data: &Arc<Mutex<ShareMap>>) {
+    let minutes = data.lock()
+        .get::<config::Config>()
+        .and_then(|config| config.watch_minutes)
+        .unwrap_or(WATCH_CHECK_MINUTES);
+
+    loop {
+        thread::sleep(Duration::from_secs(minutes * 60));
+
+        // The index's scraper is used so a section someone just looked up
+        // isn't scraped again. There's none during a reindex, so the check
+        // waits for the next round.
+        let index = match data_index(data) {
+            Some(index) => index,
+            None => continue,
+        };
+
+        let watches = data.lock()
+            .get::<watch::WatchList>()
+            .map(watch::WatchList::all)
+            .unwrap_or_default();
+
+        // Each section is scraped once however many users watch it.
+        let mut terms: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for watch in &watches {
+            let codes = terms.entry(watch.term.clone()).or_insert_with(Vec::new);
+
+            if !codes.contains(&watch.code) {
+                codes.push(watch.code.clone());
+            }
+        }
+
+        let scraped = terms.into_iter()
+            .flat_map(|(term, codes)| {
+                index.scraper().scrape_sections(&term, codes)
+                    .into_iter()
+                    .map(move |(code, course)| (term.clone(), code, course))
+            })
+            .collect::<Vec<_>>();
+
+        for (term, code, course) in scraped {
+            let course = match course {
+                Ok(course) => course,
+                Err(e) => {
+                    warn!("Couldn't check watched section {}: {}", code, e);
+                    continue;
                 }
+            };
+
+            let watching = watches.iter()
+                .filter(|watch| watch.term == term && watch.code == code)
+                .collect::<Vec<_>>();
+
+            let instructors = course.instructors.iter()
+                .map(|ins| ins.name.clone())
+                .collect::<Vec<_>>();
 
-                // Rebuild course index in another thread.
-                let data = ctx.data.clone();
-                thread::spawn(move || {
-                    fs::remove_dir_all("./index")
-                        .expect("Couldn't remove index dir.");
+            // Watches from before instructors were kept have nothing to
+            // compare against until they're seen once.
+            let mut seen = false;
 
-                    match uwin::CourseIndex::open() {
-                        Ok(index) => {
-                            data.lock()
-                                .insert::<uwin::CourseIndex>(index);
+            for watch in &watching {
+                match watch.instructors {
+                    Some(ref before) => {
+                        if let Some(change) = watch::instructor_change(before, &instructors) {
+                            tell_watcher(watch.user, &code, format!("`{}` {}: {}.", code, course.title, change));
+                            seen = true;
                         }
-                        Err(e) => error!("Error while indexing: {}", e),
                     }
-                });
+                    None => seen = true,
+                }
+            }
+
+            if seen {
+                if let Some(list) = data.lock().get_mut::<watch::WatchList>() {
+                    if let Err(e) = list.set_instructors(&term, &code, &instructors) {
+                        error!("Couldn't save watches: {}", e);
+                    }
+                }
+            }
+
+            // Sections are only watched while full, so any open seat is new.
+            if !course.availability.has_open_seats() {
+                continue;
+            }
+
+            for watch in watching {
+                tell_watcher(watch.user, &code, format!("A seat opened up in `{}` {} ({}). You're no longer watching it.", code, course.title, course.availability));
+
+                if let Some(list) = data.lock().get_mut::<watch::WatchList>() {
+                    if let Err(e) = list.remove(watch.user, &term, &code) {
+                        error!("Couldn't save watches: {}", e);
+                    }
+                }
             }
         }
+    }
+}
 
-        Ok(())
+/// Message a user about a section they're watching.
+fn tell_watcher(user: u64, code: &str, content: String) {
+    let sent = retry_send(|| UserId(user).create_dm_channel())
+        .and_then(|dm| send_reply(dm.id, &content));
+
+    if let Err(e) = sent {
+        warn!("Couldn't tell user {} about {}: {}", user, code, e);
     }
 }
 
-impl EventHandler for Handler {
-    fn message(&self, ctx: Context, msg: Message) {
-        // Make sure we can send messages in this channel.
-        let can_send = msg.channel()
-            .and_then(|c| c.guild())
-            .map(|c| {
-                let current_user = CACHE.read().user.id;
-                c.read()
-                    .permissions_for(current_user)
-                    .map(|p| p.contains(Permissions::SEND_MESSAGES | Permissions::ATTACH_FILES))
-                    .unwrap_or(false)
-            })
-            .unwrap_or(true);
+/// Send an embed with the bot icon as its thumbnail. Embeds Discord won't
+/// accept, or that we aren't allowed to post in the channel, are sent as
+/// plain text instead so the user still gets an answer.
+fn send_embed(chan: ChannelId, embed: render::Embed) -> Result<(), Error> {
+    send_embed_message(chan, embed)
+        .map(|_| ())
+}
 
-        if !can_send {
-            return;
+/// Send an embed like `send_embed`, returning the message that was sent.
+fn send_embed_message(chan: ChannelId, embed: render::Embed) -> Result<Message, Error> {
+    let permissions = channel_permissions(chan);
+
+    if !embeddable(&embed, permissions) {
+        return send_text(chan, &embed);
+    }
+
+    let result = retry_send(|| {
+        // The thumbnail is an attachment, so leave it off if we can't attach files.
+        if permissions.contains(Permissions::ATTACH_FILES) {
+            chan.send_files(vec![(IMAGE_DATA, "icon.png")], |m| m.embed(|e| {
+                embed.build(e)
+                    .thumbnail("attachment://icon.png")
+            }))
+        } else {
+            chan.send_message(|m| m.embed(|e| embed.build(e)))
         }
+    });
 
-        let mut args = msg.content
-            .split_whitespace();
+    match result {
+        Ok(msg) => Ok(msg),
+        Err(e) => match e.downcast::<SendError>() {
+            Ok(SendError::Invalid(_)) => {
+                warn!("Discord rejected embed {:?}", embed.title);
+                send_text(chan, &embed)
+            }
+            Ok(e) => Err(e.into()),
+            Err(e) => Err(e),
+        },
+    }
+}
 
-        let cmd = match args.next() {
-            Some("~course") => self.fetch_course(ctx, args, msg.channel_id),
-            Some("~reindex") => self.reindex(ctx, msg.member()),
-            _ => return,
-        };
+/// Shows the bot typing the first time a course has to be scraped from the
+/// portal. Courses scraped recently are answered right away, and typing for
+/// them would only flicker.
+struct Typing<F> {
+    typing: F,
+    started: bool,
+}
 
-        if let Err(e) = cmd {
-            error!("Error attempting command: {}", e);
+impl<F> Typing<F>
+    where F: FnMut() -> SerenityResult<()>
+{
+    fn new(typing: F) -> Self {
+        Typing {
+            typing: typing,
+            started: false,
+        }
+    }
+
+    /// Start typing, unless the bot already is.
+    fn start(&mut self) -> Result<(), Error> {
+        if !self.started {
+            retry_send(&mut self.typing)?;
+            self.started = true;
+        }
+
+        Ok(())
+    }
 
-            let _ = msg.channel_id
-                .send_message(|m| m.content("Internal error."));
+    /// Scrape a course, reusing a recent scrape unless it has to be fresh.
+    fn scrape(&mut self, course: &uwin::CoursePreview, fresh: bool) -> Result<uwin::Course, Error> {
+        if fresh || !course.is_cached() {
+            self.start()?;
         }
+
+        if fresh {
+            course.scrape_fresh()
+        } else {
+            course.scrape()
+        }
+    }
+}
+
+/// Show the bot typing in a channel when a course has to be scraped.
+fn typing(chan: ChannelId) -> Typing<impl FnMut() -> SerenityResult<()>> {
+    Typing::new(move || chan.broadcast_typing())
+}
+
+/// The commands `~help` lists. Admin commands are only listed to admins who
+/// ask for them with `-v`.
+fn help_commands(verbose: bool, admin: bool) -> Vec<&'static Command> {
+    COMMANDS.iter()
+        .filter(|command| (verbose && admin) || !command.admin)
+        .filter(|command| is_available(command.name))
+        .collect()
+}
+
+/// Why a command can't be run in a guild, or in a private channel without
+/// one, as a reply saying so. Only admin commands run in maintenance mode.
+fn refusal(settings: Option<&settings::Settings>, guild: Option<u64>, name: &str) -> Option<&'static str> {
+    let settings = settings?;
+
+    match guild {
+        Some(guild) if !settings.is_enabled(guild, name) => return Some("That command is disabled here."),
+        _ => (),
+    }
+
+    let is_user_command = COMMANDS.iter()
+        .any(|command| command.name == name && !command.admin);
+
+    if settings.maintenance() && is_user_command {
+        return Some("Under maintenance, back shortly.");
+    }
+
+    None
+}
+
+/// Whether an embed can be sent as one with some permissions, rather than
+/// as plain text. An embed Discord would reject is logged with the part at
+/// fault.
+fn embeddable(embed: &render::Embed, permissions: Permissions) -> bool {
+    if let Err(e) = embed.validate() {
+        warn!("{}", e);
+        return false;
+    }
+
+    permissions.contains(Permissions::EMBED_LINKS)
+}
+
+/// Send a plain message.
+fn send_reply(chan: ChannelId, content: &str) -> Result<Message, Error> {
+    retry_send(|| chan.send_message(|m| m.content(content)))
+}
+
+/// What can be done about a Discord request that failed.
+#[derive(Debug, PartialEq)]
+enum SendFailure {
+    /// Discord or the connection had a hiccup, so trying again may work.
+    Transient,
+    /// We aren't allowed to do it here.
+    Permission,
+    /// Discord rejected what was sent.
+    Invalid,
+    Other,
+}
+
+impl SendFailure {
+    fn of(e: &SerenityError) -> Self {
+        match *e {
+            SerenityError::Http(HttpError::UnsuccessfulRequest(ref resp)) => SendFailure::of_status(resp.status),
+            SerenityError::Model(ModelError::InvalidPermissions(_)) => SendFailure::Permission,
+            SerenityError::Model(ModelError::EmbedTooLarge(_))
+                | SerenityError::Model(ModelError::MessageTooLong(_))
+                | SerenityError::ExceededLimit(..) => SendFailure::Invalid,
+            SerenityError::Hyper(_) | SerenityError::Io(_) => SendFailure::Transient,
+            _ => SendFailure::Other,
+        }
+    }
+
+    /// What can be done about a request Discord answered with a status.
+    fn of_status(status: StatusCode) -> Self {
+        if status == StatusCode::Forbidden {
+            SendFailure::Permission
+        } else if status == StatusCode::TooManyRequests || status.is_server_error() {
+            SendFailure::Transient
+        } else if status.is_client_error() {
+            SendFailure::Invalid
+        } else {
+            SendFailure::Other
+        }
+    }
+}
+
+/// A Discord request that failed in a way callers can do something about.
+#[derive(Debug, Fail)]
+enum SendError {
+    #[fail(display = "Missing permission: {}", _0)]
+    Permission(String),
+    #[fail(display = "Discord rejected the request: {}", _0)]
+    Invalid(String),
+}
+
+/// Make a Discord request, trying again after a pause if it failed in a way
+/// that may not happen again. Errors come back as a `SendError` where it
+/// tells the caller what went wrong.
+fn retry_send<T, F>(mut send: F) -> Result<T, Error>
+    where F: FnMut() -> SerenityResult<T>
+{
+    let mut attempt = 1;
+
+    loop {
+        let e = match send() {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+
+        let failure = SendFailure::of(&e);
+
+        if failure == SendFailure::Transient && attempt < SEND_ATTEMPTS {
+            warn!("Discord request failed, trying again: {}", e);
+            thread::sleep(Duration::from_millis(500 * attempt));
+            attempt += 1;
+            continue;
+        }
+
+        // Discord says what was wrong in the body of its response.
+        let detail = match e {
+            SerenityError::Http(HttpError::UnsuccessfulRequest(mut resp)) => {
+                let mut body = String::new();
+                let _ = resp.read_to_string(&mut body);
+                format!("{} {}", resp.status, body)
+            }
+            e => e.to_string(),
+        };
+
+        return Err(match failure {
+            SendFailure::Permission => SendError::Permission(detail).into(),
+            SendFailure::Invalid => {
+                warn!("Discord rejected a request: {}", detail);
+                SendError::Invalid(detail).into()
+            }
+            _ => failure::err_msg(detail),
+        });
+    }
+}
+
+/// Our permissions in a channel. Private channels allow everything.
+fn channel_permissions(chan: ChannelId) -> Permissions {
+    let current_user = CACHE.read().user.id;
+
+    chan.find()
+        .and_then(|c| c.guild())
+        .map(|c| {
+            c.read()
+                .permissions_for(current_user)
+                .unwrap_or_else(|_| Permissions::empty())
+        })
+        .unwrap_or_else(Permissions::all)
+}
+
+/// Send an embed as a plain text message.
+fn send_text(chan: ChannelId, embed: &render::Embed) -> Result<Message, Error> {
+    send_reply(chan, &embed.to_text())
+}
+
+/// Remember a search so its pages can be turned by reacting to the message
+/// listing them.
+fn offer_pages(paged: &Mutex<BTreeMap<MessageId, Search>>, msg: Message, search: Search) {
+    for emoji in &[PREV_PAGE_EMOJI, NEXT_PAGE_EMOJI] {
+        if let Err(e) = msg.react(*emoji) {
+            warn!("Couldn't offer pages of results: {}", e);
+            return;
+        }
+    }
+
+    let mut searches = paged.lock();
+    searches.insert(msg.id, search);
+
+    // Message ids increase over time so the first one is the oldest.
+    while searches.len() > MAX_PAGED_LISTS {
+        let oldest = *searches.keys().next().unwrap();
+        searches.remove(&oldest);
+    }
+}
+
+/// Take the page reactions of the bot off a list of results.
+fn remove_page_reactions(chan: ChannelId, msg: MessageId) {
+    for emoji in &[PREV_PAGE_EMOJI, NEXT_PAGE_EMOJI] {
+        if let Err(e) = chan.delete_reaction(msg, None, *emoji) {
+            warn!("Couldn't remove page reaction: {}", e);
+        }
+    }
+}
+
+/// Periodically stop paging lists of results that were sent a while ago.
+fn expire_paged_lists(data: &Arc<Mutex<ShareMap>>) {
+    let timeout = Duration::from_secs(PAGE_MINUTES * 60);
+
+    loop {
+        thread::sleep(Duration::from_secs(60));
+
+        let paged = data.lock()
+            .get::<PagedLists>()
+            .cloned();
+
+        let expired = match paged {
+            Some(paged) => {
+                let mut searches = paged.lock();
+
+                let expired = searches.iter()
+                    .filter(|&(_, search)| search.sent.elapsed() >= timeout)
+                    .map(|(&msg, search)| (search.chan, msg))
+                    .collect::<Vec<_>>();
+
+                for &(_, msg) in &expired {
+                    searches.remove(&msg);
+                }
+
+                expired
+            }
+            None => vec![],
+        };
+
+        // Don't hold the lock while talking to Discord.
+        for (chan, msg) in expired {
+            remove_page_reactions(chan, msg);
+        }
+    }
+}
+
+/// Which way a reaction turns the page, if it's a page reaction.
+fn page_step(emoji: &ReactionType) -> Option<isize> {
+    // Discord may leave off the variation selector of an emoji.
+    let is = |name: &str, emoji: &str| name.trim_end_matches('\u{fe0f}') == emoji.trim_end_matches('\u{fe0f}');
+
+    match *emoji {
+        ReactionType::Unicode(ref name) if is(name, PREV_PAGE_EMOJI) => Some(-1),
+        ReactionType::Unicode(ref name) if is(name, NEXT_PAGE_EMOJI) => Some(1),
+        _ => None,
+    }
+}
+
+/// Tell the user a course code isn't indexed, suggesting close codes if
+/// there are any.
+fn reply_no_such_course(chan: ChannelId, code: &str, suggestions: &[String]) -> Result<(), Error> {
+    let mut content = format!("No such course `{}`.", code);
+
+    if !suggestions.is_empty() {
+        let suggestions = suggestions.iter()
+            .map(|code| format!("`{}`", code))
+            .join(", ");
+
+        content.push_str(&format!(" Did you mean {}?", suggestions));
+    }
+
+    send_reply(chan, &content)?;
+
+    Ok(())
+}
+
+/// Whether the author of a message is an administrator of the guild.
+fn is_admin(member: Option<Member>) -> bool {
+    member
+        .and_then(|member| member.permissions().ok())
+        .map(|perm| perm.administrator())
+        .unwrap_or(false)
+}
+
+/// Tell the user their query was invalid if that's why it failed, otherwise
+/// pass the error along.
+fn reply_query_error(chan: ChannelId, query: &str, e: Error) -> Result<(), Error> {
+    match e.downcast::<uwin::QueryError>() {
+        Ok(e) => {
+            warn!("{}", e);
+
+            let reply = match e {
+                uwin::QueryError::TooComplex(reason) => {
+                    format!("Query `\"{}\"` is too complex, {}.", query, reason)
+                }
+                _ => format!("Query `\"{}\"` is invalid.", query),
+            };
+
+            send_reply(chan, &reply)?;
+
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+struct Handler {
+    /// What commands start with.
+    prefix: String,
+    /// The term searched when none is given, the newest one indexed when
+    /// the bot started.
+    default_term: String,
+    /// Time a user waits between `~course` searches, so one user can't make
+    /// the bot scrape the portal over and over.
+    cooldown: Duration,
+    /// When each user last searched with `~course`.
+    last_search: Mutex<HashMap<UserId, Instant>>,
+}
+
+impl Handler {
+    /// How to use a command, written with the prefix, like
+    /// "Usage: `~prof <NAME>`".
+    fn usage(&self, name: &str) -> String {
+        let usage = COMMANDS.iter()
+            .find(|command| command.name == name)
+            .map_or(name, |command| command.usage);
+
+        format!("Usage: `{}{}`", self.prefix, usage)
+    }
+
+    /// Whether a user searched too recently to search again, noting the
+    /// search if they didn't.
+    fn cooling_down(&self, user: UserId) -> bool {
+        let mut last_search = self.last_search.lock();
+
+        // Forget searches that are past the cooldown so the map stays small.
+        last_search.retain(|_, searched| searched.elapsed() < self.cooldown);
+
+        if last_search.contains_key(&user) {
+            return true;
+        }
+
+        last_search.insert(user, Instant::now());
+        false
+    }
+
+    fn fetch_course<'a, A>(&self, ctx: Context, args: A, author: UserId, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let CourseArgs { help, help_examples, mut terms, relative_term, count, page, per_page, fresh, options, query } = match parse_course_args(args, &self.default_term, &self.prefix) {
+            Ok(args) => args,
+            Err(reply) => {
+                send_reply(chan, &reply)?;
+
+                return Ok(());
+            }
+        };
+
+        if help {
+            return send_embed(chan, render::course_help(&self.prefix));
+        }
+
+        // The course index may not exist if we are reindexing.
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        if help_examples {
+            let courses = index.sample(&self.default_term, HELP_EXAMPLES)?;
+            return send_embed(chan, render::course_examples(&courses, &self.prefix));
+        }
+
+        let paged = ctx.data.lock()
+            .get::<PagedLists>()
+            .cloned();
+        let limits = data_limits(&ctx.data);
+
+        let per_page = per_page.unwrap_or(limits.list);
+
+        if let Some(relative) = relative_term {
+            match index.relative_term(relative) {
+                Some(term) => terms = vec![term],
+                None => {
+                    let which = if relative == uwin::RelativeTerm::Current { "current" } else { "next" };
+                    send_reply(chan, &format!("No {} term is indexed.", which))?;
+
+                    return Ok(());
+                }
+            }
+        }
+
+        let options = on_page(&options, page, per_page);
+
+        // Both ends of a term range have to be indexed for the range to make sense.
+        if terms.len() > 1 {
+            let indexed = index.terms();
+            let missing = vec![&terms[0], &terms[terms.len() - 1]]
+                .into_iter()
+                .find(|&term| !indexed.contains(term))
+                .and_then(|term| uwin::TermCode::from_code(term));
+
+            if let Some(term) = missing {
+                send_reply(chan, &format!("{} is not indexed.", term))?;
+
+                return Ok(());
+            }
+        }
+
+        // Make sure there are enough results for the page asked for.
+        if page > 1 {
+            let most = terms.iter()
+                .map(|term| index.query_count(term, &query, &options))
+                .fold_results(0, |most, count| most.max(count));
+
+            let most = match most {
+                Ok(most) => most,
+                Err(e) => return reply_query_error(chan, &query, e),
+            };
+
+            let pages = page_count(most, per_page);
+
+            if page > pages {
+                let reply = match pages {
+                    0 => format!("No course found for query `\"{}\"`.", query),
+                    1 => "There is only 1 page of results.".to_owned(),
+                    pages => format!("There are only {} pages of results.", pages),
+                };
+
+                send_reply(chan, &reply)?;
+
+                return Ok(());
+            }
+        }
+
+        if count {
+            let total = terms.iter()
+                .map(|term| index.query_count(term, &query, &options))
+                .fold_results(0, |total, count| total + count);
+
+            let total = match total {
+                Ok(total) => total,
+                Err(e) => return reply_query_error(chan, &query, e),
+            };
+
+            let reply = match total {
+                1 => format!("1 course matches `\"{}\"`.", query),
+                total => format!("{} courses match `\"{}\"`.", total, query),
+            };
+
+            send_reply(chan, &reply)?;
+
+            return Ok(());
+        }
+
+        // Courses matching in each term, on every page.
+        let mut totals = vec![];
+
+        let results = terms.iter()
+            .map(|term| {
+                let found = index.query(term, &query, &options)?;
+                totals.push(found.total);
+
+                let mut courses = found.hits
+                    .into_iter()
+                    .map(|hit| hit.preview)
+                    .collect::<Vec<_>>();
+
+                // Sort the courses in order by code.
+                courses.sort_by_key(uwin::CoursePreview::sort_key);
+
+                Ok((term.clone(), courses))
+            })
+            .collect::<Result<Vec<_>, Error>>();
+
+        let mut results = match results {
+            Ok(results) => results,
+            Err(e) => return reply_query_error(chan, &query, e),
+        };
+
+        // Say what `--enrollable` left out so nobody wonders where a course went.
+        let footer = if options.enrollable {
+            let sections = uwin::QueryOptions {
+                sections: true,
+                ..options.clone()
+            };
+
+            let everything = uwin::QueryOptions {
+                enrollable: false,
+                ..sections.clone()
+            };
+
+            let count = |options: &uwin::QueryOptions| {
+                terms.iter()
+                    .map(|term| index.query_count(term, &query, options))
+                    .fold_results(0, |total, count| total + count)
+            };
+
+            let left_out = count(&everything)? - count(&sections)?;
+
+            Some(format!("Only enrollable sections: open seats, no program restriction, and drop date not passed. {} other sections were left out.", left_out))
+        } else {
+            None
+        };
+
+        // Results from a term range are grouped by term instead.
+        if results.len() > 1 && results.iter().any(|&(_, ref courses)| !courses.is_empty()) {
+            let mut embed = render::term_range(&results);
+            embed.footer = footer;
+            return send_embed(chan, embed);
+        }
+
+        let courses = results.pop()
+            .map(|(_, courses)| courses)
+            .unwrap_or_default();
+
+        match courses.as_slice() {
+            [] => {
+                // Let the user know if it was the instructor that ruled out
+                // every course their query matched.
+                let without_instructor = uwin::QueryOptions {
+                    instructor: None,
+                    ..options.clone()
+                };
+
+                let matched_without_instructor = options.instructor.is_some() && terms.iter()
+                    .any(|term| {
+                        index.query(term, &query, &without_instructor)
+                            .map(|found| found.total > 0)
+                            .unwrap_or(false)
+                    });
+
+                let reply = match (&options.instructor, &options.subject) {
+                    (&Some(ref name), _) if matched_without_instructor => {
+                        format!("Courses matched `\"{}\"` but none are taught by `{}`.", query, name)
+                    }
+                    (_, &Some(ref subject)) if query.is_empty() => format!("No courses found in subject `{}`.", subject),
+                    _ => format!("No course found for query `\"{}\"`.", query),
+                };
+
+                send_reply(chan, &reply)?;
+            }
+            [course] if course.sections > 1 => {
+                // Let the user pick which section of the course they meant.
+                let code = uwin::base_code(&course.code);
+
+                let every_section = uwin::QueryOptions {
+                    sections: true,
+                    offset: 0,
+                    limit: ::std::usize::MAX,
+                    ..options.clone()
+                };
+
+                let sections = index.query(&course.term, &query, &every_section)?
+                    .hits
+                    .into_iter()
+                    .map(|hit| hit.preview)
+                    .filter(|section| uwin::base_code(&section.code) == code)
+                    .sorted_by_key(uwin::CoursePreview::sort_key);
+
+                let hint = "Search for a section's code to see its details.".to_owned();
+
+                let mut embed = render::course_list(&sections);
+                embed.title = Some(format!("{} Sections of {}", sections.len(), code));
+                embed.footer = Some(footer.map_or(hint.clone(), |footer| format!("{}\n{}", footer, hint)));
+                send_embed(chan, embed)?;
+            }
+            [course] => {
+                let course = typing(chan).scrape(course, fresh)?;
+                let mut embed = render::course(course, &limits);
+                embed.footer = footer;
+                send_embed(chan, embed)?;
+            }
+            courses => {
+                let total = totals[0];
+
+                let mut embed = render::course_list(courses);
+                embed.footer = footer.clone();
+
+                if total > courses.len() {
+                    embed.title = Some(render::page_title(options.offset, courses.len(), total));
+                }
+
+                let msg = send_embed_message(chan, embed)?;
+
+                // Only a list with more than one page can be paged through.
+                if total > per_page {
+                    if let Some(paged) = paged {
+                        let search = Search {
+                            term: terms[0].clone(),
+                            query: query.clone(),
+                            options: options.clone(),
+                            page: page,
+                            per_page: per_page,
+                            total: total,
+                            footer: footer,
+                            author: author,
+                            chan: chan,
+                            sent: Instant::now(),
+                        };
+
+                        offer_pages(&paged, msg, search);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Turn the page of a list of results in place.
+    fn turn_page(&self, ctx: Context, reaction: &Reaction, step: isize) -> Result<(), Error> {
+        let paged = ctx.data.lock()
+            .get::<PagedLists>()
+            .cloned();
+
+        let (index, paged) = match (data_index(&ctx.data), paged) {
+            (Some(index), Some(paged)) => (index, paged),
+            _ => return Ok(()),
+        };
+
+        // Turn the page of the search and copy out what's needed to show it,
+        // so the searches aren't locked while searching or talking to Discord.
+        let turned = {
+            let mut searches = paged.lock();
+
+            let search = match searches.get_mut(&reaction.message_id) {
+                // Reactions from anyone else are left alone.
+                Some(ref search) if search.author != reaction.user_id => return Ok(()),
+                Some(search) => search,
+                None => return Ok(()),
+            };
+
+            search.turn(step)
+                .map(|options| (search.term.clone(), search.query.clone(), options, search.total, search.footer.clone()))
+        };
+
+        // Clear the reaction so it can be used again. This needs permission
+        // to manage messages, without which reacting again works too.
+        let _ = reaction.delete();
+
+        let (term, query, options, total, footer) = match turned {
+            Some(turned) => turned,
+            None => return Ok(()),
+        };
+
+        let mut courses = index.query(&term, &query, &options)?
+            .hits
+            .into_iter()
+            .map(|hit| hit.preview)
+            .collect::<Vec<_>>();
+
+        courses.sort_by_key(uwin::CoursePreview::sort_key);
+
+        let mut embed = render::course_list(&courses);
+        embed.title = Some(render::page_title(options.offset, courses.len(), total));
+        embed.footer = footer;
+
+        // The list may have been sent as text if we can't embed here.
+        if channel_permissions(reaction.channel_id).contains(Permissions::EMBED_LINKS) {
+            retry_send(|| {
+                reaction.channel_id
+                    .edit_message(reaction.message_id, |m| m.embed(|e| {
+                        embed.build(e)
+                            .thumbnail("attachment://icon.png")
+                    }))
+            })?;
+        } else {
+            retry_send(|| reaction.channel_id.edit_message(reaction.message_id, |m| m.content(embed.to_text())))?;
+        }
+
+        Ok(())
+    }
+
+    fn mirror<'a, A>(&self, ctx: Context, mut args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let code = args.join(" ");
+
+        if code.is_empty() {
+            send_reply(chan, &self.usage("mirror"))?;
+
+            return Ok(());
+        }
+
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        match index.lookup(&self.default_term, &code)? {
+            uwin::CodeLookup::Found(courses) => {
+                let course = typing(chan).scrape(&courses[0], false)?;
+                send_embed(chan, render::antireqs(&course))
+            }
+            uwin::CodeLookup::Missing(suggestions) => reply_no_such_course(chan, &code, &suggestions),
+        }
+    }
+
+    fn prereqs<'a, A>(&self, ctx: Context, mut args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let code = args.join(" ");
+
+        if code.is_empty() {
+            send_reply(chan, &self.usage("prereqs"))?;
+            return Ok(());
+        }
+
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        match index.lookup(&self.default_term, &code)? {
+            uwin::CodeLookup::Found(courses) => {
+                // Prerequisites that aren't cached are scraped too, but the
+                // course itself is the best guess of how long that takes.
+                if !courses[0].is_cached() {
+                    typing(chan).start()?;
+                }
+
+                send_embed(chan, render::prereq_tree(&index.prereq_tree(&courses[0])?))
+            }
+            uwin::CodeLookup::Missing(suggestions) => reply_no_such_course(chan, &code, &suggestions),
+        }
+    }
+
+    fn instructor<'a, A>(&self, ctx: Context, mut args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let name = args.join(" ");
+
+        if name.is_empty() {
+            send_reply(chan, &self.usage("instructor"))?;
+            return Ok(());
+        }
+
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        let limits = data_limits(&ctx.data);
+
+        let courses = match index.query_by_instructor(&self.default_term, &name, limits.list) {
+            Ok(courses) => courses,
+            Err(e) => return reply_query_error(chan, &name, e),
+        };
+
+        if courses.is_empty() {
+            send_reply(chan, &format!("No courses found for instructor `{}` this term.", name))?;
+            return Ok(());
+        }
+
+        let mut embed = render::course_list(&courses);
+        embed.title = Some(format!("Courses Taught by {}", name));
+
+        send_embed(chan, embed)
+    }
+
+    fn prof<'a, A>(&self, ctx: Context, mut args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let name = args.join(" ");
+
+        if name.is_empty() {
+            send_reply(chan, &self.usage("prof"))?;
+
+            return Ok(());
+        }
+
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        let professors = index.professors(&self.default_term, &name)?;
+
+        if professors.is_empty() {
+            send_reply(chan, &format!("No instructor named `{}` is teaching this term.", name))?;
+
+            return Ok(());
+        }
+
+        send_embed(chan, render::professors(&name, &professors))
+    }
+
+    #[cfg(feature = "card")]
+    fn card<'a, A>(&self, ctx: Context, mut args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let code = args.join(" ");
+
+        if code.is_empty() {
+            send_reply(chan, &self.usage("card"))?;
+
+            return Ok(());
+        }
+
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        let preview = match index.lookup(&self.default_term, &code)? {
+            uwin::CodeLookup::Found(mut courses) => courses.remove(0),
+            uwin::CodeLookup::Missing(suggestions) => return reply_no_such_course(chan, &code, &suggestions),
+        };
+
+        let cards = ctx.data.lock()
+            .get::<card::CardCache>()
+            .cloned()
+            .expect("Card cache should be present");
+
+        if let Some(png) = card::cached(&cards, &preview.term, &preview.code) {
+            retry_send(|| chan.send_files(vec![(&png[..], "card.png")], |m| m))?;
+
+            return Ok(());
+        }
+
+        // Rendering takes a while even when the course is cached.
+        typing(chan).start()?;
+
+        let course = preview.scrape()?;
+
+        match card::render(&course) {
+            Ok(png) => {
+                retry_send(|| chan.send_files(vec![(&png[..], "card.png")], |m| m))?;
+
+                card::store(&cards, &preview.term, &preview.code, png);
+
+                Ok(())
+            }
+            Err(e) => {
+                // The embed has the same details, it just can't be shared
+                // as easily.
+                warn!("{}", e);
+
+                let limits = data_limits(&ctx.data);
+
+                send_embed(chan, render::course(course, &limits))
+            }
+        }
+    }
+
+    fn matrix<'a, A>(&self, ctx: Context, args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let mut args = args.peekable();
+
+        let csv = args.peek() == Some(&"--csv");
+        if csv {
+            args.next();
+        }
+
+        let codes = args.collect::<Vec<_>>();
+
+        if codes.is_empty() || codes.len() > MAX_MATRIX_COURSES {
+            send_reply(chan, &format!("{} with up to {} codes.", self.usage("matrix"), MAX_MATRIX_COURSES))?;
+
+            return Ok(());
+        }
+
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        let mut typing = typing(chan);
+
+        // A course that can't be found or scraped still gets a row saying so.
+        let rows = codes.iter()
+            .map(|&code| {
+                let course = match index.lookup(&self.default_term, code) {
+                    Ok(uwin::CodeLookup::Found(courses)) => {
+                        typing.scrape(&courses[0], false)
+                            .map_err(|e| {
+                                warn!("Error scraping {} for {}matrix: {}", code, self.prefix, e);
+                                "Couldn't be scraped".to_owned()
+                            })
+                    }
+                    Ok(uwin::CodeLookup::Missing(_)) => Err("No such course".to_owned()),
+                    Err(e) => Err(e.to_string()),
+                };
+
+                (code.to_owned(), course)
+            })
+            .collect::<Vec<_>>();
+
+        let (table, name) = if csv {
+            (render::matrix_csv(&rows), "courses.csv")
+        } else {
+            (render::matrix_markdown(&rows), "courses.md")
+        };
+
+        retry_send(|| chan.send_files(vec![(table.as_bytes(), name)], |m| m.content("Course comparison")))?;
+
+        Ok(())
+    }
+
+    fn compare<'a, A>(&self, ctx: Context, args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let mut args = args.collect::<Vec<_>>();
+
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        // Terms are picked the same way as with `~course -s`.
+        let term = if args.first() == Some(&"-s") && args.len() > 1 {
+            let term = args[1];
+            args.drain(..2);
+
+            match resolve_term(&index, term) {
+                Some(term) => term,
+                None => {
+                    send_reply(chan, "Semester selection is invalid or isn't indexed.")?;
+                    return Ok(());
+                }
+            }
+        } else {
+            self.default_term.clone()
+        };
+
+        if args.len() != 2 {
+            send_reply(chan, &self.usage("compare"))?;
+            return Ok(());
+        }
+
+        let mut typing = typing(chan);
+        let mut courses = vec![];
+
+        for &code in &args {
+            let sections = match index.lookup(&term, code)? {
+                uwin::CodeLookup::Found(sections) => sections,
+                uwin::CodeLookup::Missing(suggestions) => return reply_no_such_course(chan, code, &suggestions),
+            };
+
+            match typing.scrape(&sections[0], false) {
+                Ok(course) => courses.push(course),
+                Err(e) => {
+                    warn!("Error scraping {} for {}compare: {}", code, self.prefix, e);
+                    send_reply(chan, &format!("Couldn't load `{}` right now, try again shortly.", code))?;
+                    return Ok(());
+                }
+            }
+        }
+
+        send_embed(chan, render::compare(&courses[0], &courses[1]))
+    }
+
+    fn schedule<'a, A>(&self, ctx: Context, args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let codes = args.collect::<Vec<_>>();
+
+        if codes.is_empty() || codes.len() > MAX_SCHEDULE_COURSES {
+            send_reply(chan, &format!("{} with up to {} codes.", self.usage("schedule"), MAX_SCHEDULE_COURSES))?;
+            return Ok(());
+        }
+
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        let mut typing = typing(chan);
+        let mut courses = vec![];
+
+        for &code in &codes {
+            match index.lookup(&self.default_term, code)? {
+                uwin::CodeLookup::Found(sections) => courses.push(typing.scrape(&sections[0], false)?),
+                uwin::CodeLookup::Missing(suggestions) => return reply_no_such_course(chan, code, &suggestions),
+            }
+        }
+
+        let conflicts = uwin::find_conflicts(&courses);
+
+        send_embed(chan, render::schedule(&courses, &conflicts))
+    }
+
+    fn exams<'a, A>(&self, ctx: Context, args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let mut args = args.collect::<Vec<_>>();
+
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        // Terms are picked the same way as with `~course -s`.
+        let term = if args.first() == Some(&"-s") && args.len() > 1 {
+            let term = args[1];
+            args.drain(..2);
+
+            match resolve_term(&index, term) {
+                Some(term) => term,
+                None => {
+                    send_reply(chan, "Semester selection is invalid or isn't indexed.")?;
+                    return Ok(());
+                }
+            }
+        } else {
+            self.default_term.clone()
+        };
+
+        if args.is_empty() || args.len() > MAX_MATRIX_COURSES {
+            send_reply(chan, &format!("{} with up to {} codes.", self.usage("exams"), MAX_MATRIX_COURSES))?;
+            return Ok(());
+        }
+
+        let mut typing = typing(chan);
+        let mut courses = vec![];
+
+        for &code in &args {
+            match index.lookup(&term, code)? {
+                uwin::CodeLookup::Found(sections) => courses.push(typing.scrape(&sections[0], false)?),
+                uwin::CodeLookup::Missing(suggestions) => return reply_no_such_course(chan, code, &suggestions),
+            }
+        }
+
+        let (calendar, skipped) = ics::exams(&term, &courses);
+
+        if skipped == courses.iter().map(|course| course.exams.len()).sum::<usize>() {
+            send_reply(chan, "None of the courses have an exam scheduled yet.")?;
+            return Ok(());
+        }
+
+        let content = match skipped {
+            0 => "Exams to import into your calendar".to_owned(),
+            1 => "Exams to import into your calendar. 1 exam without a date and time yet was left out.".to_owned(),
+            skipped => format!("Exams to import into your calendar. {} exams without a date and time yet were left out.", skipped),
+        };
+
+        retry_send(|| chan.send_files(vec![(calendar.as_bytes(), "exams.ics")], |m| m.content(&content)))?;
+
+        Ok(())
+    }
+
+    fn exams_on<'a, A>(&self, ctx: Context, args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let args = args.collect::<Vec<_>>();
+
+        let (date, codes) = match split_exam_date(&args) {
+            Some((date, codes)) if !codes.is_empty() && codes.len() <= MAX_MATRIX_COURSES => (date, codes),
+            _ => {
+                send_reply(chan, &format!("{} with up to {} codes, like `{}examson Dec 15 COMP1000`.", self.usage("examson"), MAX_MATRIX_COURSES, self.prefix))?;
+
+                return Ok(());
+            }
+        };
+
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        let mut typing = typing(chan);
+        let mut lines = vec![];
+
+        for &code in codes {
+            let course = match index.lookup(&self.default_term, code)? {
+                uwin::CodeLookup::Found(courses) => typing.scrape(&courses[0], false)?,
+                uwin::CodeLookup::Missing(suggestions) => return reply_no_such_course(chan, code, &suggestions),
+            };
+
+            lines.extend(exams_on_date(&course, date));
+        }
+
+        let content = if lines.is_empty() {
+            "No exams that day.".to_owned()
+        } else {
+            lines.join("\n")
+        };
+
+        send_reply(chan, &content)?;
+
+        Ok(())
+    }
+
+    fn terms<'a, A>(&self, ctx: Context, mut args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        let terms = index.terms();
+
+        // `--current` only shows the term used when no `-s` is given.
+        let terms = match args.next() {
+            Some("--current") => {
+                terms.into_iter()
+                    .filter(|term| *term == self.default_term)
+                    .collect()
+            }
+            _ => terms,
+        };
+
+        send_embed(chan, render::terms(&terms, &self.default_term))
+    }
+
+    fn subjects<'a, A>(&self, ctx: Context, mut args: A, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let text = args.join(" ").to_lowercase();
+
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        let subjects = index.subjects()?
+            .into_iter()
+            .filter(|&(ref code, ref name)| {
+                code.to_lowercase().contains(&text) || name.to_lowercase().contains(&text)
+            })
+            .collect::<Vec<_>>();
+
+        if subjects.is_empty() {
+            let reply = if text.is_empty() {
+                "No subjects are known yet.".to_owned()
+            } else {
+                format!("No subjects mention `{}`.", text)
+            };
+
+            send_reply(chan, &reply)?;
+            return Ok(());
+        }
+
+        send_embed(chan, render::subjects(&subjects, &self.prefix))
+    }
+
+    fn status(&self, ctx: Context, chan: ChannelId) -> Result<(), Error> {
+        let index = match data_index(&ctx.data) {
+            Some(index) => index,
+            _ => return Ok(()),
+        };
+
+        let subjects = index.subjects()
+            .map_err(|e| warn!("Couldn't get subjects: {}", e))
+            .ok()
+            .map(|subjects| subjects.len());
+
+        let status = render::IndexStatus {
+            courses: index.num_docs(),
+            terms: index.terms(),
+            written_at: index.written_at(),
+            subjects: subjects,
+        };
+
+        send_embed(chan, render::status(&status, index.field_stats()))
+    }
+
+    fn help<'a, A>(&self, mut args: A, member: Option<Member>, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let commands = help_commands(args.next() == Some("-v"), is_admin(member));
+        send_embed(chan, render::help(&commands, &self.prefix))
+    }
+
+    fn reindex<'a, A>(&self, ctx: Context, mut args: A, member: Option<Member>, chan: ChannelId) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        // We want to reindex if a person from a channel is an administrator.
+        if is_admin(member) {
+            match args.next() {
+                Some("--validate") => return self.validate(ctx, chan),
+                Some("--incremental") => return self.reindex_incremental(ctx, chan),
+                _ => {}
+            }
+
+            // Remove current course index. It's rebuilt where it was opened
+            // from.
+            let removed = ctx.data.lock()
+                .remove::<uwin::CourseIndex>();
+
+            // How often courses were looked up carries over to the new index.
+            let (path, lookups) = match removed {
+                Some(index) => (index.path().to_owned(), index.scraper().lookups()),
+                None => return Ok(()),
+            };
+
+            // Rebuild course index in another thread.
+            let data = ctx.data.clone();
+            thread::spawn(move || {
+                let portal = data_portal(&data);
+                let filter = data_index_filter(&data);
+
+                let index = uwin::CourseIndex::rebuild(&path, &portal, &filter)
+                    .or_else(|e| {
+                        // The old index is untouched, so keep serving it.
+                        error!("Error while indexing, keeping the previous index: {}", e);
+                        uwin::CourseIndex::open(&path, &portal, &filter)
+                    });
+
+                let index = match index {
+                    Ok(index) => Arc::new(index),
+                    Err(e) => {
+                        error!("Error reopening index: {}", e);
+                        return;
+                    }
+                };
+
+                index.scraper().add_lookups(lookups);
+
+                data.lock()
+                    .insert::<uwin::CourseIndex>(index.clone());
+
+                prefetch_popular(&data, &index);
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Scrape only the courses added since the index was built and drop the
+    /// ones removed, serving the current index meanwhile.
+    fn reindex_incremental(&self, ctx: Context, chan: ChannelId) -> Result<(), Error> {
+        // A full reindex is underway.
+        if !ctx.data.lock().contains::<uwin::CourseIndex>() {
+            return Ok(());
+        }
+
+        if UPDATING_INDEX.swap(true, Ordering::SeqCst) {
+            send_reply(chan, "An incremental reindex is already running.")?;
+            return Ok(());
+        }
+
+        let data = ctx.data.clone();
+        thread::spawn(move || {
+            let result = update_index(&data);
+            UPDATING_INDEX.store(false, Ordering::SeqCst);
+
+            let reply = match result {
+                Ok(Some(changes)) => format!("Incremental reindex added {} and removed {} courses.", changes.added(), changes.removed()),
+                // A full reindex started meanwhile and will pick everything up.
+                Ok(None) => return,
+                Err(e) => {
+                    error!("Error during incremental reindex: {}", e);
+                    "Incremental reindex failed, the index is unchanged.".to_owned()
+                }
+            };
+
+            if let Err(e) = send_reply(chan, &reply) {
+                error!("Couldn't report incremental reindex: {}", e);
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Turn a command on or off for the guild the message was sent in.
+    fn toggle<'a, A>(&self, ctx: Context, mut args: A, msg: &Message, enabled: bool) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let guild = match msg.guild_id() {
+            Some(guild) if is_admin(msg.member()) => guild,
+            _ => return Ok(()),
+        };
+
+        let name = args.next()
+            .map(|name| name.trim_start_matches(&*self.prefix));
+
+        let name = match name {
+            Some(name) if TOGGLEABLE_COMMANDS.contains(&name) => name,
+            _ => {
+                let content = format!("{} where the command is one of {}.",
+                    self.usage(if enabled { "enable" } else { "disable" }),
+                    TOGGLEABLE_COMMANDS.iter().map(|c| format!("`{}{}`", self.prefix, c)).join(", "));
+
+                send_reply(msg.channel_id, &content)?;
+
+                return Ok(());
+            }
+        };
+
+        {
+            let mut data = ctx.data.lock();
+            let settings = data.get_mut::<settings::Settings>()
+                .ok_or_else(|| failure::err_msg("Settings are not loaded."))?;
+
+            settings.set_enabled(guild.0, name, enabled)?;
+        }
+
+        let content = format!("`{}{}` is now {} here.", self.prefix, name, if enabled { "enabled" } else { "disabled" });
+
+        send_reply(msg.channel_id, &content)?;
+
+        Ok(())
+    }
+
+    /// Turn maintenance mode on or off for every guild.
+    fn maintenance<'a, A>(&self, ctx: Context, mut args: A, msg: &Message) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        if !is_admin(msg.member()) {
+            return Ok(());
+        }
+
+        let on = match args.next() {
+            Some("on") => true,
+            Some("off") => false,
+            _ => {
+                send_reply(msg.channel_id, &self.usage("maintenance"))?;
+
+                return Ok(());
+            }
+        };
+
+        {
+            let mut data = ctx.data.lock();
+            let settings = data.get_mut::<settings::Settings>()
+                .ok_or_else(|| failure::err_msg("Settings are not loaded."))?;
+
+            settings.set_maintenance(on)?;
+        }
+
+        set_presence(&ctx, &self.prefix, on);
+
+        let content = if on { "Maintenance mode is on." } else { "Maintenance mode is off." };
+
+        send_reply(msg.channel_id, content)?;
+
+        Ok(())
+    }
+
+    /// Subscribe a user to a full section so they get a message when a seat
+    /// opens up, or list the sections they're watching.
+    fn watch<'a, A>(&self, ctx: Context, mut args: A, msg: &Message) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let code = args.join(" ");
+        let user = msg.author.id.0;
+
+        if code.is_empty() {
+            let reply = {
+                let data = ctx.data.lock();
+                let list = data.get::<watch::WatchList>()
+                    .ok_or_else(|| failure::err_msg("Watches are not loaded."))?;
+
+                let watches = list.of_user(user);
+
+                if watches.is_empty() {
+                    format!("You aren't watching any sections. {}", self.usage("watch"))
+                } else {
+                    format!("You're watching {}.", watches.iter().map(|watch| format!("`{}`", watch.code)).join(", "))
+                }
+            };
+
+            send_reply(msg.channel_id, &reply)?;
+
+            return Ok(());
+        }
+
+        let (section, instructors) = {
+            let index = match data_index(&ctx.data) {
+                Some(index) => index,
+                _ => return Ok(()),
+            };
+
+            let sections = match index.lookup(&self.default_term, &code)? {
+                uwin::CodeLookup::Found(courses) => courses,
+                uwin::CodeLookup::Missing(suggestions) => return reply_no_such_course(msg.channel_id, &code, &suggestions),
+            };
+
+            if sections.len() > 1 {
+                let codes = sections.iter()
+                    .map(|course| format!("`{}`", course.code))
+                    .join(", ");
+
+                send_reply(msg.channel_id, &format!("`{}` has {} sections, watch one of them: {}", code, sections.len(), codes))?;
+
+                return Ok(());
+            }
+
+            let course = typing(msg.channel_id).scrape(&sections[0], false)?;
+
+            if course.availability.has_open_seats() {
+                send_reply(msg.channel_id, &format!("`{}` already has open seats ({}).", sections[0].code, course.availability))?;
+
+                return Ok(());
+            }
+
+            let instructors = course.instructors.into_iter()
+                .map(|ins| ins.name)
+                .collect::<Vec<_>>();
+
+            (sections[0].code.clone(), instructors)
+        };
+
+        let reply = {
+            let mut data = ctx.data.lock();
+            let list = data.get_mut::<watch::WatchList>()
+                .ok_or_else(|| failure::err_msg("Watches are not loaded."))?;
+
+            if list.of_user(user).len() >= MAX_WATCHES {
+                format!("You can watch at most {} sections. Use `{}unwatch <CODE>` to make room.", MAX_WATCHES, self.prefix)
+            } else if list.add(user, &self.default_term, &section, instructors)? {
+                format!("Watching `{}`. I'll message you when a seat opens up.", section)
+            } else {
+                format!("You're already watching `{}`.", section)
+            }
+        };
+
+        send_reply(msg.channel_id, &reply)?;
+
+        Ok(())
+    }
+
+    /// Stop messaging a user about a section they were watching.
+    fn unwatch<'a, A>(&self, ctx: Context, mut args: A, msg: &Message) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        // Codes are watched in the same form `CourseIndex::lookup` finds them.
+        let code = args.join(" ")
+            .chars()
+            .filter(|c| *c != '-' && !c.is_whitespace())
+            .flat_map(char::to_uppercase)
+            .collect::<String>();
+
+        if code.is_empty() {
+            send_reply(msg.channel_id, &self.usage("unwatch"))?;
+
+            return Ok(());
+        }
+
+        let removed = {
+            let mut data = ctx.data.lock();
+            let list = data.get_mut::<watch::WatchList>()
+                .ok_or_else(|| failure::err_msg("Watches are not loaded."))?;
+
+            list.remove(msg.author.id.0, &self.default_term, &code)?
+        };
+
+        let reply = if removed {
+            format!("Stopped watching `{}`.", code)
+        } else {
+            format!("You aren't watching `{}`.", code)
+        };
+
+        send_reply(msg.channel_id, &reply)?;
+
+        Ok(())
+    }
+
+    fn feedback<'a, A>(&self, ctx: Context, mut args: A, msg: &Message) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        let text = args.join(" ");
+
+        if text.is_empty() {
+            send_reply(msg.channel_id, &self.usage("feedback"))?;
+
+            return Ok(());
+        }
+
+        let id = {
+            let mut data = ctx.data.lock();
+            let store = data.get_mut::<feedback::FeedbackStore>()
+                .ok_or_else(|| failure::err_msg("Feedback is not loaded."))?;
+
+            store.add(msg.author.tag(), text)?
+        };
+
+        send_reply(msg.channel_id, &format!("Thanks, your feedback was recorded as #{}.", id))?;
+
+        Ok(())
+    }
+
+    /// List open feedback, show a report, or mark one resolved.
+    fn feedback_resolve<'a, A>(&self, ctx: Context, mut args: A, msg: &Message) -> Result<(), Error>
+        where A: Iterator<Item = &'a str>
+    {
+        if !is_admin(msg.member()) {
+            return Ok(());
+        }
+
+        let action = args.next();
+        let id = args.next()
+            .and_then(|id| id.trim_start_matches('#').parse().ok());
+
+        // The store is only locked to read or resolve feedback. The reply is
+        // an embed, or a line of text when there's nothing to list.
+        let reply = {
+            let mut data = ctx.data.lock();
+            let store = data.get_mut::<feedback::FeedbackStore>()
+                .ok_or_else(|| failure::err_msg("Feedback is not loaded."))?;
+
+            match (action, id) {
+                (None, _) => Ok(render::feedback_list(&store.open())),
+                (Some("show"), Some(id)) => {
+                    store.get(id)
+                        .map(render::feedback)
+                        .ok_or_else(|| format!("There is no feedback #{}.", id))
+                }
+                (Some("resolve"), Some(id)) => {
+                    if store.resolve(id)? {
+                        Err(format!("Feedback #{} is resolved.", id))
+                    } else {
+                        Err(format!("There is no feedback #{}.", id))
+                    }
+                }
+                _ => Err(self.usage("feedback-resolve")),
+            }
+        };
+
+        match reply {
+            Ok(embed) => send_embed(msg.channel_id, embed),
+            Err(content) => {
+                send_reply(msg.channel_id, &content)?;
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Check the portal layout with a small sample of courses without
+    /// touching the live index.
+    fn validate(&self, ctx: Context, chan: ChannelId) -> Result<(), Error> {
+        // Every course in the sample is scraped from the portal.
+        typing(chan).start()?;
+
+        let uwin::Validation { checked, error } = uwin::Scraper::new(data_portal(&ctx.data))
+            .validate(VALIDATE_SAMPLE);
+
+        match error {
+            None => {
+                send_reply(chan, &format!("Portal structure OK, sampled {} courses.", checked))?;
+            }
+            Some(e) => {
+                warn!("Portal validation failed: {}", e);
+
+                send_reply(chan, &format!("Portal validation failed after sampling {} courses: {}", checked, e))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl EventHandler for Handler {
+    fn ready(&self, ctx: Context, _: Ready) {
+        let maintenance = ctx.data.lock()
+            .get::<settings::Settings>()
+            .map_or(false, settings::Settings::maintenance);
+
+        set_presence(&ctx, &self.prefix, maintenance);
+    }
+
+    fn message(&self, ctx: Context, msg: Message) {
+        // No new commands are taken once the bot is shutting down.
+        if SIGNALLED.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // Make sure we can send messages in this channel. Without permission
+        // to embed links or attach files replies fall back to plain text.
+        if !channel_permissions(msg.channel_id).contains(Permissions::SEND_MESSAGES) {
+            return;
+        }
+
+        let mut args = msg.content
+            .split_whitespace();
+
+        let name = match args.next() {
+            Some(word) if word.starts_with(&*self.prefix) => &word[self.prefix.len()..],
+            _ => return,
+        };
+
+        let refused = refusal(ctx.data.lock().get::<settings::Settings>(), msg.guild_id().map(|guild| guild.0), name);
+
+        if let Some(reply) = refused {
+            let _ = send_reply(msg.channel_id, reply);
+            return;
+        }
+
+        // Only searches are limited, so admin commands like `~reindex` aren't.
+        if name == "course" && self.cooling_down(msg.author.id) {
+            let _ = send_reply(msg.channel_id, "Slow down, try that again in a few seconds.");
+            return;
+        }
+
+        let cmd = match name {
+            "course" => self.fetch_course(ctx, args, msg.author.id, msg.channel_id),
+            "mirror" => self.mirror(ctx, args, msg.channel_id),
+            "prereqs" => self.prereqs(ctx, args, msg.channel_id),
+            "prof" => self.prof(ctx, args, msg.channel_id),
+            "instructor" => self.instructor(ctx, args, msg.channel_id),
+            #[cfg(feature = "card")]
+            "card" => self.card(ctx, args, msg.channel_id),
+            "matrix" => self.matrix(ctx, args, msg.channel_id),
+            "compare" => self.compare(ctx, args, msg.channel_id),
+            "schedule" => self.schedule(ctx, args, msg.channel_id),
+            "exams" => self.exams(ctx, args, msg.channel_id),
+            "examson" => self.exams_on(ctx, args, msg.channel_id),
+            "subjects" => self.subjects(ctx, args, msg.channel_id),
+            "terms" => self.terms(ctx, args, msg.channel_id),
+            "status" => self.status(ctx, msg.channel_id),
+            "help" => self.help(args, msg.member(), msg.channel_id),
+            "whatsnew" => send_embed(msg.channel_id, render::whats_new(&WHATS_NEW[..WHATS_NEW.len().min(WHATS_NEW_SHOWN)], &self.prefix)),
+            "reindex" => self.reindex(ctx, args, msg.member(), msg.channel_id),
+            "enable" => self.toggle(ctx, args, &msg, true),
+            "disable" => self.toggle(ctx, args, &msg, false),
+            "maintenance" => self.maintenance(ctx, args, &msg),
+            "feedback" => self.feedback(ctx, args, &msg),
+            "feedback-resolve" => self.feedback_resolve(ctx, args, &msg),
+            "watch" => self.watch(ctx, args, &msg),
+            "unwatch" => self.unwatch(ctx, args, &msg),
+            _ => return,
+        };
+
+        if let Err(e) = cmd {
+            let reply = if let Some(&uwin::QueryError::BadCode(ref code)) = e.downcast_ref() {
+                format!("`{}` isn't a course code. Try a code like `COMP-1000` or `60-140`.", code)
+            } else if e.downcast_ref::<uwin::ScrapeError>().is_some() {
+                warn!("Error attempting command: {}", e);
+                "Couldn't load course details right now, try again shortly.".to_owned()
+            } else {
+                match e.downcast_ref::<SendError>() {
+                    Some(&SendError::Permission(_)) => {
+                        warn!("Error attempting command: {}", e);
+                        "I'm missing a permission I need here. Ask an admin to let me send messages, embed links, and attach files in this channel.".to_owned()
+                    }
+                    _ => {
+                        error!("Error attempting command: {}", e);
+                        "Internal error.".to_owned()
+                    }
+                }
+            };
+
+            let _ = send_reply(msg.channel_id, &reply);
+        }
+    }
+
+    fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        if reaction.user_id == CACHE.read().user.id {
+            return;
+        }
+
+        let step = match page_step(&reaction.emoji) {
+            Some(step) => step,
+            None => return,
+        };
+
+        if let Err(e) = self.turn_page(ctx, &reaction, step) {
+            error!("Error turning the page of results: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embed(value: String) -> render::Embed {
+        render::Embed {
+            title: Some("COMP-1000".to_owned()),
+            description: None,
+            fields: vec![("Prerequisites", value, false)],
+            footer: None,
+        }
+    }
+
+    fn args(line: &str) -> Result<CourseArgs, String> {
+        parse_course_args(line.split_whitespace(), "20185", "~")
+    }
+
+    #[test]
+    fn term_range_searches_each_term() {
+        let parsed = args("--term-range f18-w19 graph theory").ok().unwrap();
+
+        assert_eq!(parsed.terms, vec!["20185".to_owned(), "20191".to_owned()]);
+        assert_eq!(parsed.query, "graph theory");
+
+        assert_eq!(args("--term-range w18-w20 graph").err().unwrap(), "A term range can span at most 6 semesters.");
+        assert_eq!(args("--term-range w19-f18 graph").err().unwrap(), "Term range is invalid.");
+    }
+
+    #[test]
+    fn typing_only_for_a_cache_miss() {
+        let scraper = uwin::tests::scraper_with(&["COMP100001"]);
+        let mut typed = 0;
+
+        {
+            let mut typing = Typing::new(|| {
+                typed += 1;
+                Ok(())
+            });
+
+            typing.scrape(&uwin::tests::preview(&scraper, "COMP100001"), false).unwrap();
+        }
+
+        assert_eq!(typed, 0);
+        assert!(!uwin::tests::preview(&scraper, "COMP200001").is_cached());
+
+        {
+            let mut typing = Typing::new(|| {
+                typed += 1;
+                Ok(())
+            });
+
+            typing.start().unwrap();
+            typing.start().unwrap();
+        }
+
+        assert_eq!(typed, 1);
+    }
+
+    #[test]
+    fn self_test_checks_the_default_term() {
+        assert_eq!(self_test(&uwin::tests::index_of(&[]), "20185", "~"), SelfTest::Empty);
+
+        let index = uwin::tests::index_of(&["COMP100001", "COMP200001"]);
+
+        assert_eq!(self_test(&index, "20185", "~"), SelfTest::Found(2));
+        assert_eq!(self_test(&index, "20191", "~"), SelfTest::NoneInTerm(2));
+    }
+
+    #[test]
+    fn disabled_command_is_refused_in_its_guild() {
+        let settings: settings::Settings = serde_json::from_str(r#"{ "guilds": { "1": { "disabled": ["watch"] } } }"#).unwrap();
+
+        assert_eq!(refusal(Some(&settings), Some(1), "watch"), Some("That command is disabled here."));
+        assert_eq!(refusal(Some(&settings), Some(1), "course"), None);
+        assert_eq!(refusal(Some(&settings), Some(2), "watch"), None);
+        assert_eq!(refusal(Some(&settings), None, "watch"), None);
+        assert_eq!(refusal(None, Some(1), "watch"), None);
+    }
+
+    #[test]
+    fn limits_are_set_apart_in_the_config() {
+        let defaults = render::Limits::default();
+
+        let limits = render_limits(&config::Config {
+            description_limit: Some(50),
+            ..config::Config::default()
+        });
+
+        assert_eq!((limits.description, limits.field, limits.list), (50, defaults.field, defaults.list));
+
+        let limits = render_limits(&config::Config {
+            field_limit: Some(100),
+            list_limit: Some(0),
+            ..config::Config::default()
+        });
+
+        assert_eq!((limits.description, limits.field, limits.list), (defaults.description, 100, 1));
+
+        // Limits past what Discord allows are brought back within it.
+        let limits = render_limits(&config::Config {
+            description_limit: Some(10_000),
+            field_limit: Some(10_000),
+            ..config::Config::default()
+        });
+
+        assert!(limits.description < 2048);
+        assert_eq!(limits.field, 1024);
+    }
+
+    #[test]
+    fn maintenance_refuses_only_user_commands() {
+        let settings: settings::Settings = serde_json::from_str(r#"{ "guilds": {}, "maintenance": true }"#).unwrap();
+
+        assert_eq!(refusal(Some(&settings), Some(1), "course"), Some("Under maintenance, back shortly."));
+        assert_eq!(refusal(Some(&settings), None, "prereqs"), Some("Under maintenance, back shortly."));
+        assert_eq!(refusal(Some(&settings), Some(1), "maintenance"), None);
+        assert_eq!(refusal(Some(&settings), Some(1), "reindex"), None);
+        // Messages that aren't commands are left alone.
+        assert_eq!(refusal(Some(&settings), Some(1), "hello"), None);
+    }
+
+    #[test]
+    fn admin_commands_are_hidden_from_non_admins() {
+        let names = |verbose, admin| {
+            help_commands(verbose, admin).iter()
+                .map(|command| command.name)
+                .collect::<Vec<_>>()
+        };
+
+        for &(verbose, admin) in &[(false, false), (true, false), (false, true)] {
+            assert!(!names(verbose, admin).contains(&"reindex"));
+            assert!(names(verbose, admin).contains(&"course"));
+        }
+
+        assert!(names(true, true).contains(&"reindex"));
+        assert!(names(true, true).contains(&"course"));
+    }
+
+    #[test]
+    fn pages_turn_within_the_results() {
+        let mut search = Search {
+            term: "20185".to_owned(),
+            query: "programming".to_owned(),
+            options: uwin::QueryOptions::default(),
+            page: 1,
+            per_page: 10,
+            total: 25,
+            footer: None,
+            author: UserId(1),
+            chan: ChannelId(1),
+            sent: Instant::now(),
+        };
+
+        let mut offsets = vec![];
+
+        for &step in &[1, 1, 1, -1, -1, -1] {
+            offsets.push(search.turn(step).map(|options| (options.offset, options.limit)));
+        }
+
+        assert_eq!(offsets, vec![Some((10, 10)), Some((20, 10)), None, Some((10, 10)), Some((0, 10)), None]);
+        assert_eq!(search.page, 1);
+
+        assert_eq!(page_step(&ReactionType::Unicode("➡".to_owned())), Some(1));
+        assert_eq!(page_step(&ReactionType::Unicode(PREV_PAGE_EMOJI.to_owned())), Some(-1));
+        assert_eq!(page_step(&ReactionType::Unicode("👍".to_owned())), None);
+    }
+
+    #[test]
+    fn index_is_stale_with_enough_newer_terms() {
+        let codes = |codes: &[&str]| codes.iter().map(|code| code.to_string()).collect::<Vec<_>>();
+
+        let indexed = codes(&["20181", "20185"]);
+
+        assert!(stale_terms(&indexed, codes(&["20181", "20185"]), &[], 1).is_empty());
+        assert_eq!(stale_terms(&indexed, codes(&["20185", "20191"]), &[], 1), codes(&["20191"]));
+        assert!(stale_terms(&indexed, codes(&["20185", "20191"]), &[], 2).is_empty());
+        assert_eq!(stale_terms(&indexed, codes(&["20191", "20192"]), &[], 2), codes(&["20191", "20192"]));
+        // A newer term that isn't meant to be indexed is left out.
+        assert!(stale_terms(&indexed, codes(&["20191"]), &codes(&["20181", "20185"]), 1).is_empty());
+        assert_eq!(stale_terms(&[], codes(&["20185"]), &[], 1), codes(&["20185"]));
+    }
+
+    #[test]
+    fn options_are_read_from_arguments() {
+        let parsed = args("-s w19 -o --subject comp --instructor smith --eligible 2 --delivery online graph theory --exclude lab -n 5 --page 2").ok().unwrap();
+
+        assert_eq!(parsed.terms, vec!["20191".to_owned()]);
+        assert_eq!(parsed.query, "graph theory");
+        assert_eq!(parsed.per_page, Some(5));
+        assert_eq!(parsed.page, 2);
+
+        let options = parsed.options;
+        assert!(options.open && !options.enrollable && !options.sections);
+        assert_eq!(options.subject, Some("comp".to_owned()));
+        assert_eq!(options.instructor, Some("smith".to_owned()));
+        assert_eq!(options.eligible, Some(2));
+        assert_eq!(options.delivery, Some("online".to_owned()));
+        assert_eq!(options.exclude, vec!["lab".to_owned()]);
+
+        let defaults = args("graph theory").ok().unwrap();
+        assert_eq!(defaults.terms, vec!["20185".to_owned()]);
+        assert_eq!(defaults.page, 1);
+        assert_eq!(defaults.per_page, None);
+        assert!(defaults.options.subject.is_none() && !defaults.options.open);
+
+        // A subject can be browsed without a query, but nothing else can.
+        assert_eq!(args("--subject COMP").ok().unwrap().query, "");
+        assert!(args("-o").err().unwrap().starts_with("A search query is missing"));
+        assert_eq!(args("--eligible 9 graph").err().unwrap(), "Year of study is invalid.");
+        assert_eq!(args("-s x19 graph").err().unwrap(), "Semester selection is invalid.");
+        assert_eq!(args("graph --instructor").err().unwrap(), "Instructor name is missing.");
+    }
+
+    #[test]
+    fn invalid_embed_falls_back_to_text() {
+        let invalid = embed("COMP-1000 or ".repeat(200));
+
+        assert!(!embeddable(&invalid, Permissions::all()));
+        assert!(invalid.validate().unwrap_err().to_string().contains("Prerequisites"));
+
+        let text = invalid.to_text();
+        assert!(text.starts_with("**COMP-1000**"));
+        assert!(text.chars().count() <= 2000);
+
+        let valid = embed("COMP-1000".to_owned());
+
+        assert!(embeddable(&valid, Permissions::all()));
+        assert!(!embeddable(&valid, Permissions::SEND_MESSAGES));
+    }
+
+    #[test]
+    fn exams_are_matched_to_the_date_asked_about() {
+        use chrono::{ NaiveDate, NaiveTime };
+
+        let mut course = uwin::tests::course("COMP100001");
+        course.exams = vec![
+            uwin::tests::exam("Midterm", NaiveDate::from_ymd_opt(2018, 10, 15), None),
+            uwin::tests::exam("Final", NaiveDate::from_ymd_opt(2018, 12, 15), Some((NaiveTime::from_hms(19, 0, 0), NaiveTime::from_hms(22, 0, 0)))),
+            uwin::tests::exam("Lab", None, None),
+        ];
+
+        let (date, codes) = split_exam_date(&["Dec", "15", "COMP1000", "MATH1720"]).unwrap();
+        assert_eq!(codes, &["COMP1000", "MATH1720"]);
+        assert_eq!(exams_on_date(&course, date), vec!["`COMP100001` Key Concepts in Computer Science: **Final** at 7:00 PM - 10:00 PM"]);
+
+        let (date, codes) = split_exam_date(&["10/15", "COMP1000"]).unwrap();
+        assert_eq!(codes, &["COMP1000"]);
+        assert_eq!(exams_on_date(&course, date), vec!["`COMP100001` Key Concepts in Computer Science: **Midterm**"]);
+
+        let (date, _) = split_exam_date(&["Dec", "16", "COMP1000"]).unwrap();
+        assert!(exams_on_date(&course, date).is_empty());
+
+        assert_eq!(split_exam_date(&["COMP1000"]), None);
+        assert_eq!(split_exam_date(&[]), None);
+    }
+
+    #[test]
+    fn whats_new_starts_with_this_version() {
+        let version = |text: &str| text.split('.')
+            .map(|part| part.parse::<u32>().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(WHATS_NEW[0].0, env!("CARGO_PKG_VERSION"));
+
+        for pair in WHATS_NEW.windows(2) {
+            assert!(version(pair[0].0) > version(pair[1].0), "{} isn't newer than {}", pair[0].0, pair[1].0);
+        }
+    }
+
+    #[test]
+    fn second_page_is_the_second_slice_of_results() {
+        let index = uwin::tests::index_of(&["COMP100001", "COMP200001", "COMP300001", "COMP400001", "COMP500001"]);
+        let parsed = args("-n 2 --page 2 computer").ok().unwrap();
+        let options = on_page(&parsed.options, parsed.page, parsed.per_page.unwrap());
+
+        let codes = index.query("20185", &parsed.query, &options).unwrap()
+            .hits
+            .into_iter()
+            .map(|hit| hit.preview.code)
+            .collect::<Vec<_>>();
+
+        assert_eq!(codes, vec!["COMP300001", "COMP400001"]);
+
+        // The last page can be partly full, and there's no page past it.
+        assert_eq!(page_count(5, 2), 3);
+        assert_eq!(page_count(4, 2), 2);
+        assert_eq!(page_count(0, 2), 0);
+        assert!(args("--page 0 computer").is_err());
+    }
+
+    #[test]
+    fn text_is_sent_without_permission_to_embed() {
+        let valid = embed("COMP-1000".to_owned());
+
+        assert!(!embeddable(&valid, Permissions::SEND_MESSAGES | Permissions::ATTACH_FILES));
+        assert!(embeddable(&valid, Permissions::SEND_MESSAGES | Permissions::EMBED_LINKS));
+    }
+
+    #[test]
+    fn index_filter_comes_from_the_config() {
+        let filter = index_filter(&config::Config {
+            index_terms: Some(vec!["20185".to_owned()]),
+            index_subjects: Some(vec!["comp".to_owned(), "Math".to_owned()]),
+            ..config::Config::default()
+        });
+
+        assert_eq!(filter.terms, vec!["20185"]);
+        assert_eq!(filter.subjects, vec!["COMP", "MATH"]);
+
+        let everything = index_filter(&config::Config::default());
+        assert!(everything.terms.is_empty() && everything.subjects.is_empty());
+    }
+
+    #[test]
+    fn term_without_a_query_asks_for_one() {
+        for line in &["-s f18", "-s f18   ", "-s f18 -o", "-s w18-f18"] {
+            let error = args(line).err().expect(line);
+            assert!(error.starts_with("A search query is missing, like `~course -s f18 graph theory`."), "{}", line);
+        }
+
+        // A subject can be browsed without one.
+        assert!(args("-s f18 --subject comp").is_ok());
+    }
+
+    #[test]
+    fn send_failures_are_classified() {
+        use std::io;
+
+        assert_eq!(SendFailure::of_status(StatusCode::Forbidden), SendFailure::Permission);
+        assert_eq!(SendFailure::of_status(StatusCode::TooManyRequests), SendFailure::Transient);
+        assert_eq!(SendFailure::of_status(StatusCode::BadGateway), SendFailure::Transient);
+        assert_eq!(SendFailure::of_status(StatusCode::BadRequest), SendFailure::Invalid);
+        assert_eq!(SendFailure::of_status(StatusCode::NotModified), SendFailure::Other);
+
+        assert_eq!(SendFailure::of(&SerenityError::Model(ModelError::InvalidPermissions(Permissions::EMBED_LINKS))), SendFailure::Permission);
+        assert_eq!(SendFailure::of(&SerenityError::Model(ModelError::EmbedTooLarge(7000))), SendFailure::Invalid);
+        assert_eq!(SendFailure::of(&SerenityError::Model(ModelError::MessageTooLong(3000))), SendFailure::Invalid);
+        assert_eq!(SendFailure::of(&SerenityError::ExceededLimit("content".to_owned(), 2000)), SendFailure::Invalid);
+        assert_eq!(SendFailure::of(&SerenityError::Io(io::Error::new(io::ErrorKind::ConnectionReset, "reset"))), SendFailure::Transient);
+        assert_eq!(SendFailure::of(&SerenityError::Other("unknown")), SendFailure::Other);
+    }
+
+    #[test]
+    fn portal_comes_from_the_config() {
+        let defaults = uwin::Portal::default();
+
+        let portal = portal(&config::Config {
+            search_url: Some("http://localhost:8000/search".to_owned()),
+            directory_search_url: Some("http://localhost:8000/directory".to_owned()),
+            scrape_retries: Some(0),
+            scrape_timeout_secs: Some(5),
+            ..config::Config::default()
+        });
+
+        assert_eq!(portal.search_url, "http://localhost:8000/search");
+        assert_eq!(portal.directory_url, defaults.directory_url);
+        assert_eq!(portal.directory_search_url, "http://localhost:8000/directory");
+        assert_eq!((portal.retries, portal.retry_delay_ms, portal.timeout_secs), (0, defaults.retry_delay_ms, 5));
     }
 }
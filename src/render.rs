@@ -0,0 +1,1129 @@
+use std::{ cmp, iter };
+
+use itertools::Itertools;
+use serenity::builder::CreateEmbed;
+use typemap::Key;
+
+use feedback::Feedback;
+use chrono::{ DateTime, Local, Weekday };
+
+use uwin::{ self, Course, CoursePreview, FieldStats, PrereqTree, Professor, TermCode };
+use { Command, EMBED_COLOR };
+
+/// Queries in the examples of `~course -h`, which may not find anything in
+/// every term.
+const STATIC_EXAMPLES: &[&str] = &["60100", "graph theory", "-s f18 graph theory", "programming --exclude lab"];
+
+/// Discord's limits for the parts of an embed in characters.
+const TITLE_LIMIT: usize = 256;
+const DESCRIPTION_LIMIT: usize = 2048;
+const FIELD_NAME_LIMIT: usize = 256;
+const FIELD_VALUE_LIMIT: usize = 1024;
+const FIELD_COUNT_LIMIT: usize = 25;
+const FOOTER_LIMIT: usize = 2048;
+const EMBED_LIMIT: usize = 6000;
+/// Characters of a single item of a list in a field, like one prerequisite.
+const ITEM_LIMIT: usize = 300;
+/// Discord's limit for the content of a message in characters.
+const MESSAGE_LIMIT: usize = 2000;
+
+/// How much the bot shows at once, kept within Discord's limits.
+#[derive(Clone, Copy)]
+pub struct Limits {
+    /// Characters of a single course's description.
+    pub description: usize,
+    /// Characters of each field of a single course.
+    pub field: usize,
+    /// Results listed at once for a search.
+    pub list: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            description: 200,
+            field: FIELD_VALUE_LIMIT,
+            list: 10,
+        }
+    }
+}
+
+impl Key for Limits {
+    type Value = Limits;
+}
+
+impl Limits {
+    /// Bring limits that were set too high back within Discord's limits.
+    pub fn clamped(self) -> Self {
+        Limits {
+            // Leave room for the delivery badge and the trailing ellipsis.
+            description: cmp::min(self.description, DESCRIPTION_LIMIT - 48),
+            field: cmp::min(self.field, FIELD_VALUE_LIMIT),
+            list: cmp::max(self.list, 1),
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Embed is invalid: {}", _0)]
+pub struct EmbedError(String);
+
+/// A description of an embed that can be built without talking to Discord.
+pub struct Embed {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub fields: Vec<(&'static str, String, bool)>,
+    pub footer: Option<String>,
+}
+
+impl Embed {
+    /// Fill in a serenity embed builder.
+    pub fn build(&self, e: CreateEmbed) -> CreateEmbed {
+        let mut e = e.color(EMBED_COLOR);
+
+        if let Some(ref title) = self.title {
+            e = e.title(title);
+        }
+
+        if let Some(ref description) = self.description {
+            e = e.description(description);
+        }
+
+        if let Some(ref footer) = self.footer {
+            e = e.footer(|f| f.text(footer));
+        }
+
+        e.fields(self.fields.iter().cloned())
+    }
+
+    /// Check the embed against Discord's limits, naming the part at fault.
+    pub fn validate(&self) -> Result<(), EmbedError> {
+        let len = |s: &str| s.chars().count();
+
+        let title = self.title.as_ref().map_or(0, |s| len(s));
+        if title > TITLE_LIMIT {
+            return Err(EmbedError(format!("title is {} characters", title)));
+        }
+
+        let description = self.description.as_ref().map_or(0, |s| len(s));
+        if description > DESCRIPTION_LIMIT {
+            return Err(EmbedError(format!("description is {} characters", description)));
+        }
+
+        if self.fields.len() > FIELD_COUNT_LIMIT {
+            return Err(EmbedError(format!("{} fields", self.fields.len())));
+        }
+
+        let footer = self.footer.as_ref().map_or(0, |s| len(s));
+        if footer > FOOTER_LIMIT {
+            return Err(EmbedError(format!("footer is {} characters", footer)));
+        }
+
+        let mut total = title + description + footer;
+
+        for &(name, ref value, _) in &self.fields {
+            if len(name) > FIELD_NAME_LIMIT || len(value) > FIELD_VALUE_LIMIT {
+                return Err(EmbedError(format!("field \"{}\" is {} characters", name, len(value))));
+            }
+
+            total += len(name) + len(value);
+        }
+
+        if total > EMBED_LIMIT {
+            return Err(EmbedError(format!("embed is {} characters", total)));
+        }
+
+        Ok(())
+    }
+
+    /// A plain text version of the embed that fits in a single message.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        if let Some(ref title) = self.title {
+            text.push_str(&format!("**{}**\n", title));
+        }
+
+        if let Some(ref description) = self.description {
+            text.push_str(description.trim_end());
+            text.push('\n');
+        }
+
+        for &(name, ref value, _) in &self.fields {
+            text.push_str(&format!("\n**{}**\n{}\n", name, value));
+        }
+
+        if let Some(ref footer) = self.footer {
+            text.push_str(&format!("\n*{}*\n", footer));
+        }
+
+        if text.chars().count() > MESSAGE_LIMIT {
+            text = text.chars()
+                .take(MESSAGE_LIMIT - 3)
+                .chain("...".chars())
+                .collect();
+        }
+
+        text
+    }
+}
+
+/// The commands the bot responds to.
+pub fn help(commands: &[&Command], prefix: &str) -> Embed {
+    let description = commands.iter()
+        .format_with("\n", |command, f| {
+            f(&format_args!("`{}{}` {}", prefix, command.usage, command.description))
+        })
+        .to_string();
+
+    Embed {
+        title: Some("Commands".to_owned()),
+        description: Some(description),
+        fields: vec![],
+        footer: None,
+    }
+}
+
+/// Recently added features tagged with the version they're in, with the
+/// usage of the command each is about if there is one.
+pub fn whats_new(entries: &[(&str, &str, &str)], prefix: &str) -> Embed {
+    let description = entries.iter()
+        .format_with("\n", |&(version, usage, feature), f| {
+            if usage.is_empty() {
+                f(&format_args!("`{}` {}", version, feature))
+            } else {
+                f(&format_args!("`{}` `{}{}` {}", version, prefix, usage, feature))
+            }
+        })
+        .to_string();
+
+    Embed {
+        title: Some("What's New".to_owned()),
+        description: Some(description),
+        fields: vec![],
+        footer: None,
+    }
+}
+
+/// Help for the `~course` command.
+pub fn course_help(prefix: &str) -> Embed {
+    Embed {
+        title: None,
+        description: None,
+        fields: vec![
+            ("Usage", format!("{}course [OPTION] <QUERY>", prefix), false),
+            ("Options", "`-h` View the command help.\n`-s <[fsw]XX>` Select a semester where f (Fall) s (Summer) w (Winter) and XX is the year, or `current` or `next` for the indexed semester going on now or after it\n`--term-range <[fsw]XX-[fsw]XX>` Search every semester in a range\n`--page <N>` Show a later page of results\n`-n <COUNT>` Show from 1 to 25 results at once\n`--sections` List every section instead of each course once\n`--count` Only show how many courses match\n`-f`, `--fresh` Check the portal for the latest seats instead of reusing a recent lookup".to_owned(), false),
+            ("Filters", "`--breadth <CATEGORY>` Only show courses counting towards a breadth requirement\n`--instructor <NAME>` Only show courses taught by an instructor\n`--note <TEXT>` Only show courses with a note mentioning the text\n`--eligible <YEAR>` Only show courses open to students in a year of study\n`-o`, `--open` Only show sections with open seats as of the last reindex\n`--enrollable` Only show sections you could register for right now\n`--delivery <MODE>` Only show sections delivered a certain way (in person, online, hybrid, hyflex)\n`--subject <SUBJECT>` Only show courses in a subject, or every one of them without a query\n`--exclude <WORD>` Leave out courses mentioning a word, can be given more than once\n`--min-score <SCORE>` Leave out courses that match the query poorly".to_owned(), false),
+            ("Fields", format!("`subject:<SUBJECT>`, `number:<NUMBER>`, and `title:<WORD>` in a query only match courses in the subject, with a number starting with it, or with the word in the title, like `{}course subject:COMP graph`", prefix), false),
+            ("Examples", format!("{}\n`{}course --help-examples` shows examples from this term", static_examples(prefix), prefix), false),
+        ],
+        footer: None,
+    }
+}
+
+/// Examples of `~course` that find real courses, or the usual examples if
+/// there are none.
+pub fn course_examples(courses: &[CoursePreview], prefix: &str) -> Embed {
+    let examples = if courses.is_empty() {
+        static_examples(prefix)
+    } else {
+        courses.iter()
+            .map(|course| {
+                format!("{0}course {1}\n{0}course {2}", prefix, &course.code[..course.code.len().min(7)], course.title.to_lowercase())
+            })
+            .join("\n")
+    };
+
+    Embed {
+        title: Some("Examples".to_owned()),
+        description: Some(examples),
+        fields: vec![],
+        footer: None,
+    }
+}
+
+/// The usual examples of `~course` written with the prefix.
+fn static_examples(prefix: &str) -> String {
+    STATIC_EXAMPLES.iter()
+        .map(|query| format!("{}course {}", prefix, query))
+        .join("\n")
+}
+
+/// Full information for a single course.
+pub fn course(course: Course, limits: &Limits) -> Embed {
+    let Course {
+        title,
+        description,
+        note,
+        restrictions,
+        meets,
+        instructors,
+        availability,
+        prereqs,
+        antireqs,
+        materials,
+        breadth,
+        delivery,
+        exams,
+        ..
+    } = course;
+
+    // The delivery mode changes whether a section works for someone at all,
+    // so it goes before anything else.
+    let badge = delivery
+        .map(|mode| format!("**`{}`**\n\n", mode))
+        .unwrap_or_default();
+
+    let description = format!("{}{}\n\n", badge, truncate_words(&description, limits.description));
+
+    let mut fields = vec![];
+
+    if let Some(note) = note {
+        fields.push(("Note", note, false));
+    }
+
+    if !restrictions.is_empty() {
+        fields.push(("Restrictions", join_items(&restrictions, limits.field), false));
+    }
+
+    fields.push(("Meets", meets.into_iter().join("\n"), false));
+
+    if !instructors.is_empty() {
+        // Links are kept whole, since a cut one shows as broken markdown.
+        let instructors = instructors
+            .into_iter()
+            .map(|ins| match ins.profile_link() {
+                Some(ref url) if ins.name.len() + url.len() + 4 <= ITEM_LIMIT => format!("[{}]({})", ins.name, url),
+                _ => ins.name,
+            })
+            .collect::<Vec<_>>();
+
+        fields.push(("Instructors", join_items(&instructors, limits.field), true));
+    }
+
+    fields.push(("Availability", availability.to_string(), true));
+
+    if !prereqs.is_empty() {
+        fields.push(("Prerequisites", join_items(&prereqs, limits.field), false));
+    }
+
+    if !antireqs.is_empty() {
+        fields.push(("Antirequisites", join_items(&antireqs, limits.field), false));
+    }
+
+    if !materials.is_empty() {
+        fields.push(("Materials", join_items(&materials, limits.field), false));
+    }
+
+    if !breadth.is_empty() {
+        fields.push(("Breadth", breadth.join(", "), false));
+    }
+
+    if !exams.is_empty() {
+        let exams = exams
+            .into_iter()
+            .map(|ex| {
+                let mut line = format!("**{}**", ex.ty);
+
+                if let Some(date) = ex.date_string() {
+                    line.push_str(&format!(" on {}", date));
+                }
+
+                if let Some(time) = ex.time_string() {
+                    line.push_str(&format!(" at {}", time));
+                }
+
+                if let Some(building) = ex.building {
+                    line.push_str(&format!(" in {}", building));
+                }
+
+                if let Some(room) = ex.room {
+                    line.push_str(&format!(" room {}", room));
+                }
+
+                line
+            })
+            .collect::<Vec<_>>();
+
+        fields.push(("Exams", join_items(&exams, limits.field), false));
+    }
+
+    let title = truncate(title, TITLE_LIMIT);
+
+    // Fields that would take the embed past Discord's total limit are left
+    // out rather than having the whole course fail to send.
+    let mut total = title.chars().count() + description.chars().count();
+
+    let fields = fields.into_iter()
+        .map(|(name, value, inline)| (name, truncate(value, limits.field), inline))
+        .take(FIELD_COUNT_LIMIT)
+        .take_while(|&(name, ref value, _)| {
+            total += name.chars().count() + value.chars().count();
+            total <= EMBED_LIMIT
+        })
+        .collect();
+
+    Embed {
+        title: Some(title),
+        description: Some(description),
+        fields: fields,
+        footer: None,
+    }
+}
+
+/// Join the items of a list one per line within a number of characters.
+/// Long items are cut short and items that don't fit are counted in a note
+/// at the end instead.
+fn join_items(items: &[String], len: usize) -> String {
+    let mut joined = String::new();
+
+    for (i, item) in items.iter().enumerate() {
+        let item = truncate(item.clone(), cmp::min(ITEM_LIMIT, len));
+        let left = items.len() - i - 1;
+
+        // Room for this item, and for the note if any are left after it.
+        let note = if left > 0 { format!("\n…and {} more", left).chars().count() } else { 0 };
+        let sep = if joined.is_empty() { 0 } else { 1 };
+
+        if joined.chars().count() + sep + item.chars().count() + note > len && !joined.is_empty() {
+            joined.push_str(&format!("\n…and {} more", items.len() - i));
+            break;
+        }
+
+        if sep > 0 {
+            joined.push('\n');
+        }
+
+        joined.push_str(&item);
+    }
+
+    joined
+}
+
+/// Cut text down to a number of characters, ending with an ellipsis if
+/// anything was cut.
+fn truncate(text: String, len: usize) -> String {
+    if text.chars().count() <= len {
+        return text;
+    }
+
+    text.chars()
+        .take(len.saturating_sub(1))
+        .chain(iter::once('…'))
+        .collect()
+}
+
+/// Cut text down to at most a number of characters at the end of a word,
+/// ending with "..." if anything was cut. A first word that's too long on
+/// its own is cut in the middle.
+fn truncate_words(text: &str, len: usize) -> String {
+    let text = text.trim();
+
+    if text.chars().count() <= len {
+        return text.to_owned();
+    }
+
+    // The byte index after the character the cut can't go past, and after
+    // the next one so a word ending right at the limit is kept whole.
+    let end = text.char_indices()
+        .nth(len)
+        .map_or(text.len(), |(i, _)| i);
+    let next = text[end..].chars()
+        .next()
+        .map_or(end, |c| end + c.len_utf8());
+
+    let cut = match text[..next].rfind(char::is_whitespace) {
+        Some(i) if i > 0 => text[..i].trim_end(),
+        _ => &text[..end],
+    };
+
+    format!("{}...", cut)
+}
+
+/// The antirequisites of a course, which can't be taken for credit along
+/// with it.
+pub fn antireqs(course: &Course) -> Embed {
+    let description = if course.antireqs.is_empty() {
+        "No antirequisites are listed.".to_owned()
+    } else {
+        course.antireqs.join("\n")
+    };
+
+    Embed {
+        title: Some(format!("Antirequisites for {}", course.title)),
+        description: Some(description),
+        fields: vec![],
+        footer: None,
+    }
+}
+
+/// A list of course search results.
+pub fn course_list(courses: &[CoursePreview]) -> Embed {
+    let lines = courses.iter()
+        .map(|course| (course_line(course), true))
+        .collect::<Vec<_>>();
+
+    Embed {
+        title: Some(format!("Top {} Results", courses.len())),
+        description: Some(join_limited(&lines)),
+        fields: vec![],
+        footer: None,
+    }
+}
+
+/// The title of a page of search results.
+pub fn page_title(offset: usize, len: usize, total: usize) -> String {
+    format!("Results {}-{} of {}", offset + 1, offset + len, total)
+}
+
+/// Search results for a range of terms, grouped by term.
+pub fn term_range(results: &[(String, Vec<CoursePreview>)]) -> Embed {
+    let mut lines = vec![];
+
+    for &(ref term, ref courses) in results {
+        if courses.is_empty() {
+            continue;
+        }
+
+        if !lines.is_empty() {
+            lines.push((String::new(), false));
+        }
+
+        let name = term_name(term).unwrap_or_else(|| term.clone());
+        lines.push((format!("**{}**", name), false));
+        lines.extend(courses.iter().map(|course| (course_line(course), true)));
+    }
+
+    Embed {
+        title: Some("Results by Term".to_owned()),
+        description: Some(join_limited(&lines)),
+        fields: vec![],
+        footer: None,
+    }
+}
+
+/// The courses taught by each instructor matching a name.
+pub fn professors(name: &str, professors: &[Professor]) -> Embed {
+    let mut lines = vec![];
+
+    for professor in professors {
+        if !lines.is_empty() {
+            lines.push((String::new(), false));
+        }
+
+        lines.push((format!("**{}**", professor.name), false));
+        lines.extend(professor.courses.iter().map(|course| (course_line(course), true)));
+    }
+
+    Embed {
+        title: Some(format!("Courses Taught by {}", name)),
+        description: Some(join_limited(&lines)),
+        fields: vec![],
+        footer: None,
+    }
+}
+
+/// The prerequisites of a course as an indented list, with the courses
+/// each one names nested under it.
+pub fn prereq_tree(tree: &PrereqTree) -> Embed {
+    fn walk(tree: &PrereqTree, depth: usize, lines: &mut Vec<(String, bool)>) {
+        // Discord trims leading spaces, but not em spaces.
+        let indent = |depth| iter::repeat('\u{2003}').take(depth).collect::<String>();
+
+        for prereq in &tree.prereqs {
+            lines.push((format!("{}• {}", indent(depth), prereq.text), true));
+
+            for course in &prereq.courses {
+                lines.push((format!("{}↳ `{}` {}", indent(depth + 1), course.code, course.title), false));
+                walk(course, depth + 2, lines);
+            }
+        }
+    }
+
+    let mut lines = vec![];
+    walk(tree, 0, &mut lines);
+
+    let description = if lines.is_empty() {
+        "No prerequisites are listed.".to_owned()
+    } else {
+        join_limited(&lines)
+    };
+
+    Embed {
+        title: Some(format!("Prerequisites for {} {}", tree.code, tree.title)),
+        description: Some(description),
+        fields: vec![],
+        footer: None,
+    }
+}
+
+/// Two courses side by side, with what differs between them listed first.
+pub fn compare(first: &Course, second: &Course) -> Embed {
+    let aspects = |course: &Course| {
+        let or_none = |text: String| if text.is_empty() { "None".to_owned() } else { text };
+
+        vec![
+            ("Meets", or_none(course.meets.iter().join("\n"))),
+            ("Availability", course.availability.to_string()),
+            ("Instructors", or_none(course.instructors.iter().map(|ins| &ins.name).join("\n"))),
+            ("Course Value", or_none(course.course_value.clone())),
+            ("Campus", or_none(course.campus.clone())),
+        ]
+    };
+
+    let (first_aspects, second_aspects) = (aspects(first), aspects(second));
+
+    let differs = first_aspects.iter()
+        .zip(&second_aspects)
+        .filter(|&(a, b)| a.1 != b.1)
+        .map(|(a, _)| a.0)
+        .join(", ");
+
+    let description = if differs.is_empty() {
+        "These sections are the same in every way shown.".to_owned()
+    } else {
+        format!("Differs in {}.", differs)
+    };
+
+    // Inline fields go three to a row, so a blank third field keeps each
+    // course in its own column.
+    let blank = || ("\u{200b}", "\u{200b}".to_owned(), true);
+
+    let mut fields = vec![
+        ("Course", truncate(format!("`{}` {}", first.code, first.title), FIELD_VALUE_LIMIT), true),
+        ("Course", truncate(format!("`{}` {}", second.code, second.title), FIELD_VALUE_LIMIT), true),
+        blank(),
+    ];
+
+    for (a, b) in first_aspects.into_iter().zip(second_aspects) {
+        fields.push((a.0, truncate(a.1, FIELD_VALUE_LIMIT), true));
+        fields.push((b.0, truncate(b.1, FIELD_VALUE_LIMIT), true));
+        fields.push(blank());
+    }
+
+    Embed {
+        title: Some(truncate(format!("{} vs {}", first.code, second.code), TITLE_LIMIT)),
+        description: Some(description),
+        fields: fields,
+        footer: None,
+    }
+}
+
+/// A weekly grid of when courses meet, with the pairs of courses given by
+/// `uwin::find_conflicts` called out.
+pub fn schedule(courses: &[Course], conflicts: &[(usize, usize)]) -> Embed {
+    let days = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun];
+
+    // Days nothing meets on are left out.
+    let grid = days.iter()
+        .filter_map(|day| {
+            let blocks = courses.iter()
+                .flat_map(|course| {
+                    course.meets.iter()
+                        .flat_map(|meet| meet.times.iter())
+                        .filter(move |time| time.days.contains(day))
+                        .map(move |time| (time.start, time.end, &course.code))
+                })
+                .sorted();
+
+            if blocks.is_empty() {
+                return None;
+            }
+
+            let blocks = blocks.into_iter()
+                .map(|(start, end, code)| format!("{}-{} {}", start.format("%H:%M"), end.format("%H:%M"), code))
+                .join("  ");
+
+            Some(format!("{:?} {}", day, blocks))
+        })
+        .join("\n");
+
+    let mut fields = vec![];
+
+    if !grid.is_empty() {
+        fields.push(("Week", truncate(format!("```\n{}\n```", grid), FIELD_VALUE_LIMIT), false));
+    }
+
+    let conflicts = if conflicts.is_empty() {
+        "No conflicts.".to_owned()
+    } else {
+        conflicts.iter()
+            .map(|&(i, j)| format!("⚠️ `{}` and `{}` overlap", courses[i].code, courses[j].code))
+            .join("\n")
+    };
+
+    fields.push(("Conflicts", truncate(conflicts, FIELD_VALUE_LIMIT), false));
+
+    let unscheduled = courses.iter()
+        .filter(|course| course.meets.iter().all(|meet| meet.times.is_empty()))
+        .map(|course| format!("`{}` {}", course.code, course.title))
+        .join("\n");
+
+    if !unscheduled.is_empty() {
+        fields.push(("Async / No Conflict", truncate(unscheduled, FIELD_VALUE_LIMIT), false));
+    }
+
+    Embed {
+        title: Some("Weekly Schedule".to_owned()),
+        description: None,
+        fields: fields,
+        footer: None,
+    }
+}
+
+/// Column headings of a course comparison table.
+const MATRIX_COLUMNS: &[&str] = &["Code", "Title", "Meets", "Availability", "Instructors", "Prerequisites", "Exam Date"];
+
+/// The cells of a course comparison row, or the reason the course is
+/// missing in the title column.
+fn matrix_row(code: &str, course: &Result<Course, String>) -> Vec<String> {
+    match *course {
+        Ok(ref course) => vec![
+            course.code.clone(),
+            course.title.clone(),
+            course.meets.iter().join("; "),
+            course.availability.to_string(),
+            course.instructors.iter().map(|ins| &ins.name).join("; "),
+            course.prereqs.join("; "),
+            course.exams.iter()
+                .filter_map(|ex| ex.date_string())
+                .join("; "),
+        ],
+        Err(ref reason) => {
+            let mut row = vec![code.to_owned(), format!("({})", reason)];
+            row.resize(MATRIX_COLUMNS.len(), String::new());
+            row
+        }
+    }
+}
+
+/// A Markdown table comparing courses.
+pub fn matrix_markdown(rows: &[(String, Result<Course, String>)]) -> String {
+    let line = |cells: Vec<String>| {
+        let cells = cells.into_iter()
+            .map(|cell| cell.replace('|', "\\|"))
+            .join(" | ");
+
+        format!("| {} |\n", cells)
+    };
+
+    let mut table = line(MATRIX_COLUMNS.iter().map(|s| s.to_string()).collect());
+    table.push_str(&line(MATRIX_COLUMNS.iter().map(|_| "---".to_owned()).collect()));
+
+    for &(ref code, ref course) in rows {
+        table.push_str(&line(matrix_row(code, course)));
+    }
+
+    table
+}
+
+/// A CSV table comparing courses.
+pub fn matrix_csv(rows: &[(String, Result<Course, String>)]) -> String {
+    let line = |cells: Vec<String>| {
+        let cells = cells.into_iter()
+            .map(|cell| {
+                if cell.contains(|c| c == ',' || c == '"' || c == '\n') {
+                    format!("\"{}\"", cell.replace('"', "\"\""))
+                } else {
+                    cell
+                }
+            })
+            .join(",");
+
+        format!("{}\r\n", cells)
+    };
+
+    let mut table = line(MATRIX_COLUMNS.iter().map(|s| s.to_string()).collect());
+
+    for &(ref code, ref course) in rows {
+        table.push_str(&line(matrix_row(code, course)));
+    }
+
+    table
+}
+
+/// A single line of a list of search results, with the campus since the
+/// same course often runs at more than one.
+fn course_line(course: &CoursePreview) -> String {
+    // A course standing for several sections is shown by its own code, and
+    // the campus of one section may not be the campus of the rest.
+    if course.sections > 1 {
+        return format!("`{}` {} ({} sections)", uwin::base_code(&course.code), course.title, course.sections);
+    }
+
+    match course.campus {
+        Some(ref campus) if !campus.is_empty() => format!("`{}` {} — {}", course.code, course.title, truncate(campus.clone(), 24)),
+        _ => format!("`{}` {}", course.code, course.title),
+    }
+}
+
+/// Join lines into a description that fits within Discord's limit. Lines that
+/// don't fit are left out with a note saying how many results were dropped,
+/// where only the lines marked `true` count as results.
+fn join_limited(lines: &[(String, bool)]) -> String {
+    let joined = lines.iter()
+        .map(|&(ref line, _)| line)
+        .join("\n");
+
+    if joined.chars().count() <= DESCRIPTION_LIMIT {
+        return joined;
+    }
+
+    // Leave room for the note about what was left out.
+    let limit = DESCRIPTION_LIMIT - 20;
+
+    let mut text = String::new();
+    let mut len = 0;
+
+    for (i, &(ref line, _)) in lines.iter().enumerate() {
+        let line_len = line.chars().count() + 1;
+
+        if len + line_len > limit {
+            let more = lines[i..].iter()
+                .filter(|&&(_, is_result)| is_result)
+                .count();
+
+            text.push_str(&format!("…and {} more", more));
+            break;
+        }
+
+        text.push_str(line);
+        text.push('\n');
+        len += line_len;
+    }
+
+    text
+}
+
+/// Subject codes and names laid out in columns.
+pub fn subjects(subjects: &[(String, String)], prefix: &str) -> Embed {
+    let mut columns: Vec<String> = vec![];
+    let mut left_out = 0;
+
+    for &(ref code, ref name) in subjects {
+        let line = format!("`{}` {}", code, name);
+
+        let fits = columns.last()
+            .map_or(false, |column| column.chars().count() + line.chars().count() < FIELD_VALUE_LIMIT);
+
+        if fits {
+            let column = columns.last_mut().unwrap();
+            column.push('\n');
+            column.push_str(&line);
+            continue;
+        }
+
+        // Leave room for the rest of the embed.
+        let len = columns.iter().map(|column| column.chars().count()).sum::<usize>();
+
+        if columns.len() < FIELD_COUNT_LIMIT && len + FIELD_VALUE_LIMIT < EMBED_LIMIT - 100 {
+            columns.push(line);
+        } else {
+            left_out += 1;
+        }
+    }
+
+    let fields = columns.into_iter()
+        .enumerate()
+        .map(|(i, column)| (if i == 0 { "Subjects" } else { "\u{200b}" }, column, true))
+        .collect();
+
+    Embed {
+        title: None,
+        description: None,
+        fields: fields,
+        footer: if left_out > 0 { Some(format!("{} more subjects, narrow them down with `{}subjects <TEXT>`.", left_out, prefix)) } else { None },
+    }
+}
+
+/// The list of indexed terms, marking the term searched by default.
+pub fn terms(terms: &[String], current: &str) -> Embed {
+    let description = if terms.is_empty() {
+        "No terms are indexed.".to_owned()
+    } else {
+        terms.iter()
+            .rev()
+            .format_with("\n", |term, f| {
+                let name = term_name(term).unwrap_or_else(|| term.clone());
+
+                if term == current {
+                    f(&format_args!("► **{}**", name))
+                } else {
+                    f(&format_args!("{}", name))
+                }
+            })
+            .to_string()
+    };
+
+    Embed {
+        title: Some("Terms".to_owned()),
+        description: Some(description),
+        fields: vec![],
+        footer: None,
+    }
+}
+
+/// Turns a term code like `20185` into a name like Fall 2018 (`f18`).
+fn term_name(code: &str) -> Option<String> {
+    TermCode::from_code(code)
+        .map(|term| format!("{} (`{}`)", term, term.short()))
+}
+
+/// Feedback that hasn't been resolved yet.
+pub fn feedback_list(items: &[&Feedback]) -> Embed {
+    let lines = items.iter()
+        .map(|item| {
+            let summary = item.text.chars().take(80).collect::<String>();
+            (format!("`#{}` {}: {}", item.id, item.author, summary), true)
+        })
+        .collect::<Vec<_>>();
+
+    let description = if lines.is_empty() {
+        "No open feedback.".to_owned()
+    } else {
+        join_limited(&lines)
+    };
+
+    Embed {
+        title: Some("Open Feedback".to_owned()),
+        description: Some(description),
+        fields: vec![],
+        footer: None,
+    }
+}
+
+/// A single feedback report.
+pub fn feedback(item: &Feedback) -> Embed {
+    let status = if item.resolved { "Resolved" } else { "Open" };
+
+    Embed {
+        title: Some(format!("Feedback #{}", item.id)),
+        description: Some(item.text.clone()),
+        fields: vec![
+            ("From", item.author.clone(), true),
+            ("Status", status.to_owned(), true),
+        ],
+        footer: None,
+    }
+}
+
+/// What's in the course index and how old it is, for `status`.
+pub struct IndexStatus {
+    /// Courses in the index across every term.
+    pub courses: u64,
+    pub terms: Vec<String>,
+    pub written_at: Option<DateTime<Local>>,
+    pub subjects: Option<usize>,
+}
+
+/// Status of the course index.
+pub fn status(status: &IndexStatus, stats: Option<&FieldStats>) -> Embed {
+    let stats = match stats {
+        Some(stats) => stats.to_string(),
+        None => "The index was not rebuilt since the bot started.".to_owned(),
+    };
+
+    let terms = if status.terms.is_empty() {
+        "None".to_owned()
+    } else {
+        status.terms.iter()
+            .map(|code| term_name(code).unwrap_or_else(|| code.clone()))
+            .join("\n")
+    };
+
+    let written_at = status.written_at
+        .map_or("Unknown".to_owned(), |time| time.format("%b %-d, %Y %-I:%M %p").to_string());
+
+    let subjects = status.subjects.map_or("Unknown".to_owned(), |count| count.to_string());
+
+    Embed {
+        title: Some("Status".to_owned()),
+        description: None,
+        fields: vec![
+            ("Courses", status.courses.to_string(), true),
+            ("Subjects", subjects, true),
+            ("Last Updated", written_at, true),
+            ("Terms", terms, false),
+            ("Missing Fields", stats, false),
+        ],
+        footer: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uwin::{ Exam, tests::{ course, instructor, preview, scraper_with } };
+
+    #[test]
+    fn current_term_is_flagged() {
+        let codes = vec!["20181".to_owned(), "20182".to_owned(), "20185".to_owned()];
+        let description = terms(&codes, "20182").description.unwrap();
+
+        assert_eq!(description, "Fall 2018 (`f18`)\n► **Summer 2018 (`s18`)**\nWinter 2018 (`w18`)");
+    }
+
+    #[test]
+    fn unknown_current_term_flags_nothing() {
+        let codes = vec!["20181".to_owned(), "20185".to_owned()];
+        let description = terms(&codes, "20191").description.unwrap();
+
+        assert!(!description.contains('►'));
+    }
+
+    #[test]
+    fn short_description_is_kept() {
+        assert_eq!(truncate_words("  An introduction to computer science. ", 200), "An introduction to computer science.");
+    }
+
+    #[test]
+    fn exactly_200_description_is_kept() {
+        let description = format!("{}final", "word ".repeat(39));
+        assert_eq!(description.chars().count(), 200);
+
+        assert_eq!(truncate_words(&description, 200), description);
+        assert_eq!(truncate_words(&format!("{}!", description), 200), format!("{}...", "word ".repeat(39).trim()));
+    }
+
+    #[test]
+    fn long_description_is_cut_at_a_word() {
+        let description = "computing ".repeat(30);
+        let cut = truncate_words(&description, 200);
+
+        assert!(cut.ends_with("computing..."));
+        assert!(cut.chars().count() <= 203);
+
+        // A single word longer than the limit is cut in the middle, at a
+        // character boundary.
+        let cut = truncate_words(&"é".repeat(250), 200);
+        assert_eq!(cut, format!("{}...", "é".repeat(200)));
+    }
+
+    #[test]
+    fn pathological_course_stays_within_limits() {
+        let long = |word: &str, n| vec![word; n].join(" ");
+
+        let mut course = course("COMP100001");
+        course.title = long("Title", 100);
+        course.description = long("Description", 1000);
+        course.note = Some(long("Note", 500));
+        course.restrictions = (0..100).map(|_| long("Restricted", 50)).collect();
+        course.prereqs = (0..200).map(|i| format!("COMP-{} {}", i, long("or", 300))).collect();
+        course.antireqs = (0..200).map(|i| format!("COMP-{}", i)).collect();
+        course.materials = (0..50).map(|_| long("Textbook", 100)).collect();
+        course.breadth = (0..100).map(|_| "Social Sciences".to_owned()).collect();
+        course.instructors = (0..100).map(|i| instructor(&format!("{}{}@uwindsor.ca", long("x", 100), i))).collect();
+        course.exams = (0..100)
+            .map(|_| Exam {
+                ty: long("Final", 100),
+                slot: None,
+                date: None,
+                date_text: Some(long("Dec", 100)),
+                time: None,
+                time_text: Some(long("TBA", 100)),
+                building: Some(long("Erie", 100)),
+                room: Some(long("1120", 100)),
+                area: None,
+            })
+            .collect();
+
+        let embed = super::course(course, &Limits::default());
+
+        embed.validate().unwrap();
+
+        for &(_, ref value, _) in &embed.fields {
+            assert!(value.chars().count() <= FIELD_VALUE_LIMIT);
+        }
+    }
+
+    #[test]
+    fn long_titles_are_cut_from_a_list() {
+        let scraper = scraper_with(&[]);
+
+        let courses = (0..25)
+            .map(|i| {
+                let mut course = preview(&scraper, &format!("COMP{}01", 1000 + i));
+                course.title = "Advanced Topics ".repeat(15);
+                course
+            })
+            .collect::<Vec<_>>();
+
+        let embed = course_list(&courses);
+        let description = embed.description.clone().unwrap();
+
+        embed.validate().unwrap();
+        assert!(description.chars().count() <= DESCRIPTION_LIMIT);
+
+        let listed = description.lines().count() - 1;
+        assert!(description.ends_with(&format!("…and {} more", 25 - listed)));
+    }
+
+    #[test]
+    fn matrix_rows_mark_courses_that_failed() {
+        let mut scraped = course("COMP100001");
+        scraped.title = "Data, Logic | Proof".to_owned();
+        scraped.prereqs = vec!["COMP-1000".to_owned(), "MATH-1020".to_owned()];
+
+        let rows = vec![
+            ("COMP-1000".to_owned(), Ok(scraped)),
+            ("COMP-9999".to_owned(), Err("No such course".to_owned())),
+        ];
+
+        assert_eq!(matrix_csv(&rows), "Code,Title,Meets,Availability,Instructors,Prerequisites,Exam Date\r\n\
+            COMP100001,\"Data, Logic | Proof\",MWF 10:00 AM - 11:20 AM Erie Hall 1120,0 of 90,,COMP-1000; MATH-1020,\r\n\
+            COMP-9999,(No such course),,,,,\r\n");
+
+        assert_eq!(matrix_markdown(&rows), "| Code | Title | Meets | Availability | Instructors | Prerequisites | Exam Date |\n\
+            | --- | --- | --- | --- | --- | --- | --- |\n\
+            | COMP100001 | Data, Logic \\| Proof | MWF 10:00 AM - 11:20 AM Erie Hall 1120 | 0 of 90 |  | COMP-1000; MATH-1020 |  |\n\
+            | COMP-9999 | (No such course) |  |  |  |  |  |\n");
+    }
+
+    #[test]
+    fn each_limit_applies_on_its_own() {
+        let mut course = course("COMP100001");
+        course.description = "computing ".repeat(100);
+        course.prereqs = (0..100).map(|i| format!("COMP-{}", 1000 + i)).collect();
+
+        let field = |embed: &Embed, name| embed.fields.iter()
+            .find(|&&(field, _, _)| field == name)
+            .map(|&(_, ref value, _)| value.chars().count())
+            .unwrap();
+
+        let short_description = super::course(course.clone(), &Limits { description: 50, ..Limits::default() });
+        let short_fields = super::course(course, &Limits { field: 100, ..Limits::default() });
+
+        assert!(short_description.description.as_ref().unwrap().chars().count() < 60);
+        assert!(field(&short_description, "Prerequisites") > 100);
+
+        assert!(short_fields.description.as_ref().unwrap().chars().count() > 150);
+        assert!(field(&short_fields, "Prerequisites") <= 100);
+    }
+
+    #[test]
+    fn text_version_keeps_every_part_of_the_embed() {
+        let embed = Embed {
+            title: Some("COMP-1000 Key Concepts".to_owned()),
+            description: Some("An introduction.\n\n".to_owned()),
+            fields: vec![("Meets", "MW 10:00 AM".to_owned(), false), ("Availability", "5 of 30 open".to_owned(), true)],
+            footer: Some("Fall 2018".to_owned()),
+        };
+
+        assert_eq!(
+            embed.to_text(),
+            "**COMP-1000 Key Concepts**\nAn introduction.\n\n**Meets**\nMW 10:00 AM\n\n**Availability**\n5 of 30 open\n\n*Fall 2018*\n"
+        );
+    }
+
+    #[test]
+    fn examples_fall_back_to_the_usual_ones() {
+        let description = course_examples(&[], "~").description.unwrap();
+        assert_eq!(description, static_examples("~"));
+
+        let scraper = scraper_with(&[]);
+        let courses = vec![preview(&scraper, "COMP100001")];
+        let description = course_examples(&courses, "~").description.unwrap();
+
+        assert_eq!(description, "~course COMP100\n~course key concepts in computer science");
+    }
+}
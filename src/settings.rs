@@ -0,0 +1,81 @@
+use std::collections::{ HashMap, HashSet };
+use std::path::{ Path, PathBuf };
+
+use failure::Error;
+use typemap::Key;
+
+use json_store;
+
+const SETTINGS_PATH: &str = "./settings.json";
+
+/// Settings chosen by server admins, kept across restarts.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Settings {
+    guilds: HashMap<u64, GuildSettings>,
+    /// Whether user commands are turned off everywhere.
+    #[serde(default)]
+    maintenance: bool,
+    /// Where the settings are saved.
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct GuildSettings {
+    disabled: HashSet<String>,
+}
+
+impl Key for Settings {
+    type Value = Settings;
+}
+
+impl Settings {
+    /// Load the settings file, starting fresh if there isn't one yet.
+    pub fn load() -> Result<Settings, Error> {
+        Settings::load_from(Path::new(SETTINGS_PATH))
+    }
+
+    /// Load settings kept somewhere other than the usual file.
+    pub fn load_from(path: &Path) -> Result<Settings, Error> {
+        let mut loaded: Settings = json_store::load(path)?;
+        loaded.path = path.to_owned();
+        Ok(loaded)
+    }
+
+    /// Write the settings file.
+    fn save(&self) -> Result<(), Error> {
+        json_store::save(&self.path, self)
+    }
+
+    pub fn is_enabled(&self, guild: u64, cmd: &str) -> bool {
+        self.guilds.get(&guild)
+            .map_or(true, |g| !g.disabled.contains(cmd))
+    }
+
+    pub fn maintenance(&self) -> bool {
+        self.maintenance
+    }
+
+    /// Turn maintenance mode on or off and save the change.
+    pub fn set_maintenance(&mut self, on: bool) -> Result<(), Error> {
+        self.maintenance = on;
+        self.save()
+    }
+
+    /// Turn a command on or off in a guild and save the change.
+    pub fn set_enabled(&mut self, guild: u64, cmd: &str, enabled: bool) -> Result<(), Error> {
+        {
+            let disabled = &mut self.guilds.entry(guild)
+                .or_insert_with(GuildSettings::default)
+                .disabled;
+
+            if enabled {
+                disabled.remove(cmd);
+            } else {
+                disabled.insert(cmd.to_owned());
+            }
+        }
+
+        self.save()
+    }
+}
@@ -1,28 +1,105 @@
-use std::fs;
-use std::path::Path;
+use std::cmp;
+use std::collections::{ BTreeMap, HashMap, HashSet };
+use std::fmt;
+use std::fs::{ self, File };
+use std::iter;
+use std::path::{ Path, PathBuf };
+use std::sync::{ Mutex, atomic::{ AtomicBool, AtomicUsize, Ordering } };
+use std::thread;
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
 
+use chrono::{ DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Weekday };
 use failure::Error;
 use itertools::Itertools;
-use rayon::prelude::*;
-use reqwest::Client;
+use rayon::{ ThreadPool, ThreadPoolBuilder, prelude::* };
+use regex::Regex;
+use reqwest::{ Client, RedirectPolicy, Response, Url, header::Location };
 use select::{
     document::Document,
+    node::Node,
     predicate::{ Predicate, Attr, Name, Text, Class, And },
 };
 use tantivy::{
     self,
+    DocAddress,
+    DocId,
+    DocSet,
     Index,
+    IndexWriter,
+    Score,
+    Searcher,
+    SegmentLocalId,
+    SegmentReader,
+    SkipResult,
     schema::*,
-    collector::TopCollector,
+    collector::{ Collector, CountCollector, TopCollector },
+    fastfield::BytesFastFieldReader,
     query::*,
     tokenizer::*,
 };
 use typemap::Key;
 
-/// Endpoint URL for the course search functionality.
+/// Default endpoint URL for the course search functionality.
 static SEARCH_URL: &str = "https://my.uwindsor.ca/web/uw/course-search";
-/// URL for directory services.
-static DIRECTORY_SERVICES: &str = "http://apps.uwindsor.ca/uwincpb/jsp/DirectoryServicesProfile.jsp?q=";
+/// Default URL for directory services.
+static DIRECTORY_SERVICES: &str = "http://apps.uwindsor.ca/uwincpb/jsp/DirectoryServicesProfile.jsp";
+/// Default URL for searching directory services by name.
+static DIRECTORY_SEARCH: &str = "http://apps.uwindsor.ca/uwincpb/jsp/DirectoryServicesSearch.jsp";
+/// Words after a name that aren't part of it, compared without periods.
+static NAME_SUFFIXES: &[&str] = &["jr", "sr", "ii", "iii", "iv", "v", "phd", "md"];
+
+/// Directory the live index is kept in by default.
+pub static INDEX_PATH: &str = "./index";
+/// File in the index directory with when the index was last written.
+static WRITTEN_FILE: &str = "written_at";
+/// File in the index directory the subjects in the search form are kept in.
+static SUBJECTS_FILE: &str = "subjects.json";
+
+/// Set once the bot starts shutting down, so long scrapes stop at the next
+/// course and nothing new is written to the index.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+/// Scrapes and index writes underway, which a shutdown waits for.
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Levels of prerequisites `CourseIndex::prereq_tree` follows.
+const MAX_PREREQ_DEPTH: usize = 3;
+/// Most courses `CourseIndex::prereq_tree` scrapes for one tree.
+const MAX_PREREQ_COURSES: usize = 20;
+/// Characters of HTML kept in a `ParseError`.
+const SNIPPET_LEN: usize = 300;
+/// Longest query in characters a user can search for.
+const MAX_QUERY_LEN: usize = 200;
+/// Deepest a query can nest groups in parentheses.
+const MAX_QUERY_DEPTH: usize = 3;
+/// Most words a query can contain.
+const MAX_QUERY_WORDS: usize = 16;
+/// Most redirects followed for a single request.
+const MAX_REDIRECTS: usize = 5;
+/// Times a failed portal request is tried again by default.
+const DEFAULT_RETRIES: u32 = 3;
+/// Milliseconds before the first retry of a portal request by default. Each
+/// retry after waits twice as long as the one before.
+const DEFAULT_RETRY_DELAY_MS: u64 = 500;
+/// Longest wait in milliseconds before trying a portal request again,
+/// however many retries there have been.
+const MAX_RETRY_DELAY_MS: u64 = 60_000;
+/// Requests made to the portal at once while indexing by default.
+const DEFAULT_MAX_REQUESTS: usize = 8;
+/// Seconds a request to the portal can take by default.
+const DEFAULT_TIMEOUT_SECS: u64 = 15;
+/// Minutes a scraped course is reused by default.
+const DEFAULT_CACHE_MINUTES: u64 = 15;
+/// Length of the grams the code and title are indexed by. Shorter words
+/// can't match those fields.
+const NGRAM_LEN: usize = 3;
+/// Number of results a query returns unless told otherwise.
+pub const DEFAULT_LIMIT: usize = 10;
+/// Words too common in descriptions to help find a course. They're left out
+/// of the description when it's indexed and of queries against it.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "into", "is", "it",
+    "of", "on", "or", "such", "that", "the", "their", "this", "to", "with",
+];
 
 /// Base query used for every request.
 static BASE_QUERY: &[(&str, &str)] = &[
@@ -35,579 +112,4803 @@ static BASE_QUERY: &[(&str, &str)] = &[
     ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_struts.portlet.mode", "view"),
 ];
 
-#[derive(Debug, Fail)]
-#[fail(display = "Error parsing HTML at {}", _0)]
-pub struct ParseError(&'static str);
+/// Formats the portal writes dates in.
+const DATE_FORMATS: &[&str] = &["%b %d, %Y", "%B %d, %Y", "%Y-%m-%d", "%m/%d/%Y", "%d-%b-%Y"];
 
-#[derive(Debug, Fail)]
-#[fail(display = "Query is invalid: {:?}", _0)]
-pub struct QueryError(QueryParserError);
+/// Stands in for a date that couldn't be read, after any real date.
+const UNKNOWN_DATE: u64 = 9999_12_31;
 
-/// Instructor information
-pub struct Instructor {
-    pub name: String,
-    pub title: Option<String>,
-    pub department: Option<String>,
-    pub phone: Option<String>,
-    pub email: Option<String>,
+/// Years of study a course without a year restriction is open to.
+const YEARS_OF_STUDY: &[u8] = &[1, 2, 3, 4];
+
+lazy_static! {
+    /// Seat counts like "3 of 40 seats available".
+    static ref SEATS_OF_REGEX: Regex = Regex::new(r"(?i)(\d+)\s*(?:of|/)\s*(\d+)").unwrap();
+    /// Seat counts like "3 seats available".
+    static ref SEATS_LEFT_REGEX: Regex = Regex::new(r"(?i)(\d+)\s*seats?\b").unwrap();
+    /// Waitlist counts like "Waitlist: 5" or "5 waitlisted".
+    static ref WAITLIST_REGEX: Regex = Regex::new(r"(?i)wait\s*list(?:ed)?\D{0,3}(\d+)|(\d+)\s*(?:on\s+(?:the\s+)?)?wait\s*list").unwrap();
+    /// A meeting like "Mon Wed 10:00 AM - 11:20 AM", with the days and the
+    /// hour, minute, and AM or PM of when it starts and ends.
+    static ref MEETING_REGEX: Regex = Regex::new(r"(?i)((?:\b(?:mon|tue|wed|thu|fri|sat|sun)[a-z]*\.?[\s,/&]*)+)(\d{1,2}):(\d{2})(?:\s*([ap])\.?m\.?)?\s*(?:-|–|to)\s*(\d{1,2}):(\d{2})(?:\s*([ap])\.?m\.?)?").unwrap();
+    /// A time range like "7:00 PM - 10:00 PM", with the hour, minute, and AM
+    /// or PM of when it starts and ends.
+    static ref TIME_RANGE_REGEX: Regex = Regex::new(r"(?i)(\d{1,2}):(\d{2})(?:\s*([ap])\.?m\.?)?\s*(?:-|–|to)\s*(\d{1,2}):(\d{2})(?:\s*([ap])\.?m\.?)?").unwrap();
+    /// The year in a date like "Dec 12, 2018" or "2018-12-12".
+    static ref DATE_YEAR_REGEX: Regex = Regex::new(r"\b(\d{4})\b").unwrap();
+    static ref WEEKDAY_REGEX: Regex = Regex::new(r"(?i)\b(mon|tue|wed|thu|fri|sat|sun)").unwrap();
+    /// A course code in a prerequisite, like "COMP-1000", "60-141", or
+    /// "03-60-141".
+    static ref PREREQ_CODE_REGEX: Regex = Regex::new(r"\b(?:[A-Z]{3,4}[- ]?\d{4}|\d{2}-\d{2,3}(?:-\d{3})?)\b").unwrap();
+    static ref TERM_REGEX: Regex = Regex::new(r"([fsw])(\d\d)").unwrap();
+    /// A list of years of study, like "3rd/4th year" or "third and fourth year".
+    static ref YEARS_REGEX: Regex = Regex::new(r"(?i)\b((?:(?:[1-5](?:st|nd|rd|th)?|first|second|third|fourth|fifth)\s*(?:/|,|-|&|and|or)?\s*)+)[- ]?years?\b").unwrap();
+    static ref ISO_DATE_REGEX: Regex = Regex::new(r"^\d{4}-(\d{1,2})-(\d{1,2})$").unwrap();
+    static ref SLASH_DATE_REGEX: Regex = Regex::new(r"^(\d{1,2})/(\d{1,2})(?:/\d{2,4})?$").unwrap();
+    static ref MONTH_DAY_REGEX: Regex = Regex::new(r"^(?:\w+,?\s+)?([a-z]+)\.?\s+(\d{1,2})(?:st|nd|rd|th)?(?:,?\s+\d{4})?$").unwrap();
+    static ref DAY_MONTH_REGEX: Regex = Regex::new(r"^(\d{1,2})(?:st|nd|rd|th)?\s+([a-z]+)\.?(?:,?\s+\d{4})?$").unwrap();
+    static ref YEAR_REGEX: Regex = Regex::new(r"(?i)[1-5]|first|second|third|fourth|fifth").unwrap();
+    /// A program, like "Computer Science majors only" or "restricted to
+    /// students in the Nursing program".
+    static ref PROGRAM_REGEX: Regex = Regex::new(r"(?:(?i:restricted to|open only to|open to|limited to)\s+(?:(?i:students in)\s+)?(?:(?i:the)\s+)?([A-Z][\w&]*(?:\s+(?:of\s+|and\s+)?[A-Z][\w&]*)*)\s+(?i:program|majors|students))|(?:\b([A-Z][\w&]*(?:\s+(?:of\s+|and\s+)?[A-Z][\w&]*)*)\s+(?i:majors|students)\s+(?i:only))").unwrap();
 }
 
-impl Instructor {
-    pub fn directory_url(&self) -> Option<String> {
-        self.email.as_ref()
-            .and_then(|e| e.split('@').next())
-            .map(|id| format!("{}{}", DIRECTORY_SERVICES, id))
-    }
+/// The part of a page that couldn't be parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseField {
+    TermCodeValue,
+    TermCodeName,
+    SubjectList,
+    CourseList,
+    CourseTitle,
+    CourseDetails,
+    Meets,
+    Starts,
+    Ends,
+    Campus,
+    Availability,
+    CourseValue,
+    DateDropsClose,
+    CourseExam,
+    CourseInstructors,
+    InstructorName,
+    StoredTerm,
+    StoredCode,
+    StoredTitle,
+    SectionCode,
 }
 
-/// Exam information
-pub struct Exam {
-    pub ty: String,
-    pub slot: Option<String>,
-    pub date: Option<String>,
-    pub time: Option<String>,
-    pub building: Option<String>,
-    pub room: Option<String>,
-    pub area: Option<String>,
+impl ParseField {
+    /// A name for the field that won't change, for alerts to match on.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ParseField::TermCodeValue => "term_code_value",
+            ParseField::TermCodeName => "term_code_name",
+            ParseField::SubjectList => "subject_list",
+            ParseField::CourseList => "course_list",
+            ParseField::CourseTitle => "course_title",
+            ParseField::CourseDetails => "course_details",
+            ParseField::Meets => "meets",
+            ParseField::Starts => "starts",
+            ParseField::Ends => "ends",
+            ParseField::Campus => "campus",
+            ParseField::Availability => "availability",
+            ParseField::CourseValue => "course_value",
+            ParseField::DateDropsClose => "date_drops_close",
+            ParseField::CourseExam => "course_exam",
+            ParseField::CourseInstructors => "course_instructors",
+            ParseField::InstructorName => "instructor_name",
+            ParseField::StoredTerm => "stored_term",
+            ParseField::StoredCode => "stored_code",
+            ParseField::StoredTitle => "stored_title",
+            ParseField::SectionCode => "section_code",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match *self {
+            ParseField::TermCodeValue => "term code value",
+            ParseField::TermCodeName => "term code name",
+            ParseField::SubjectList => "subject list",
+            ParseField::CourseList => "course list",
+            ParseField::CourseTitle => "course title",
+            ParseField::CourseDetails => "course details",
+            ParseField::Meets => "meets",
+            ParseField::Starts => "starts",
+            ParseField::Ends => "ends",
+            ParseField::Campus => "campus",
+            ParseField::Availability => "availability",
+            ParseField::CourseValue => "course value",
+            ParseField::DateDropsClose => "date drops close",
+            ParseField::CourseExam => "course exam",
+            ParseField::CourseInstructors => "course instructors",
+            ParseField::InstructorName => "instructor name",
+            ParseField::StoredTerm => "stored term",
+            ParseField::StoredCode => "stored code",
+            ParseField::StoredTitle => "stored title",
+            ParseField::SectionCode => "section code",
+        }
+    }
 }
 
-/// Full course information
-pub struct Course {
-    pub code: String,
-    pub title: String,
-    pub meets: String,
-    pub starts: String,
-    pub ends: String,
-    pub campus: String,
-    pub availability: String,
-    pub course_value: String,
-    pub date_drops_close: String,
-    pub description: String,
-    pub note: Option<String>,
-    pub prereqs: Vec<String>,
-    pub exams: Vec<Exam>,
-    pub instructors: Vec<Instructor>,
+/// A field couldn't be found, with where it was looked for so a changed
+/// layout can be seen in the logs.
+#[derive(Debug, Fail)]
+pub struct ParseError {
+    pub field: ParseField,
+    /// The page the field was looked for in.
+    pub url: Option<String>,
+    /// The start of the HTML the field was looked for in.
+    pub snippet: Option<String>,
 }
 
-/// Course preview information that is stored in the index.
-/// We save this information when we index all of the courses so
-/// we only have to fully scrape a course when we need to.
-pub struct CoursePreview<'a> {
-    scraper: &'a Scraper,
-    pub term: String,
-    pub code: String,
-    pub title: String,
+impl ParseError {
+    /// A field missing from something other than a page, like a stored
+    /// document.
+    pub fn new(field: ParseField) -> Self {
+        ParseError {
+            field: field,
+            url: None,
+            snippet: None,
+        }
+    }
+
+    /// A field missing from HTML fetched from a URL.
+    pub fn at(field: ParseField, url: &str, html: &str) -> Self {
+        let html = html.split_whitespace().join(" ");
+        let mut snippet = html.chars().take(SNIPPET_LEN).collect::<String>();
+
+        if html.len() > snippet.len() {
+            snippet.push('…');
+        }
+
+        ParseError {
+            field: field,
+            url: Some(url.to_owned()),
+            snippet: Some(snippet),
+        }
+    }
 }
 
-impl<'a> CoursePreview<'a> {
-    /// Scrape all information for a course.
-    pub fn scrape(&self) -> Result<Course, Error> {
-        self.scraper.scrape_full(&self.term, &self.code)
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error parsing HTML at {} (parse_error={})", self.field.description(), self.field.code())?;
+
+        if let Some(ref url) = self.url {
+            write!(f, " from {}", url)?;
+        }
+
+        if let Some(ref snippet) = self.snippet {
+            write!(f, " in {:?}", snippet)?;
+        }
+
+        Ok(())
     }
 }
 
-/// A search index for all current courses.
-pub struct CourseIndex {
-    scraper: Scraper,
-    index: Index,
-    term: Field,
-    code: Field,
-    title: Field,
-    description: Field,
+/// The search form didn't have the term list, which nothing can be scraped
+/// without.
+#[derive(Debug, Fail)]
+#[fail(display = "Course search form has no term list (page title {:?}), the portal layout may have changed", title)]
+pub struct FormError {
+    title: String,
 }
 
-impl Key for CourseIndex {
-    type Value = Self;
+/// The portal answered with an error status, even after trying again if the
+/// error was on its end.
+#[derive(Debug, Fail)]
+#[fail(display = "Portal answered with status {}", _0)]
+pub struct StatusError(u16);
+
+/// The portal sent us to its login page, which usually means the session
+/// expired. Trying again later starts a new session.
+#[derive(Debug, Fail)]
+#[fail(display = "Portal redirected to a login page ({:?}), the session may have expired", _0)]
+pub struct SessionError(String);
+
+/// A course couldn't be scraped from the portal, which is usually down or
+/// has changed its layout rather than anything being wrong with the bot.
+#[derive(Debug, Fail)]
+#[fail(display = "Couldn't scrape {} in term {}: {}", code, term, reason)]
+pub struct ScrapeError {
+    pub term: String,
+    pub code: String,
+    /// What went wrong, for the logs.
+    pub reason: String,
 }
 
-impl CourseIndex {
-    /// Opens or attempts to create a new index by scraping information from the
-    /// university search system.
-    pub fn open() -> Result<Self, Error> {
-        let ngram = TextOptions::default()
-            .set_indexing_options(
-                TextFieldIndexing::default()
-                    .set_tokenizer("ngram")
-                    .set_index_option(IndexRecordOption::WithFreqsAndPositions)
-            )
-            .set_stored();
+/// Work was refused or stopped partway because the bot is shutting down.
+#[derive(Debug, Fail)]
+#[fail(display = "Shutting down")]
+pub struct ShutdownError;
 
-        let mut schema_builder = SchemaBuilder::default();
-        let term = schema_builder.add_text_field("term", STRING | STORED);
-        let code = schema_builder.add_text_field("code", ngram.clone());
-        let title = schema_builder.add_text_field("title", ngram);
-        let description = schema_builder.add_text_field("description", TEXT);
-        let schema = schema_builder.build();
+#[derive(Debug, Fail)]
+pub enum QueryError {
+    #[fail(display = "Query is invalid: {:?}", _0)]
+    Parse(QueryParserError),
+    #[fail(display = "Query is too complex: {}", _0)]
+    TooComplex(&'static str),
+    #[fail(display = "Not a course code: {}", _0)]
+    BadCode(String),
+}
 
-        let path = Path::new("./index");
+/// A course code as the portal expects it, from any of the ways people
+/// write one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CourseCode {
+    /// The subject and number, like `COMP1000` or `0360140`. Codes written
+    /// without the faculty, like `60-140`, have only the last five digits.
+    pub activity: String,
+    pub section: Option<String>,
+}
 
-        let exists = path.is_dir();
+impl CourseCode {
+    /// Parse a code like `60-100`, `60100`, `60100-01`, `03-60-100-01`,
+    /// `COMP 1000`, or `COMP-1000-01`.
+    pub fn parse(text: &str) -> Result<CourseCode, QueryError> {
+        let bad = || QueryError::BadCode(text.trim().to_owned());
 
-        let index = if exists {
-            Index::open_in_dir(path)?
-        } else {
-            fs::create_dir(path)?;
-            Index::create_in_dir(path, schema)?
-        };
+        let upper = text.trim().to_uppercase();
 
-        index.tokenizers()
-            .register("ngram", {
-                NgramTokenizer::new(3, 3, false)
-                    .filter(RemoveLongFilter::limit(40))
-                    .filter(LowerCaser)
-            });
+        if !upper.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c.is_whitespace()) {
+            return Err(bad());
+        }
 
-        let scraper = Scraper::new();
+        let groups = upper.split(|c: char| c == '-' || c.is_whitespace())
+            .filter(|group| !group.is_empty())
+            .collect::<Vec<_>>();
 
-        if !exists {
-            let mut index_writer = index.writer(100_000_000)?;
+        let subject = upper.chars()
+            .take_while(char::is_ascii_alphabetic)
+            .collect::<String>();
 
-            info!("Scraping course information...");
+        // The digits after the subject, in the groups they were written in.
+        let numbers = if subject.is_empty() {
+            groups.clone()
+        } else {
+            iter::once(&groups[0][subject.len()..])
+                .chain(groups[1..].iter().cloned())
+                .filter(|group| !group.is_empty())
+                .collect()
+        };
 
-            let data = scraper.scrape()?;
+        if numbers.is_empty() || !numbers.iter().all(|group| group.chars().all(|c| c.is_ascii_digit())) {
+            return Err(bad());
+        }
 
-            info!("Adding course information to index...");
+        let lens = numbers.iter().map(|group| group.len()).collect::<Vec<_>>();
+        let joined = numbers.concat();
 
-            for (ter, courses) in data {
-                for (c, t, d) in courses {
-                    let mut doc = tantivy::Document::default();
-                    doc.add_text(term, &ter);
-                    doc.add_text(code, &c);
-                    doc.add_text(title, &t);
-                    doc.add_text(description, &d);
-                    index_writer.add_document(doc);
+        let (activity, section) = if subject.is_empty() {
+            match lens.as_slice() {
+                [5] | [7] | [2, 3] | [2, 2, 3] => (joined, None),
+                [9] | [5, 2] | [7, 2] | [2, 3, 2] | [2, 2, 3, 2] => {
+                    let (activity, section) = joined.split_at(joined.len() - 2);
+                    (activity.to_owned(), Some(section.to_owned()))
                 }
+                _ => return Err(bad()),
             }
+        } else {
+            match lens.as_slice() {
+                [1..=4] => (subject + &joined, None),
+                [6] | [1..=4, 2] => {
+                    let (number, section) = joined.split_at(joined.len() - 2);
+                    (subject + number, Some(section.to_owned()))
+                }
+                _ => return Err(bad()),
+            }
+        };
 
-            index_writer.commit()?;
-            index.load_searchers()?;
-        }
-
-        Ok(CourseIndex {
-            scraper: scraper,
-            index: index,
-            term: term,
-            code: code,
-            title: title,
-            description: description,
+        Ok(CourseCode {
+            activity: activity,
+            section: section,
         })
     }
 
-    /// Returns a list of courses found in the index.
-    pub fn query<'a>(&'a self, term: &str, query: &str) -> Result<Vec<CoursePreview<'a>>, Error> {
-        // The query string the user has entered.
-        let default_fields = vec![self.code, self.title, self.description];
-        let user_query = QueryParser::for_index(&self.index, default_fields)
-            .parse_query(query)
-            .map_err(QueryError)?;
+    /// Whether an indexed code is a section of this course, or this section
+    /// if it has one.
+    fn matches(&self, code: &str) -> bool {
+        let other = match CourseCode::parse(code) {
+            Ok(other) => other,
+            Err(_) => return false,
+        };
 
-        // The query for the current term (semester).
-        let term_query = TermQuery::new(
-            Term::from_field_text(self.term, term),
-            IndexRecordOption::Basic,
-        );
+        // Only codes of digits can leave out the start, the faculty.
+        let same_course = other.activity == self.activity
+            || (self.activity.chars().all(|c| c.is_ascii_digit()) && other.activity.ends_with(&self.activity));
 
-        let query = BooleanQuery::from(vec![
-            (Occur::Must, user_query),
-            (Occur::Must, Box::new(term_query))
-        ]);
+        same_course && self.section.as_ref().map_or(true, |section| other.section.as_ref() == Some(section))
+    }
 
-        let mut top = TopCollector::with_limit(10);
-        let searcher = self.index.searcher();
-        searcher.search(&query, &mut top)?;
+    /// The activity code and section of a section's code, as the portal
+    /// needs to find it. Codes come from the portal so one without both is
+    /// a row that wasn't parsed right.
+    fn split_section(full_code: &str) -> Result<(String, String), ParseError> {
+        let code = CourseCode::parse(full_code).ok();
 
-        top.docs()
-            .iter()
-            .map(|doc| {
-                let doc = searcher.doc(doc)?;
-                let term = doc.get_first(self.term).unwrap();
-                let code = doc.get_first(self.code).unwrap();
-                let title = doc.get_first(self.title).unwrap();
-
-                Ok(CoursePreview {
-                    scraper: &self.scraper,
-                    term: term.text().to_owned(),
-                    code: code.text().to_owned(),
-                    title: title.text().to_owned(),
-                })
-            })
-            .collect::<Result<Vec<_>, Error>>()
+        match code {
+            Some(CourseCode { activity, section: Some(section) }) => Ok((activity, section)),
+            _ => Err(ParseError {
+                field: ParseField::SectionCode,
+                url: None,
+                snippet: Some(full_code.to_owned()),
+            }),
+        }
     }
 }
 
-pub struct Scraper(Client);
-
-impl Key for Scraper {
-    type Value = Self;
+/// A term picked by when it is rather than by name, for `-s current`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelativeTerm {
+    Current,
+    Next,
 }
 
-impl Scraper {
-    fn new() -> Self {
-        Scraper(Client::new())
+impl RelativeTerm {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "current" => Some(RelativeTerm::Current),
+            "next" => Some(RelativeTerm::Next),
+            _ => None,
+        }
     }
+}
 
-    /// Scrape all terms
-    fn scrape(&self) -> Result<Vec<(String, Vec<(String, String, String)>)>, Error> {
-        let resp = self.0.get(SEARCH_URL)
-            .query(BASE_QUERY)
-            .send()
-            .and_then(|mut r| r.text())?;
+/// The season of a term, in the order they happen within a year.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Season {
+    Winter,
+    Summer,
+    Fall,
+}
 
-        let doc = Document::from(resp.as_ref());
+/// A term (semester) such as Fall 2018.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TermCode {
+    pub year: u32,
+    pub season: Season,
+}
 
-        doc.find({
-                And(Name("select"), Attr("id", "ExecuteCourseSearch_acadtermCode"))
-            })
-            .next()
-            .ok_or(ParseError("term code list"))?
-            .children()
-            .filter(|node| node.is(Name("option")))
-            .map(|node| {
-                let code = node.attr("value")
-                    .ok_or(ParseError("term code value"))?;
+impl TermCode {
+    /// Parse the portal's term code. Term codes are in the form [YEAR][CODE]
+    /// where year is XXXX and code is 1 (Winter) 2 (Summer) or 5 (Fall).
+    pub fn from_code(code: &str) -> Option<Self> {
+        if code.len() != 5 || !code.is_char_boundary(4) {
+            return None;
+        }
 
-                let courses = self.scrape_courses(code)?;
+        let (year, season) = code.split_at(4);
+        let season = match season {
+            "1" => Season::Winter,
+            "2" => Season::Summer,
+            "5" => Season::Fall,
+            _ => return None,
+        };
 
-                let name = node.find(Text)
-                    .flat_map(|node| node.as_text())
-                    .flat_map(str::split_whitespace)
-                    .join(" ");
+        Some(TermCode {
+            year: year.parse().ok()?,
+            season: season,
+        })
+    }
 
-                if name.is_empty() {
-                    return Err(ParseError("term code name").into());
-                }
+    /// Parse the short form users enter, [wWsSfF]XX where XX is the year.
+    pub fn from_short(s: &str) -> Option<Self> {
+        let c = TERM_REGEX.captures(s)?;
 
-                Ok((code.to_owned(), courses))
-            })
-            .collect::<Result<Vec<_>, Error>>()
-    }
+        let season = match c.get(1)?.as_str() {
+            "w" | "W" => Season::Winter,
+            "s" | "S" => Season::Summer,
+            "f" | "F" => Season::Fall,
+            _ => return None,
+        };
 
-    /// Scrape all courses for a term
-    fn scrape_courses(&self, term: &str) -> Result<Vec<(String, String, String)>, Error> {
-        let query = [
-            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_struts.portlet.action", "/courseSearch/ExecuteCourseSearch"),
-        ];
+        let year: u32 = c.get(2)?.as_str().parse().ok()?;
 
-        let form = [
-            ("acadtermCode", term),
-            ("advancedSearch", "false"),
-            ("courseSearchForm.acadLevel", ""),
-            ("courseSearchForm.courseNumber", ""),
-            ("courseSearchForm.searchBy", "Course"),
-            ("courseSearchForm.subject", " "),
-        ];
+        Some(TermCode {
+            year: 2000 + year,
+            season: season,
+        })
+    }
 
-        let resp = self.0.post(SEARCH_URL)
-            .query(BASE_QUERY)
-            .query(&query)
-            .form(&form)
-            .send()
-            .and_then(|mut r| r.text())?;
+    /// Parse a range of terms in the short form like `f18-w19`, returning
+    /// every term from the start to the end inclusive.
+    pub fn range(s: &str) -> Option<Vec<Self>> {
+        let mut ends = s.splitn(2, '-');
+        let start = TermCode::from_short(ends.next()?)?;
+        let end = TermCode::from_short(ends.next()?)?;
 
-        let doc = Document::from(resp.as_ref());
+        if start > end {
+            return None;
+        }
 
-        doc.find({
-                Attr("id", "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_CourseResults")
-                    .child(Name("table"))
-                    .child(Name("tbody"))
-            })
-            .next()
-            .ok_or(ParseError("course list"))?
-            .children()
-            .filter(|node| node.is(Name("tr")))
-            .map(|node| {
-                // We only want the list of course codes.
-                let code = node.children()
-                    .filter(|node| node.is(Name("td")))
-                    .next()
-                    .map(|node| {
-                        node.find(Text)
-                            .flat_map(|node| node.as_text())
-                            .flat_map(|s| {
-                                s.split_whitespace()
-                                    .flat_map(|s| s.split("-"))
-                            })
-                            .collect::<String>()
-                    })
-                    .filter(|code| !code.is_empty())
-                    .ok_or(ParseError("course code"))?;
+        let mut terms = vec![start];
+        while *terms.last().unwrap() < end {
+            let next = terms.last().unwrap().next();
+            terms.push(next);
+        }
 
-                Ok(code)
-            })
-            .collect::<Result<Vec<_>, Error>>()?
-            .into_par_iter() // We will get the courses in parallel.
-            .map(|code| {
-                self.scrape_basic(term, &code)
-                    .map(|(title, description)| (code, title, description))
-            })
-            .collect::<Result<Vec<_>, Error>>()
+        Some(terms)
     }
 
-    /// Scrape the title and description for a given course code for a given term.
-    /// This information is used to build the intial search index.
-    fn scrape_basic(&self, term: &str, full_code: &str) -> Result<(String, String), Error> {
-        let (code, section) = full_code.split_at(7);
+    /// The term today's date falls in, where Winter is January to April,
+    /// Summer is May to August, and Fall is September to December.
+    pub fn today() -> Self {
+        use chrono::Datelike;
 
-        let details_query = [
-            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.acadtermCode", term),
-            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.activityCode", code),
-            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.sectionNo", section),
-        ];
+        let today = Local::today();
 
-        let resp = self.0.get(SEARCH_URL)
-            .query(BASE_QUERY)
-            .query(&details_query)
-            .query(&[
-                ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_struts.portlet.action", "/courseSearch/viewCourseDetails"),
-            ])
-            .send()
-            .and_then(|mut r| r.text())?;
+        let season = match today.month() {
+            1..=4 => Season::Winter,
+            5..=8 => Season::Summer,
+            _ => Season::Fall,
+        };
 
-        let doc = Document::from(resp.as_ref());
+        TermCode {
+            year: today.year() as u32,
+            season: season,
+        }
+    }
 
-        let title = doc.find({
-                Name("body")
-                    .child(Name("h1"))
-            })
-            .next()
-            .ok_or(ParseError("course title"))?
-            .find(Text)
-            .flat_map(|node| node.as_text())
-            .flat_map(str::split_whitespace)
-            .join(" ");
+    /// The term that follows this one.
+    pub fn next(&self) -> Self {
+        match self.season {
+            Season::Winter => TermCode { year: self.year, season: Season::Summer },
+            Season::Summer => TermCode { year: self.year, season: Season::Fall },
+            Season::Fall => TermCode { year: self.year + 1, season: Season::Winter },
+        }
+    }
 
-        let description = doc.find({
-                Attr("id", "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_tabs-details")
-            })
-            .next()
-            .ok_or(ParseError("course details"))?
-            .find({
-                Name("p")
-                    .descendant(Text)
-            })
-            .flat_map(|node| node.as_text())
-            .flat_map(str::split_whitespace)
-            .join(" ");
+    /// The code the portal uses for this term.
+    pub fn code(&self) -> String {
+        let season = match self.season {
+            Season::Winter => 1,
+            Season::Summer => 2,
+            Season::Fall => 5,
+        };
 
-        Ok((title, description))
+        format!("{}{}", self.year, season)
     }
 
-    /// Scrape full course information for a given course when requested.
-    fn scrape_full(&self, term: &str, full_code: &str) -> Result<Course, Error> {
-        let (code, section) = full_code.split_at(7);
+    /// The short form users enter for this term.
+    pub fn short(&self) -> String {
+        let season = match self.season {
+            Season::Winter => 'w',
+            Season::Summer => 's',
+            Season::Fall => 'f',
+        };
 
-        let details_query = [
-            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.acadtermCode", term),
-            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.activityCode", code),
-            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.sectionNo", section),
-        ];
+        format!("{}{:02}", season, self.year % 100)
+    }
+}
 
-        //
-        // Main Query
-        //
-        let resp = self.0.get(SEARCH_URL)
-            .query(BASE_QUERY)
-            .query(&details_query)
-            .query(&[
-               ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_struts.portlet.action", "/courseSearch/viewCourseDetails"),
-            ])
-            .send()
-            .and_then(|mut r| r.text())?;
+impl fmt::Display for TermCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let season = match self.season {
+            Season::Winter => "Winter",
+            Season::Summer => "Summer",
+            Season::Fall => "Fall",
+        };
 
-        let doc = Document::from(resp.as_ref());
+        write!(f, "{} {}", season, self.year)
+    }
+}
 
-        let title = doc.find({
-                Name("body")
-                    .child(Name("h1"))
-            })
-            .next()
-            .ok_or(ParseError("course title"))?
-            .find(Text)
-            .flat_map(|node| node.as_text())
-            .flat_map(str::split_whitespace)
-            .join(" ");
+/// Instructor information
+#[derive(Clone)]
+pub struct Instructor {
+    pub name: String,
+    pub title: Option<String>,
+    pub department: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    /// The directory profile page of the portal the instructor came from.
+    directory: String,
+    /// The directory search page of the portal the instructor came from.
+    directory_search: String,
+}
 
-        let details = doc.find({
-                Attr("id", "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_tabs-details")
-            })
-            .next()
-            .ok_or(ParseError("course details"))?;
+impl Instructor {
+    /// Link to the instructor's directory profile, which is looked up by the
+    /// id in their email. Emails that don't look like `id@domain` get no link.
+    pub fn directory_url(&self) -> Option<String> {
+        let email = self.email.as_ref()?.trim();
 
-        let meets = details.children()
-            .filter(|node| node.is(Name("div")))
-            .next()
-            .map(|node| {
-                node.find(Text)
-                    .flat_map(|n| n.as_text())
-                    .flat_map(str::split_whitespace)
-                    .join(" ")
-            })
-            .ok_or(ParseError("meets"))?;
+        let (id, domain) = match email.split('@').collect::<Vec<_>>().as_slice() {
+            [id, domain] => (*id, *domain),
+            _ => return None,
+        };
 
-        let f = |id: &str| {
-            details.find({
-                    Name("div")
-                        .descendant(Attr("id", id))
-                })
-                .next()
-                .map(|node| {
-                    node.find(Text)
-                        .flat_map(|n| n.as_text())
-                        .flat_map(str::split_whitespace)
-                        .join(" ")
-                })
+        if id.is_empty() || !domain.contains('.') || email.contains(char::is_whitespace) {
+            return None;
+        }
+
+        // The id is percent-encoded as a query parameter, so accents and
+        // characters like parentheses can't break the URL or the link.
+        Url::parse_with_params(&self.directory, &[("q", id)])
+            .ok()
+            .map(Url::into_string)
+    }
+
+    /// Link to a directory search for the instructor's last name, for
+    /// instructors without an email to find their profile by.
+    pub fn directory_search_url(&self) -> Option<String> {
+        let last_name = self.last_name()?;
+
+        Url::parse_with_params(&self.directory_search, &[("lastName", last_name)])
+            .ok()
+            .map(Url::into_string)
+    }
+
+    /// The best link there is for the instructor: their profile if their
+    /// email has an id in it, otherwise a search for their name.
+    pub fn profile_link(&self) -> Option<String> {
+        self.directory_url().or_else(|| self.directory_search_url())
+    }
+
+    /// The last name of the instructor, leaving out suffixes like "Jr." and
+    /// "III". Names like "Smith, John" are taken to have the last name first.
+    fn last_name(&self) -> Option<&str> {
+        let is_suffix = |word: &str| {
+            let word = word.trim_matches('.').to_lowercase();
+            NAME_SUFFIXES.contains(&word.as_str())
         };
 
-        let starts = f("dateSessionStartsFormatted")
-            .ok_or(ParseError("starts"))?;
+        // A part after a comma that's only suffixes, as in "John Smith, Jr.",
+        // doesn't make the name last name first.
+        let parts = self.name.split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty() && !part.split_whitespace().all(&is_suffix))
+            .collect::<Vec<_>>();
 
-        let ends = f("dateSessionEndsFormatted")
-            .ok_or(ParseError("ends"))?;
+        let words = if parts.len() > 1 { parts.first()? } else { parts.last()? };
 
-        let campus = f("courseSectionInfo_campus")
-            .ok_or(ParseError("campus"))?;
+        words.split_whitespace()
+            .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+            .filter(|word| !word.is_empty() && !is_suffix(word))
+            .last()
+    }
+}
 
-        let availability = f("courseSectionInfo_sectionAvailability")
-            .ok_or(ParseError("availability"))?;
+/// Exam information
+#[derive(Clone)]
+pub struct Exam {
+    pub ty: String,
+    pub slot: Option<String>,
+    /// The date, if it could be read.
+    pub date: Option<NaiveDate>,
+    /// The date as the portal wrote it, to show when it couldn't be read.
+    pub date_text: Option<String>,
+    /// When the exam starts and ends, if it could be read.
+    pub time: Option<(NaiveTime, NaiveTime)>,
+    /// The time as the portal wrote it, to show when it couldn't be read.
+    pub time_text: Option<String>,
+    pub building: Option<String>,
+    pub room: Option<String>,
+    pub area: Option<String>,
+}
 
-        let course_value = f("courseSectionInfo_courseValue")
-            .ok_or(ParseError("course_value"))?;
+impl Exam {
+    /// The month and day of the exam, if its date could be read.
+    pub fn month_day(&self) -> Option<(u32, u32)> {
+        use chrono::Datelike;
 
-        let date_drops_close = f("dateDropsCloseFormatted")
-            .ok_or(ParseError("date_drops_close"))?;
+        self.date.map(|date| (date.month(), date.day()))
+    }
 
-        let note = details.find({
-                And(Name("p"), Class("uwinNoteText"))
-            })
-            .next()
-            .map(|node| {
-                node.find(Text)
-                    .flat_map(|node| node.as_text())
-                    .flat_map(str::split_whitespace)
-                    .join(" ")
-            });
+    /// When the exam starts and ends, if both its date and time could be
+    /// read.
+    pub fn start_end(&self) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        let date = self.date?;
+        let (start, end) = self.time?;
 
-        let description = doc.find({
-                Attr("id", "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_tabs-details")
-            })
-            .next()
-            .ok_or(ParseError("course details"))?
-            .find({
-                Name("p")
-                    .descendant(Text)
-            })
-            .flat_map(|node| node.as_text())
-            .flat_map(str::split_whitespace)
-            .join(" ");
+        Some((date.and_time(start), date.and_time(end)))
+    }
 
-        let prereqs = doc.find({
-                Attr("id", "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_tabs-prerequistes")
-                    .child(Name("ul"))
-                    .child(Name("li"))
-            })
-            .map(|node| {
-                node.find(Text)
-                    .flat_map(|node| node.as_text())
-                    .flat_map(str::split_whitespace)
-                    .join(" ")
-            })
-            .collect::<Vec<_>>();
+    /// The date to show, like "Dec 12, 2018", or the portal's text if it
+    /// couldn't be read.
+    pub fn date_string(&self) -> Option<String> {
+        self.date
+            .map(|date| date.format("%b %-d, %Y").to_string())
+            .or_else(|| self.date_text.clone())
+    }
 
-        let exams = doc.find({
-                Attr("id", "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_tabs-exams")
-                    .descendant(Name("tr"))
-            })
-            .skip(1) // Skip title
-            .map(|node| {
-                let mut columns = node.find(Name("td").descendant(Text))
-                    .flat_map(|n| n.as_text())
-                    .map(str::trim)
-                    .map(str::to_owned);
+    /// The time to show, like "7:00 PM - 10:00 PM", or the portal's text if
+    /// it couldn't be read.
+    pub fn time_string(&self) -> Option<String> {
+        self.time
+            .map(|(start, end)| format!("{} - {}", start.format("%-I:%M %p"), end.format("%-I:%M %p")))
+            .or_else(|| self.time_text.clone())
+    }
+}
 
-                Some(Exam {
-                    ty: columns.next()?,
-                    slot: columns.next(),
-                    date: columns.next(),
-                    time: columns.next(),
-                    building: columns.next(),
-                    room: columns.next(),
-                    area: columns.next(),
-                })
-            })
-            .collect::<Option<Vec<_>>>()
-            .ok_or(ParseError("course exam"))?;
+/// Read the date of an exam, in one of the formats the portal uses or any
+/// `parse_month_day` reads. Dates written without a year are taken to be in
+/// the year given.
+pub fn parse_exam_date(text: &str, year: i32) -> Option<NaiveDate> {
+    if let Some(date) = parse_date(text) {
+        return Some(date);
+    }
 
-        //
-        // Instructor Query
-        //
-        let resp = self.0.get(SEARCH_URL)
-            .query(BASE_QUERY)
-            .query(&details_query)
-            .query(&[
-                ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_struts.portlet.action", "/courseSearch/viewCourseDetailsInstructors"),
-            ])
-            .send()
-            .and_then(|mut r| r.text())?;
+    let (month, day) = parse_month_day(text)?;
 
-        let doc = Document::from(resp.as_ref());
+    let year = DATE_YEAR_REGEX.captures(text)
+        .and_then(|caps| caps[1].parse().ok())
+        .unwrap_or(year);
 
-        let instructors = doc.find({
-                And(Name("ul"), Class("uwinListView"))
-            })
-            .next()
-            .ok_or(ParseError("course instructors"))?;
+    NaiveDate::from_ymd_opt(year, month, day)
+}
 
-        let instructors = instructors.children()
-            .filter(|node| node.is(Name("li")))
-            .map(|node| {
-                let name = node.find({
-                        Name("b")
-                    })
-                    .next()
-                    .map(|node| {
-                        node.find(Text)
-                            .flat_map(|node| node.as_text())
-                            .flat_map(str::split_whitespace)
-                            .join(" ")
-                    })
-                    .ok_or(ParseError("instructor name"))?;
+/// Read a time range like "7:00 PM - 10:00 PM" or "19:00-22:00". A start
+/// without AM or PM shares the end's, so "7:00 - 10:00 PM" is in the evening.
+pub fn parse_time_range(text: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let caps = TIME_RANGE_REGEX.captures(text)?;
 
-                let mut info = node.find({
-                        Name("div")
-                            .child(Class("wwctrl"))
+    let half = |i| caps.get(i).map(|m| m.as_str().to_lowercase());
+    let end_half = half(6);
+
+    let time = |hour, minute, half: Option<String>| {
+        let hour: u32 = caps.get(hour)?.as_str().parse().ok()?;
+        let minute: u32 = caps.get(minute)?.as_str().parse().ok()?;
+
+        // Times without AM or PM are read as 24 hour times.
+        let hour = match half {
+            Some(ref half) if half == "p" => hour % 12 + 12,
+            Some(_) => hour % 12,
+            None => hour,
+        };
+
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    };
+
+    let start = time(1, 2, half(3).or_else(|| end_half.clone()))?;
+    let end = time(4, 5, end_half)?;
+
+    if start < end {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+/// Read a month and day from a date like "Dec 15", "December 15, 2018",
+/// "15 Dec", "12/15", or "2018-12-15".
+pub fn parse_month_day(text: &str) -> Option<(u32, u32)> {
+    let text = text.trim().to_lowercase();
+
+    let month_number = |name: &str| {
+        ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"].iter()
+            .position(|month| name.len() >= 3 && name.starts_with(month))
+            .map(|i| i as u32 + 1)
+    };
+
+    let (month, day) = if let Some(caps) = ISO_DATE_REGEX.captures(&text) {
+        (caps[1].parse().ok()?, caps[2].parse().ok()?)
+    } else if let Some(caps) = SLASH_DATE_REGEX.captures(&text) {
+        (caps[1].parse().ok()?, caps[2].parse().ok()?)
+    } else if let Some(caps) = MONTH_DAY_REGEX.captures(&text) {
+        (month_number(&caps[1])?, caps[2].parse().ok()?)
+    } else if let Some(caps) = DAY_MONTH_REGEX.captures(&text) {
+        (month_number(&caps[2])?, caps[1].parse().ok()?)
+    } else {
+        return None;
+    };
+
+    if month >= 1 && month <= 12 && day >= 1 && day <= 31 {
+        Some((month, day))
+    } else {
+        None
+    }
+}
+
+/// A single meeting block of a section such as a lecture or lab.
+#[derive(Clone)]
+pub struct MeetingPattern {
+    pub text: String,
+    /// When and where the block meets, which is empty for blocks that are
+    /// TBA or asynchronous.
+    pub times: Vec<MeetingTime>,
+}
+
+impl MeetingPattern {
+    pub fn parse(text: &str) -> Self {
+        MeetingPattern {
+            text: text.to_owned(),
+            times: MeetingTime::parse_all(text),
+        }
+    }
+}
+
+impl fmt::Display for MeetingPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Days and times a meeting block runs, like "Mon Wed 10:00 AM - 11:20 AM".
+#[derive(Clone)]
+pub struct MeetingTime {
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// Whatever follows the time, like the building and room.
+    pub location: Option<String>,
+}
+
+impl MeetingTime {
+    /// Read every meeting in the text of a block. Anything between the end
+    /// of a meeting and the start of the next is its location.
+    pub fn parse_all(text: &str) -> Vec<MeetingTime> {
+        let matches = MEETING_REGEX.captures_iter(text)
+            .collect::<Vec<_>>();
+
+        matches.iter()
+            .enumerate()
+            .filter_map(|(i, caps)| {
+                let time = |hour, minute, half| {
+                    let hour: u32 = caps.get(hour)?.as_str().parse().ok()?;
+                    let minute: u32 = caps.get(minute)?.as_str().parse().ok()?;
+
+                    // Times without AM or PM are read as 24 hour times.
+                    let hour = match caps.get(half).map(|m| m.as_str().to_lowercase()) {
+                        Some(ref half) if half == "p" => hour % 12 + 12,
+                        Some(_) => hour % 12,
+                        None => hour,
+                    };
+
+                    NaiveTime::from_hms_opt(hour, minute, 0)
+                };
+
+                let days = WEEKDAY_REGEX.captures_iter(&caps[1])
+                    .filter_map(|day| match day[1].to_lowercase().as_str() {
+                        "mon" => Some(Weekday::Mon),
+                        "tue" => Some(Weekday::Tue),
+                        "wed" => Some(Weekday::Wed),
+                        "thu" => Some(Weekday::Thu),
+                        "fri" => Some(Weekday::Fri),
+                        "sat" => Some(Weekday::Sat),
+                        "sun" => Some(Weekday::Sun),
+                        _ => None,
                     })
-                    .map(|node| {
-                        node.find(Text)
-                            .flat_map(|s| s.as_text())
-                            .flat_map(str::split_whitespace)
-                            .join(" ")
-                    });
+                    .collect::<Vec<_>>();
 
-                Ok(Instructor {
-                    name: name,
-                    title: info.next(),
-                    department: info.next(),
-                    phone: info.next(),
-                    email: info.next(),
+                let end = caps.get(0)?.end();
+                let next = matches.get(i + 1)
+                    .and_then(|next| next.get(0))
+                    .map_or(text.len(), |next| next.start());
+
+                let location = text[end..next].trim_matches(|c: char| c.is_whitespace() || c == ',' || c == ';');
+
+                Some(MeetingTime {
+                    days: days,
+                    start: time(2, 3, 4)?,
+                    end: time(5, 6, 7)?,
+                    location: Some(location.to_owned()).filter(|location| !location.is_empty()),
                 })
             })
-            .collect::<Result<Vec<_>, Error>>()?;
+            .collect()
+    }
+}
 
-        Ok(Course {
-            code: full_code.to_string(),
-            title: title,
-            meets: meets,
-            starts: starts,
-            ends: ends,
-            campus: campus,
-            availability: availability,
-            course_value: course_value,
-            date_drops_close: date_drops_close,
-            description: description,
-            note: note,
-            prereqs: prereqs,
-            exams: exams,
-            instructors: instructors,
-        })
+impl fmt::Display for MeetingTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} - {}", self.days.iter().map(|day| format!("{:?}", day)).join(" "), self.start.format("%-I:%M %p"), self.end.format("%-I:%M %p"))?;
+
+        if let Some(ref location) = self.location {
+            write!(f, " {}", location)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The pairs of courses, by their index, with meeting times that overlap on
+/// the same day. Courses without meeting times never conflict.
+pub fn find_conflicts(courses: &[Course]) -> Vec<(usize, usize)> {
+    let times = |course: &Course| {
+        course.meets.iter()
+            .flat_map(|meet| meet.times.iter())
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    let overlaps = |a: &MeetingTime, b: &MeetingTime| {
+        a.days.iter().any(|day| b.days.contains(day)) && a.start < b.end && b.start < a.end
+    };
+
+    let times = courses.iter()
+        .map(times)
+        .collect::<Vec<_>>();
+
+    (0..courses.len())
+        .tuple_combinations()
+        .filter(|&(i, j)| times[i].iter().any(|a| times[j].iter().any(|b| overlaps(a, b))))
+        .collect()
+}
+
+/// Seats in a section, read from text like "3 of 40 seats available" or
+/// "Section Full". Counts that can't be read are 0.
+#[derive(Clone)]
+pub struct Availability {
+    pub open: u32,
+    pub total: u32,
+    pub waitlist: Option<u32>,
+    /// The text the counts were read from.
+    text: String,
+}
+
+impl Availability {
+    pub fn parse(text: &str) -> Self {
+        let number = |caps: &::regex::Captures, i| {
+            caps.get(i)
+                .and_then(|m| m.as_str().parse().ok())
+        };
+
+        let (open, total) = if let Some(caps) = SEATS_OF_REGEX.captures(text) {
+            (number(&caps, 1).unwrap_or(0), number(&caps, 2).unwrap_or(0))
+        } else if let Some(caps) = SEATS_LEFT_REGEX.captures(text) {
+            (number(&caps, 1).unwrap_or(0), 0)
+        } else {
+            (0, 0)
+        };
+
+        let waitlist = WAITLIST_REGEX.captures(text)
+            .and_then(|caps| number(&caps, 1).or_else(|| number(&caps, 2)));
+
+        Availability {
+            open: open,
+            total: total,
+            waitlist: waitlist,
+            text: text.to_owned(),
+        }
+    }
+
+    /// Whether a student could still get a seat. Sections that only say
+    /// something like "Open" go by the wording.
+    pub fn has_open_seats(&self) -> bool {
+        if self.open > 0 {
+            true
+        } else if self.total > 0 || SEATS_LEFT_REGEX.is_match(&self.text) {
+            false
+        } else {
+            has_open_seats(&self.text)
+        }
+    }
+}
+
+impl fmt::Display for Availability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Full course information
+#[derive(Clone)]
+pub struct Course {
+    pub code: String,
+    pub title: String,
+    pub meets: Vec<MeetingPattern>,
+    pub starts: String,
+    pub ends: String,
+    pub campus: String,
+    pub delivery: Option<String>,
+    pub availability: Availability,
+    pub course_value: String,
+    pub date_drops_close: String,
+    pub description: String,
+    pub note: Option<String>,
+    /// Year and program restrictions recognized in the note.
+    pub restrictions: Vec<String>,
+    pub prereqs: Vec<String>,
+    pub antireqs: Vec<String>,
+    /// Required textbooks and other materials.
+    pub materials: Vec<String>,
+    pub breadth: Vec<String>,
+    pub exams: Vec<Exam>,
+    pub instructors: Vec<Instructor>,
+}
+
+/// Course information scraped for the search index.
+struct IndexedCourse {
+    code: String,
+    /// The subject the course was listed under, if courses were listed by
+    /// subject.
+    subject: Option<String>,
+    title: String,
+    description: String,
+    note: Option<String>,
+    breadth: Vec<String>,
+    delivery: Option<String>,
+    campus: Option<String>,
+    instructors: Vec<Instructor>,
+    /// The meeting blocks as written, which like the fields after it are
+    /// only kept to count what couldn't be scraped.
+    meets: Option<String>,
+    starts: Option<String>,
+    ends: Option<String>,
+    availability: Option<String>,
+    course_value: Option<String>,
+    /// Whether the section has seats left.
+    open_seats: bool,
+    /// Whether the section is restricted to a program.
+    program_restricted: bool,
+    /// The last day the section can be dropped, if it could be read.
+    drops_close: Option<NaiveDate>,
+}
+
+/// What changed in the portal since the index was built, found by
+/// `Scraper::scrape_changes` and applied with `CourseIndex::apply`.
+pub struct IndexChanges {
+    /// Courses listed now that weren't indexed, by term.
+    added: Vec<(String, IndexedCourse)>,
+    /// Term and code of indexed courses that aren't listed anymore.
+    removed: Vec<(String, String)>,
+}
+
+impl IndexChanges {
+    pub fn added(&self) -> usize {
+        self.added.len()
+    }
+
+    pub fn removed(&self) -> usize {
+        self.removed.len()
+    }
+}
+
+/// Course preview information that is stored in the index.
+/// We save this information when we index all of the courses so
+/// we only have to fully scrape a course when we need to.
+#[derive(Clone)]
+pub struct CoursePreview<'a> {
+    scraper: &'a Scraper,
+    pub term: String,
+    pub code: String,
+    pub title: String,
+    /// The campus the section runs at, if it was scraped.
+    pub campus: Option<String>,
+    /// Sections of the course this stands for when `CourseIndex::query`
+    /// lists each course once, otherwise 1.
+    pub sections: usize,
+}
+
+impl<'a> CoursePreview<'a> {
+    /// Key to sort courses by code with, see `code_sort_key`.
+    pub fn sort_key(&self) -> (String, u32, String) {
+        code_sort_key(&self.code)
+    }
+
+    /// Scrape all information for a course, reusing a recent scrape.
+    pub fn scrape(&self) -> Result<Course, Error> {
+        self.scraper.count_lookup(&self.term, &self.code);
+        self.scraper.scrape_cached(&self.term, &self.code)
+    }
+
+    /// Scrape all information for a course from the portal, for when stale
+    /// availability would mislead.
+    pub fn scrape_fresh(&self) -> Result<Course, Error> {
+        self.scraper.count_lookup(&self.term, &self.code);
+        self.scraper.scrape_fresh(&self.term, &self.code)
+    }
+
+    /// Whether `scrape` can reuse a recent scrape instead of asking the
+    /// portal.
+    pub fn is_cached(&self) -> bool {
+        self.scraper.is_cached(&self.term, &self.code)
+    }
+}
+
+/// A course found by `CourseIndex::query` with how well it matched.
+pub struct SearchHit<'a> {
+    pub preview: CoursePreview<'a>,
+    /// Relevance to the query, where higher is better. Hits come best first.
+    pub score: f32,
+    /// The fields the query matched in, like "code" or "title".
+    pub matched_fields: Vec<&'static str>,
+}
+
+/// A page of courses found by `CourseIndex::query`.
+pub struct SearchResults<'a> {
+    pub hits: Vec<SearchHit<'a>>,
+    /// Courses matching on every page, as `CourseIndex::query_count` counts
+    /// them.
+    pub total: usize,
+}
+
+/// A course with the indexed courses named in each of its prerequisites,
+/// found by `CourseIndex::prereq_tree`.
+pub struct PrereqTree {
+    pub code: String,
+    pub title: String,
+    pub prereqs: Vec<Prereq>,
+}
+
+/// A prerequisite as written, with the trees of the courses it names. Text
+/// that names no course, or that is too deep in the tree, has none.
+pub struct Prereq {
+    pub text: String,
+    pub courses: Vec<PrereqTree>,
+}
+
+/// An instructor found by `CourseIndex::professors` with every course they
+/// teach, however their name was spelled for each section.
+pub struct Professor<'a> {
+    pub name: String,
+    pub courses: Vec<CoursePreview<'a>>,
+}
+
+/// How often each scraped field came back empty while indexing. A field that
+/// is suddenly missing for many courses usually means the portal changed.
+#[derive(Default)]
+pub struct FieldStats {
+    pub courses: usize,
+    pub missing: BTreeMap<&'static str, usize>,
+}
+
+impl FieldStats {
+    /// Count a scraped field, noting whether it was empty.
+    fn record(&mut self, field: &'static str, value: &str) {
+        let missing = self.missing.entry(field)
+            .or_insert(0);
+
+        if value.trim().is_empty() {
+            *missing += 1;
+        }
+    }
+
+    /// Count every scraped field of a course.
+    fn record_course(&mut self, course: &IndexedCourse) {
+        fn text(value: &Option<String>) -> &str {
+            value.as_ref().map_or("", String::as_str)
+        }
+
+        self.courses += 1;
+        self.record("title", &course.title);
+        self.record("description", &course.description);
+        self.record("instructors", &course.instructors.iter().map(|ins| &ins.name).join(" "));
+        self.record("meets", text(&course.meets));
+        self.record("starts", text(&course.starts));
+        self.record("ends", text(&course.ends));
+        self.record("campus", text(&course.campus));
+        self.record("availability", text(&course.availability));
+        self.record("course_value", text(&course.course_value));
+        self.record("delivery", text(&course.delivery));
+    }
+}
+
+impl fmt::Display for FieldStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines = self.missing.iter()
+            .format_with("\n", |(field, missing), f| {
+                f(&format_args!("{} missing in {}/{} courses", field, missing, self.courses))
+            });
+
+        write!(f, "{}", lines)
+    }
+}
+
+/// A search index for all current courses.
+pub struct CourseIndex {
+    scraper: Scraper,
+    index: Index,
+    /// The directory the index is kept in.
+    path: PathBuf,
+    stats: Option<FieldStats>,
+    term: Field,
+    code: Field,
+    /// The code again as a fast field, so hits can be grouped and ordered by
+    /// it without loading their documents.
+    code_bytes: Field,
+    subject: Field,
+    /// The course number from the code, along with each of its leading
+    /// digits so "2" finds second year courses.
+    number: Field,
+    title: Field,
+    /// The title indexed by whole words, for words too short for `title`.
+    title_words: Field,
+    description: Field,
+    note: Field,
+    /// Years of study a course is open to, from its note.
+    years: Field,
+    breadth: Field,
+    delivery: Field,
+    campus: Field,
+    instructor: Field,
+    /// The email of each instructor in the same order as their names, or
+    /// nothing if they don't have one.
+    instructor_email: Field,
+    open_seats: Field,
+    program_restricted: Field,
+    /// The drop deadline as a number like 20181107.
+    drops_close: Field,
+    /// The term and code of a course, like "20185:0360140001", which
+    /// identifies its document.
+    key: Field,
+}
+
+impl Key for CourseIndex {
+    type Value = ::std::sync::Arc<Self>;
+}
+
+impl CourseIndex {
+    /// Builds a new index in a directory next to the live one, like
+    /// `./index.new` for `./index`, and only replaces the live one once the
+    /// build succeeds, so a failed scrape leaves the old index in place.
+    pub fn rebuild(path: &Path, portal: &Portal, filter: &IndexFilter) -> Result<Self, Error> {
+        let rebuild_path = sibling_path(path, ".new");
+        let old_path = sibling_path(path, ".old");
+
+        // Left over from a rebuild that failed partway.
+        for leftover in &[&rebuild_path, &old_path] {
+            if leftover.is_dir() {
+                fs::remove_dir_all(leftover)?;
+            }
+        }
+
+        let stats = CourseIndex::create(&rebuild_path, portal, filter)?.stats;
+
+        // The old index is moved aside rather than deleted so it can be put
+        // back if the new one can't take its place.
+        if path.is_dir() {
+            fs::rename(path, &old_path)?;
+        }
+
+        if let Err(e) = fs::rename(&rebuild_path, path) {
+            if old_path.is_dir() {
+                fs::rename(&old_path, path)?;
+            }
+
+            return Err(e.into());
+        }
+
+        if old_path.is_dir() {
+            if let Err(e) = fs::remove_dir_all(&old_path) {
+                warn!("Couldn't remove the previous index: {}", e);
+            }
+        }
+
+        let mut index = CourseIndex::load(path, portal)?;
+        index.stats = stats;
+
+        Ok(index)
+    }
+
+    /// The directory the index is kept in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The scraper courses in the index are scraped with, which keeps the
+    /// courses it scrapes for a few minutes.
+    pub fn scraper(&self) -> &Scraper {
+        &self.scraper
+    }
+
+    /// Opens the index in a directory, or builds a new one there by scraping
+    /// information from the university search system if there's none that
+    /// can be served. An index that can't be served is only replaced once
+    /// the new one is built.
+    pub fn open(path: &Path, portal: &Portal, filter: &IndexFilter) -> Result<Self, Error> {
+        // A rebuild cut off between moving the old index aside and moving the
+        // new one in leaves only the old one.
+        let old_path = sibling_path(path, ".old");
+
+        if !path.is_dir() && old_path.is_dir() {
+            warn!("Restoring the index moved aside by an unfinished rebuild...");
+            fs::rename(&old_path, path)?;
+        }
+
+        // An index that can't be served as it is gets rebuilt from scratch,
+        // so the bot recovers on its own after a crash.
+        match CourseIndex::unusable(path, &CourseIndex::schema()) {
+            None => CourseIndex::load(path, portal),
+            Some(reason) => {
+                warn!("{}, building a new index...", reason);
+                CourseIndex::rebuild(path, portal, filter)
+            }
+        }
+    }
+
+    /// The fields every course is indexed with.
+    fn schema() -> Schema {
+        let ngram = TextOptions::default()
+            .set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer("ngram")
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions)
+            )
+            .set_stored();
+
+        let mut schema_builder = SchemaBuilder::default();
+        schema_builder.add_text_field("term", STRING | STORED);
+        schema_builder.add_text_field("code", ngram.clone());
+        schema_builder.add_bytes_field("code_bytes");
+        schema_builder.add_text_field("subject", STRING);
+        schema_builder.add_text_field("number", STRING);
+        schema_builder.add_text_field("key", STRING);
+        schema_builder.add_text_field("title", ngram);
+        schema_builder.add_text_field("title_words", TEXT);
+        schema_builder.add_text_field("description", {
+            TextOptions::default()
+                .set_indexing_options(
+                    TextFieldIndexing::default()
+                        .set_tokenizer("description")
+                        .set_index_option(IndexRecordOption::WithFreqsAndPositions)
+                )
+        });
+        schema_builder.add_text_field("note", TEXT);
+        schema_builder.add_text_field("years", TEXT);
+        schema_builder.add_text_field("breadth", TEXT);
+        schema_builder.add_text_field("delivery", TEXT);
+        schema_builder.add_text_field("campus", STORED);
+        schema_builder.add_text_field("instructor", TEXT | STORED);
+        schema_builder.add_text_field("instructor_email", STORED);
+        schema_builder.add_u64_field("open_seats", INT_INDEXED);
+        schema_builder.add_u64_field("program_restricted", INT_INDEXED);
+        schema_builder.add_u64_field("drops_close", INT_INDEXED);
+        schema_builder.build()
+    }
+
+    /// Opens an index that was checked with `unusable`.
+    fn load(path: &Path, portal: &Portal) -> Result<Self, Error> {
+        let index = Index::open_in_dir(path)?;
+        Ok(CourseIndex::with_index(index, path, portal))
+    }
+
+    /// Build a new index in a directory that doesn't exist yet by scraping
+    /// information from the university search system.
+    fn create(path: &Path, portal: &Portal, filter: &IndexFilter) -> Result<Self, Error> {
+        fs::create_dir(path)?;
+
+        let index = Index::create_in_dir(path, CourseIndex::schema())?;
+        let mut course_index = CourseIndex::with_index(index, path, portal);
+
+        // A shutdown stops the scrape at the next course. The writer is then
+        // dropped without committing, leaving nothing half written.
+        let _in_flight = InFlight::start()?;
+        let mut index_writer = course_index.index.writer(100_000_000)?;
+
+        info!("Scraping course information...");
+
+        // Subjects only help with searching so the index is still built
+        // without them. Courses are listed by subject with these.
+        if let Err(e) = course_index.scraper.refresh_subjects() {
+            warn!("Couldn't scrape subjects: {}", e);
+        }
+
+        let data = course_index.scraper.scrape(filter)?;
+
+        info!("Adding course information to index...");
+
+        let mut field_stats = FieldStats::default();
+
+        for (ter, courses) in data {
+            for course in courses {
+                field_stats.record_course(&course);
+                course_index.add_course(&mut index_writer, &ter, &course);
+            }
+        }
+
+        index_writer.commit()?;
+        course_index.index.load_searchers()?;
+        course_index.record_written()?;
+
+        for line in field_stats.to_string().lines() {
+            info!("{}", line);
+        }
+
+        course_index.stats = Some(field_stats);
+
+        Ok(course_index)
+    }
+
+    /// Serve an index with the fields of `schema`.
+    fn with_index(index: Index, path: &Path, portal: &Portal) -> Self {
+        register_tokenizers(&index);
+
+        let schema = index.schema();
+        let field = |name| schema.get_field(name).expect("Index should have the fields of the schema");
+
+        CourseIndex {
+            scraper: Scraper::for_index(portal.clone(), path),
+            path: path.to_owned(),
+            stats: None,
+            term: field("term"),
+            code: field("code"),
+            code_bytes: field("code_bytes"),
+            subject: field("subject"),
+            number: field("number"),
+            title: field("title"),
+            title_words: field("title_words"),
+            description: field("description"),
+            note: field("note"),
+            years: field("years"),
+            breadth: field("breadth"),
+            delivery: field("delivery"),
+            campus: field("campus"),
+            instructor: field("instructor"),
+            instructor_email: field("instructor_email"),
+            open_seats: field("open_seats"),
+            program_restricted: field("program_restricted"),
+            drops_close: field("drops_close"),
+            key: field("key"),
+            index: index,
+        }
+    }
+
+    /// Why an existing index can't be served, if it can't.
+    fn unusable(path: &Path, schema: &Schema) -> Option<String> {
+        if !path.is_dir() {
+            return Some(format!("No index found at {}", path.display()));
+        }
+
+        let index = match Index::open_in_dir(path) {
+            Ok(index) => index,
+            Err(e) => return Some(format!("Index couldn't be opened ({})", e)),
+        };
+
+        // An index built with a different schema can't be queried with our
+        // fields, which are looked up by name and have to be of the same type.
+        let fields = |schema: &Schema| {
+            schema.fields()
+                .iter()
+                .map(|entry| (entry.name().to_owned(), entry.field_type().clone()))
+                .collect::<Vec<_>>()
+        };
+
+        if fields(&index.schema()) != fields(schema) {
+            return Some("Index schema has changed".to_owned());
+        }
+
+        // A build that was cut off before committing leaves no courses, and
+        // no term is ever listed without any.
+        if index.searcher().num_docs() == 0 {
+            return Some("Index is empty, it may have been left partly written".to_owned());
+        }
+
+        None
+    }
+
+    /// Add a course to the index, replacing any document already added for
+    /// it so adding a course twice can't show it twice.
+    fn add_course(&self, index_writer: &mut IndexWriter, term: &str, course: &IndexedCourse) {
+        let mut doc = tantivy::Document::default();
+        doc.add_text(self.term, term);
+        doc.add_text(self.code, &course.code);
+        doc.add_bytes(self.code_bytes, course.code.as_bytes().to_vec());
+
+        let listed_subject = course.subject.as_ref().map(|name| name.to_uppercase());
+
+        if let Some(ref name) = listed_subject {
+            doc.add_text(self.subject, name);
+        }
+
+        if let Some((subject, number)) = code_parts(&course.code) {
+            if listed_subject.as_ref() != Some(&subject) {
+                doc.add_text(self.subject, &subject);
+            }
+
+            for len in 1..number.len() + 1 {
+                doc.add_text(self.number, &number[..len]);
+            }
+        }
+
+        doc.add_text(self.title, &course.title);
+        doc.add_text(self.title_words, &course.title);
+        doc.add_text(self.description, &course.description);
+
+        if let Some(ref text) = course.note {
+            doc.add_text(self.note, text);
+        }
+
+        let open_to = course.note.as_ref()
+            .and_then(|text| parse_years(text))
+            .unwrap_or_else(|| YEARS_OF_STUDY.to_vec());
+
+        for year in open_to {
+            doc.add_text(self.years, &year.to_string());
+        }
+
+        for category in &course.breadth {
+            doc.add_text(self.breadth, category);
+        }
+
+        if let Some(ref mode) = course.delivery {
+            doc.add_text(self.delivery, mode);
+        }
+
+        if let Some(ref campus) = course.campus {
+            doc.add_text(self.campus, campus);
+        }
+
+        for ins in &course.instructors {
+            doc.add_text(self.instructor, &ins.name);
+            doc.add_text(self.instructor_email, ins.email.as_ref().map_or("", String::as_str));
+        }
+
+        // A drop deadline that couldn't be read never counts as passed.
+        let deadline = course.drops_close
+            .map_or(UNKNOWN_DATE, date_number);
+
+        doc.add_u64(self.open_seats, course.open_seats as u64);
+        doc.add_u64(self.program_restricted, course.program_restricted as u64);
+        doc.add_u64(self.drops_close, deadline);
+
+        let course_key = course_key(term, &course.code);
+        doc.add_text(self.key, &course_key);
+
+        index_writer.delete_term(Term::from_field_text(self.key, &course_key));
+        index_writer.add_document(doc);
+    }
+
+    /// Every course code indexed for each term.
+    pub fn indexed_codes(&self) -> Result<HashMap<String, HashSet<String>>, Error> {
+        self.terms()
+            .into_iter()
+            .map(|term| {
+                let codes = self.courses(&term)?
+                    .into_iter()
+                    .map(|course| course.code)
+                    .collect();
+
+                Ok((term, codes))
+            })
+            .collect()
+    }
+
+    /// Add the new courses and delete the removed ones found by
+    /// `Scraper::scrape_changes`, leaving every other course as it is.
+    pub fn apply(&self, changes: &IndexChanges) -> Result<(), Error> {
+        let _in_flight = InFlight::start()?;
+        let mut index_writer = self.index.writer(100_000_000)?;
+
+        for &(ref term, ref course) in &changes.added {
+            self.add_course(&mut index_writer, term, course);
+        }
+
+        for &(ref term, ref code) in &changes.removed {
+            index_writer.delete_term(Term::from_field_text(self.key, &course_key(term, code)));
+        }
+
+        index_writer.commit()?;
+        self.index.load_searchers()?;
+        self.record_written()?;
+
+        Ok(())
+    }
+
+    /// Keep the current time as when the index was last written.
+    fn record_written(&self) -> Result<(), Error> {
+        fs::write(self.path.join(WRITTEN_FILE), Local::now().to_rfc3339())?;
+        Ok(())
+    }
+
+    /// When the index was last built or had changes applied, if it's been
+    /// recorded.
+    pub fn written_at(&self) -> Option<DateTime<Local>> {
+        let text = fs::read_to_string(self.path.join(WRITTEN_FILE)).ok()?;
+
+        DateTime::parse_from_rfc3339(text.trim())
+            .ok()
+            .map(|time| time.with_timezone(&Local))
+    }
+
+    /// Parse a filter where every word has to match the field.
+    fn filter(&self, field: Field, text: &str) -> Result<Box<Query>, Error> {
+        let mut parser = QueryParser::for_index(&self.index, vec![field]);
+        parser.set_conjunction_by_default();
+
+        let query = parser.parse_query(text)
+            .map_err(QueryError::Parse)?;
+
+        Ok(query)
+    }
+
+    /// Returns the number of courses in the index across all terms.
+    pub fn num_docs(&self) -> u64 {
+        self.index.searcher().num_docs()
+    }
+
+    /// Returns the number of courses indexed for a term.
+    pub fn count(&self, term: &str) -> Result<usize, Error> {
+        let query = TermQuery::new(
+            Term::from_field_text(self.term, term),
+            IndexRecordOption::Basic,
+        );
+
+        let mut count = CountCollector::default();
+        self.index.searcher().search(&query, &mut count)?;
+
+        Ok(count.count())
+    }
+
+    /// Returns the subjects in the search form as code and name pairs.
+    pub fn subjects(&self) -> Result<Vec<(String, String)>, Error> {
+        self.scraper.subjects()
+    }
+
+    /// Returns the missing field counts if the index was built by this process.
+    pub fn field_stats(&self) -> Option<&FieldStats> {
+        self.stats.as_ref()
+    }
+
+    /// Returns the term codes present in the index, oldest first.
+    pub fn terms(&self) -> Vec<String> {
+        let searcher = self.index.searcher();
+        let field = searcher.field(self.term);
+
+        let mut stream = field.terms();
+        let mut terms = vec![];
+
+        while stream.advance() {
+            terms.push(String::from_utf8_lossy(stream.key()).into_owned());
+        }
+
+        terms
+    }
+
+    /// The code of the newest term indexed, or of the term going on now if
+    /// nothing is indexed.
+    pub fn newest_term(&self) -> String {
+        newest_term(&self.terms())
+    }
+
+    /// The code of an indexed term picked by when it is. The current term is
+    /// the latest one indexed that has started, or the first one indexed if
+    /// none have, and the next term is the one indexed after it.
+    pub fn relative_term(&self, relative: RelativeTerm) -> Option<String> {
+        let today = TermCode::today();
+
+        let terms = self.terms()
+            .iter()
+            .filter_map(|code| TermCode::from_code(code))
+            .sorted();
+
+        let current = terms.iter()
+            .rposition(|term| *term <= today)
+            .unwrap_or(0);
+
+        let index = match relative {
+            RelativeTerm::Current => current,
+            RelativeTerm::Next => current + 1,
+        };
+
+        terms.get(index).map(TermCode::code)
+    }
+
+    /// Returns every course indexed for a term.
+    fn courses<'a>(&'a self, term: &str) -> Result<Vec<CoursePreview<'a>>, Error> {
+        let count = self.count(term)?;
+
+        let query = TermQuery::new(
+            Term::from_field_text(self.term, term),
+            IndexRecordOption::Basic,
+        );
+
+        self.collect(&query, count)
+    }
+
+    /// Scrape a course and, a few levels deep, the indexed courses named in
+    /// its prerequisites. A course already higher up the tree isn't followed
+    /// again, so prerequisites that lead back around can't loop.
+    pub fn prereq_tree(&self, course: &CoursePreview) -> Result<PrereqTree, Error> {
+        let mut path = vec![];
+        let mut budget = MAX_PREREQ_COURSES;
+
+        self.prereq_subtree(course, &mut path, &mut budget)
+    }
+
+    fn prereq_subtree(&self, preview: &CoursePreview, path: &mut Vec<String>, budget: &mut usize) -> Result<PrereqTree, Error> {
+        let course = preview.scrape()?;
+        *budget = budget.saturating_sub(1);
+
+        // Sections of a course share its prerequisites.
+        path.push(base_code(&preview.code));
+
+        let mut prereqs = vec![];
+
+        for text in &course.prereqs {
+            let mut courses = vec![];
+
+            if path.len() <= MAX_PREREQ_DEPTH {
+                for code in PREREQ_CODE_REGEX.find_iter(text) {
+                    if *budget == 0 {
+                        break;
+                    }
+
+                    let found = match self.find_code(&preview.term, code.as_str())? {
+                        Some(found) => found,
+                        None => continue,
+                    };
+
+                    if path.iter().any(|code| *code == base_code(&found.code)) {
+                        continue;
+                    }
+
+                    match self.prereq_subtree(&found, path, budget) {
+                        Ok(tree) => courses.push(tree),
+                        Err(e) => warn!("Couldn't scrape prerequisite {}: {}", found.code, e),
+                    }
+                }
+            }
+
+            prereqs.push(Prereq {
+                text: text.clone(),
+                courses: courses,
+            });
+        }
+
+        path.pop();
+
+        Ok(PrereqTree {
+            code: course.code,
+            title: course.title,
+            prereqs: prereqs,
+        })
+    }
+
+    /// Finds a section of a course named in text like a prerequisite.
+    fn find_code<'a>(&'a self, term: &str, code: &str) -> Result<Option<CoursePreview<'a>>, Error> {
+        match self.lookup(term, code)? {
+            CodeLookup::Found(courses) => Ok(courses.into_iter().next()),
+            CodeLookup::Missing(_) => Ok(None),
+        }
+    }
+
+    /// Returns a few courses from a term, spread out through the index and
+    /// different each time.
+    pub fn sample<'a>(&'a self, term: &str, n: usize) -> Result<Vec<CoursePreview<'a>>, Error> {
+        let courses = self.courses(term)?;
+
+        if courses.is_empty() || n == 0 {
+            return Ok(vec![]);
+        }
+
+        let start = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.subsec_nanos() as usize)
+            .unwrap_or(0);
+
+        let step = cmp::max(courses.len() / n, 1);
+
+        let mut sample = (0..cmp::min(n, courses.len()))
+            .map(|i| courses[(start + i * step) % courses.len()].clone())
+            .collect::<Vec<_>>();
+
+        sample.dedup_by(|c, other| c.code == other.code);
+
+        Ok(sample)
+    }
+
+    /// Confirms a course code exists for a term before anything is scraped
+    /// for it. The code can be written any way `CourseCode::parse` accepts
+    /// and a code without a section matches every section.
+    pub fn lookup<'a>(&'a self, term: &str, code: &str) -> Result<CodeLookup<'a>, Error> {
+        let parsed = CourseCode::parse(code)?;
+
+        // A whole section code is its own key, so one document is enough.
+        if let Some(ref section) = parsed.section {
+            if parsed.activity.len() != 5 {
+                let key = course_key(term, &format!("{}{}", parsed.activity, section));
+                let query = TermQuery::new(
+                    Term::from_field_text(self.key, &key),
+                    IndexRecordOption::Basic,
+                );
+
+                let found = self.collect(&query, 1)?;
+
+                if !found.is_empty() {
+                    return Ok(CodeLookup::Found(found));
+                }
+            }
+        }
+
+        let (subject, number) = match lookup_parts(&parsed.activity) {
+            Some(parts) => parts,
+            None => return Ok(CodeLookup::Missing(vec![])),
+        };
+
+        let mut found = self.numbered(term, &subject, &number)?
+            .into_iter()
+            .filter(|course| parsed.matches(&course.code))
+            .collect::<Vec<_>>();
+
+        if !found.is_empty() {
+            found.sort_by_key(CoursePreview::sort_key);
+            return Ok(CodeLookup::Found(found));
+        }
+
+        // Suggest the codes in the subject closest to a typo'd one, sharing
+        // as much of the start of the number as any of them do.
+        for len in (0..number.len()).rev() {
+            let others = self.numbered(term, &subject, &number[..len])?;
+
+            if !others.is_empty() {
+                let suggestions = others.into_iter()
+                    .map(|course| course.code)
+                    .sorted()
+                    .into_iter()
+                    .dedup()
+                    .take(5)
+                    .collect();
+
+                return Ok(CodeLookup::Missing(suggestions));
+            }
+        }
+
+        Ok(CodeLookup::Missing(vec![]))
+    }
+
+    /// Courses in a term in a subject with a number starting with the digits
+    /// given, or every course in the subject without any.
+    fn numbered<'a>(&'a self, term: &str, subject: &str, number: &str) -> Result<Vec<CoursePreview<'a>>, Error> {
+        let mut queries = vec![
+            (Occur::Must, Box::new(TermQuery::new(
+                Term::from_field_text(self.term, term),
+                IndexRecordOption::Basic,
+            )) as Box<Query>),
+            (Occur::Must, Box::new(TermQuery::new(
+                Term::from_field_text(self.subject, subject),
+                IndexRecordOption::Basic,
+            )) as Box<Query>),
+        ];
+
+        if !number.is_empty() {
+            queries.push((Occur::Must, Box::new(TermQuery::new(
+                Term::from_field_text(self.number, number),
+                IndexRecordOption::Basic,
+            )) as Box<Query>));
+        }
+
+        let query = BooleanQuery::from(queries);
+
+        let mut count = CountCollector::default();
+        self.index.searcher().search(&query, &mut count)?;
+
+        self.collect(&query, count.count())
+    }
+
+    /// The previews of up to a number of courses matching a query.
+    fn collect<'a>(&'a self, query: &Query, limit: usize) -> Result<Vec<CoursePreview<'a>>, Error> {
+        if limit == 0 {
+            return Ok(vec![]);
+        }
+
+        let mut top = TopCollector::with_limit(limit);
+        let searcher = self.index.searcher();
+        searcher.search(query, &mut top)?;
+
+        top.docs()
+            .iter()
+            .map(|doc| self.preview(&searcher.doc(doc)?))
+            .collect()
+    }
+
+    /// Builds a course preview from a stored document.
+    fn preview(&self, doc: &tantivy::Document) -> Result<CoursePreview, Error> {
+        let field = |field, name| {
+            doc.get_first(field)
+                .map(|value| value.text().to_owned())
+                .ok_or_else(|| ParseError::new(name))
+        };
+
+        Ok(CoursePreview {
+            scraper: &self.scraper,
+            term: field(self.term, ParseField::StoredTerm)?,
+            code: field(self.code, ParseField::StoredCode)?,
+            title: field(self.title, ParseField::StoredTitle)?,
+            campus: doc.get_first(self.campus).map(|value| value.text().to_owned()),
+            sections: 1,
+        })
+    }
+
+    /// Returns the instructors teaching in a term whose name matches, each
+    /// with the courses they teach. Sections list the same instructor under
+    /// spellings like "J. Smith" and "John Smith", so they are grouped by
+    /// their email when they have one and by initial and last name otherwise.
+    pub fn professors<'a>(&'a self, term: &str, name: &str) -> Result<Vec<Professor<'a>>, Error> {
+        // Find the candidates by the longest word of the name, which is
+        // usually the last name, and check the rest of it afterwards.
+        let word = match name.split(|c: char| !c.is_alphanumeric()).max_by_key(|word| word.len()) {
+            Some(word) if !word.is_empty() => word,
+            _ => return Ok(vec![]),
+        };
+
+        let count = self.count(term)?;
+
+        if count == 0 {
+            return Ok(vec![]);
+        }
+
+        let query = BooleanQuery::from(vec![
+            (Occur::Must, Box::new(TermQuery::new(
+                Term::from_field_text(self.term, term),
+                IndexRecordOption::Basic,
+            )) as Box<Query>),
+            (Occur::Must, self.filter(self.instructor, word)?),
+        ]);
+
+        let mut top = TopCollector::with_limit(count);
+        let searcher = self.index.searcher();
+        searcher.search(&query, &mut top)?;
+
+        let mut sections = vec![];
+
+        for address in top.docs() {
+            let doc = searcher.doc(&address)?;
+            let course = self.preview(&doc)?;
+
+            let names = doc.get_all(self.instructor);
+            let emails = doc.get_all(self.instructor_email);
+
+            for (i, ins) in names.iter().enumerate() {
+                let ins = ins.text();
+
+                if !name_matches(name, ins) {
+                    continue;
+                }
+
+                let email = emails.get(i)
+                    .map(|email| email.text())
+                    .filter(|email| !email.is_empty());
+
+                sections.push((ins.to_owned(), email.map(str::to_owned), course.clone()));
+            }
+        }
+
+        let professors = group_instructors(sections)
+            .into_iter()
+            .map(|(name, courses)| {
+                let mut courses = courses;
+                courses.sort_by_key(CoursePreview::sort_key);
+                courses.dedup_by(|c, other| c.code == other.code);
+
+                Professor {
+                    name: name,
+                    courses: courses,
+                }
+            })
+            .collect();
+
+        Ok(professors)
+    }
+
+    /// Returns the best courses found in the index for a term, narrowed
+    /// down by the options, with how many matched in all. Each course is
+    /// listed once, as its best matching section, unless the options ask for
+    /// every section.
+    pub fn query<'a>(&'a self, term: &str, query: &str, options: &QueryOptions) -> Result<SearchResults<'a>, Error> {
+        let (text, _) = split_fielded(query);
+
+        // Searchers come from a pool tantivy refills after every commit, so
+        // one is leased per query and used for all of it. That way a query
+        // holds one searcher rather than several and sees the same segments
+        // throughout even if a reindex commits partway.
+        let searcher = self.index.searcher();
+
+        let matching = self.matching(&searcher, term, query, options)?;
+        let total = matching.len();
+
+        // Only the documents on the page are loaded.
+        let hits = matching.into_iter()
+            .skip(options.offset)
+            .take(options.limit)
+            .collect::<Vec<_>>();
+
+        let addresses = hits.iter()
+            .map(|hit| hit.address)
+            .collect::<Vec<_>>();
+
+        let matched = self.matched_fields(&searcher, &text, &addresses)?;
+
+        let hits = hits.into_iter()
+            .zip(matched)
+            .map(|(hit, matched_fields)| {
+                let mut preview = self.preview(&searcher.doc(&hit.address)?)?;
+                preview.sections = hit.sections;
+
+                Ok(SearchHit {
+                    preview: preview,
+                    score: hit.score,
+                    matched_fields: matched_fields,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(SearchResults {
+            hits: hits,
+            total: total,
+        })
+    }
+
+    /// Every course matching a query in the order `query` lists them, read
+    /// from the code fast field without loading any documents.
+    fn matching(&self, searcher: &Searcher, term: &str, query: &str, options: &QueryOptions) -> Result<Vec<Match>, Error> {
+        let full_query = self.build_query(term, query, options)?;
+        let (text, _) = split_fielded(query);
+
+        // A browse has no scores to cut off.
+        let min_score = if text.is_empty() { None } else { options.min_score };
+
+        let mut collector = MatchCollector::new(self.code_bytes, !text.is_empty(), min_score);
+        searcher.search(&full_query, &mut collector)?;
+
+        let mut hits = collector.matches;
+
+        // Every course matches a browse without a query equally well, so
+        // those are listed by code to keep the pages in order. Otherwise
+        // hits come best first.
+        if text.is_empty() {
+            hits.sort_by_key(|hit| code_sort_key(&hit.code));
+        } else {
+            hits.sort_by(|a, b| {
+                b.score.partial_cmp(&a.score)
+                    .unwrap_or(cmp::Ordering::Equal)
+                    .then(a.address.cmp(&b.address))
+            });
+        }
+
+        if options.sections {
+            return Ok(hits);
+        }
+
+        // Keep the first section of each course, which is its best match,
+        // and count the rest.
+        let mut courses: Vec<Match> = vec![];
+        let mut positions = HashMap::new();
+
+        for hit in hits {
+            let i = *positions.entry(base_code(&hit.code)).or_insert(courses.len());
+
+            if i == courses.len() {
+                courses.push(hit);
+            } else {
+                courses[i].sections += 1;
+            }
+        }
+
+        Ok(courses)
+    }
+
+    /// Returns the courses an instructor teaches in a term, in order by code.
+    pub fn query_by_instructor<'a>(&'a self, term: &str, name: &str, limit: usize) -> Result<Vec<CoursePreview<'a>>, Error> {
+        let options = QueryOptions {
+            instructor: Some(name.to_owned()),
+            limit: limit,
+            ..QueryOptions::default()
+        };
+
+        let courses = self.query(term, "", &options)?
+            .hits
+            .into_iter()
+            .map(|hit| hit.preview)
+            .collect();
+
+        Ok(courses)
+    }
+
+    /// Returns the fields the user's query matches in for each of the
+    /// documents.
+    fn matched_fields(&self, searcher: &Searcher, query: &str, docs: &[DocAddress]) -> Result<Vec<Vec<&'static str>>, Error> {
+        let fields = [
+            ("code", self.code),
+            ("title", self.title),
+            ("title", self.title_words),
+            ("description", self.description),
+            ("note", self.note),
+        ];
+
+        let mut matched = vec![vec![]; docs.len()];
+
+        if query.trim().is_empty() {
+            return Ok(matched);
+        }
+
+        for &(name, field) in &fields {
+            // A word too short for any grams leaves nothing to search in the
+            // code and title, which the parser reports as a syntax error.
+            // The whole query already parsed, so nothing matched there.
+            let weight = match QueryParser::for_index(&self.index, vec![field]).parse_query(query) {
+                Ok(query) => query.weight(searcher, false)?,
+                Err(QueryParserError::SyntaxError) => continue,
+                Err(e) => return Err(QueryError::Parse(e).into()),
+            };
+
+            for (i, &DocAddress(segment, doc)) in docs.iter().enumerate() {
+                let mut scorer = weight.scorer(searcher.segment_reader(segment))?;
+
+                if scorer.skip_next(doc) == SkipResult::Reached && !matched[i].contains(&name) {
+                    matched[i].push(name);
+                }
+            }
+        }
+
+        Ok(matched)
+    }
+
+    /// Returns the number of courses a query matches, counted the way
+    /// `query` lists them. The limit in the options doesn't apply.
+    pub fn query_count(&self, term: &str, query: &str, options: &QueryOptions) -> Result<usize, Error> {
+        let (text, _) = split_fielded(query);
+        let searcher = self.index.searcher();
+
+        // Sections are counted as they are unless some would be cut off by
+        // their score. Courses are counted by the distinct codes in the fast
+        // field, so neither loads any documents.
+        if options.sections && (text.is_empty() || options.min_score.is_none()) {
+            return count_matches(&searcher, &self.build_query(term, query, options)?);
+        }
+
+        Ok(self.matching(&searcher, term, query, options)?.len())
+    }
+
+    /// Combines the user's query with the term and any filters, including
+    /// those written into the query like `subject:COMP`.
+    fn build_query(&self, term: &str, query: &str, options: &QueryOptions) -> Result<BooleanQuery, Error> {
+        let (query, fielded) = split_fielded(query);
+
+        // The query for the current term (semester).
+        let term_query = TermQuery::new(
+            Term::from_field_text(self.term, term),
+            IndexRecordOption::Basic,
+        );
+
+        let mut clauses: Vec<(Occur, Box<Query>)> = vec![
+            (Occur::Must, Box::new(term_query))
+        ];
+
+        // Without a query every course passing the filters matches.
+        if !query.trim().is_empty() {
+            clauses.push((Occur::Must, self.user_query(&query)?));
+        }
+
+        if let Some(ref subject) = options.subject {
+            let subject_query = TermQuery::new(
+                Term::from_field_text(self.subject, &subject.to_uppercase()),
+                IndexRecordOption::Basic,
+            );
+
+            clauses.push((Occur::Must, Box::new(subject_query)));
+        }
+
+        for (key, value) in fielded {
+            let field_query: Box<Query> = match key {
+                QueryField::Subject => Box::new(TermQuery::new(
+                    Term::from_field_text(self.subject, &value.to_uppercase()),
+                    IndexRecordOption::Basic,
+                )),
+                QueryField::Number => Box::new(TermQuery::new(
+                    Term::from_field_text(self.number, &value),
+                    IndexRecordOption::Basic,
+                )),
+                QueryField::Title => {
+                    check_complexity(&value)?;
+                    self.filter(self.title_words, &value)?
+                }
+            };
+
+            clauses.push((Occur::Must, field_query));
+        }
+
+        if let Some(ref note) = options.note {
+            clauses.push((Occur::Must, self.filter(self.note, note)?));
+        }
+
+        if let Some(year) = options.eligible {
+            let year_query = TermQuery::new(
+                Term::from_field_text(self.years, &year.to_string()),
+                IndexRecordOption::Basic,
+            );
+
+            clauses.push((Occur::Must, Box::new(year_query)));
+        }
+
+        if let Some(ref breadth) = options.breadth {
+            clauses.push((Occur::Must, self.filter(self.breadth, breadth)?));
+        }
+
+        if let Some(ref instructor) = options.instructor {
+            clauses.push((Occur::Must, self.filter(self.instructor, instructor)?));
+        }
+
+        if let Some(ref delivery) = options.delivery {
+            clauses.push((Occur::Must, self.filter(self.delivery, delivery)?));
+        }
+
+        let flag = |field, value| {
+            Box::new(TermQuery::new(
+                Term::from_field_u64(field, value),
+                IndexRecordOption::Basic,
+            ))
+        };
+
+        if options.open {
+            clauses.push((Occur::Must, flag(self.open_seats, 1)));
+        }
+
+        if options.enrollable {
+            let today = date_number(Local::today().naive_local());
+
+            clauses.push((Occur::Must, flag(self.open_seats, 1)));
+            clauses.push((Occur::Must, flag(self.program_restricted, 0)));
+            clauses.push((Occur::Must, Box::new(RangeQuery::new_u64(self.drops_close, today..u64::max_value()))));
+        }
+
+        // Excluded words are matched as whole words, so excluding "lab"
+        // doesn't also drop "Syllabus".
+        for text in &options.exclude {
+            check_complexity(text)?;
+
+            let mut parser = QueryParser::for_index(&self.index, vec![self.title_words, self.description, self.note]);
+            parser.set_conjunction_by_default();
+
+            let query = parser.parse_query(text)
+                .map_err(QueryError::Parse)?;
+
+            clauses.push((Occur::MustNot, query));
+        }
+
+        Ok(BooleanQuery::from(clauses))
+    }
+
+    /// Parses the query string the user has entered.
+    fn user_query(&self, query: &str) -> Result<Box<Query>, Error> {
+        check_complexity(query)?;
+
+        let default_fields = vec![self.code, self.title, self.description, self.note];
+        let mut user_query = QueryParser::for_index(&self.index, default_fields)
+            .parse_query(query)
+            .map_err(QueryError::Parse)?;
+
+        // Words like "ai" are too short to have any grams, so they would
+        // silently match nothing in the code and title. Those are also
+        // matched against whole words of the title.
+        let has_short_word = query.split(|c: char| !c.is_alphanumeric())
+            .any(|word| !word.is_empty() && word.chars().count() < NGRAM_LEN);
+
+        if has_short_word {
+            let word_fields = vec![self.title_words, self.description, self.note];
+            let word_query = QueryParser::for_index(&self.index, word_fields)
+                .parse_query(query)
+                .map_err(QueryError::Parse)?;
+
+            user_query = Box::new(BooleanQuery::from(vec![
+                (Occur::Should, user_query),
+                (Occur::Should, word_query),
+            ]));
+        }
+
+        Ok(user_query)
+    }
+}
+
+/// A field that can be searched by writing it into a query, like
+/// `subject:COMP`.
+#[derive(Clone, Copy)]
+enum QueryField {
+    Subject,
+    Number,
+    Title,
+}
+
+/// Splits words like `subject:COMP` out of a query, returning the rest of
+/// the query and the field each of those words searches. Other words with a
+/// colon are searched as plain words, so `foo:bar` is `foo bar`.
+fn split_fielded(query: &str) -> (String, Vec<(QueryField, String)>) {
+    let mut words = vec![];
+    let mut fielded = vec![];
+
+    for word in query.split_whitespace() {
+        let (key, value) = match word.find(':') {
+            Some(i) => (&word[..i], &word[i + 1..]),
+            None => {
+                words.push(word.to_owned());
+                continue;
+            }
+        };
+
+        let field = match key.to_lowercase().as_str() {
+            "subject" => Some(QueryField::Subject),
+            "number" => Some(QueryField::Number),
+            "title" => Some(QueryField::Title),
+            _ => None,
+        };
+
+        match field {
+            Some(field) if !value.is_empty() => fielded.push((field, value.to_owned())),
+            _ => words.push(word.replace(':', " ")),
+        }
+    }
+
+    (words.join(" "), fielded)
+}
+
+/// Filters and limits for `CourseIndex::query`.
+#[derive(Clone)]
+pub struct QueryOptions {
+    /// Only courses in this subject, like "COMP". With a subject the query
+    /// can be empty to list the whole subject.
+    pub subject: Option<String>,
+    /// Only courses with a note mentioning this.
+    pub note: Option<String>,
+    /// Only courses open to students in this year of study.
+    pub eligible: Option<u8>,
+    /// Only courses counting towards this breadth category.
+    pub breadth: Option<String>,
+    /// Only courses taught by this instructor.
+    pub instructor: Option<String>,
+    /// Only courses offered in this delivery mode.
+    pub delivery: Option<String>,
+    /// Only sections with open seats when the index was built.
+    pub open: bool,
+    /// Only sections with open seats, no program restriction, and a drop
+    /// deadline that hasn't passed.
+    pub enrollable: bool,
+    /// Leave out courses matching any of these.
+    pub exclude: Vec<String>,
+    /// Leave out courses that match the query with a lower score, so vague
+    /// queries don't list barely related courses.
+    pub min_score: Option<f32>,
+    /// List every matching section instead of each course once.
+    pub sections: bool,
+    /// Most courses to return.
+    pub limit: usize,
+    /// Number of the best courses to skip before those returned.
+    pub offset: usize,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        QueryOptions {
+            subject: None,
+            note: None,
+            eligible: None,
+            breadth: None,
+            instructor: None,
+            delivery: None,
+            open: false,
+            enrollable: false,
+            exclude: vec![],
+            min_score: None,
+            sections: false,
+            limit: DEFAULT_LIMIT,
+            offset: 0,
+        }
+    }
+}
+
+/// Outcome of looking up a course code with `CourseIndex::lookup`.
+pub enum CodeLookup<'a> {
+    /// Sections of the code given.
+    Found(Vec<CoursePreview<'a>>),
+    /// Indexed codes sharing the longest prefix with the one given.
+    Missing(Vec<String>),
+}
+
+/// Which terms and subjects to index. An empty list means all of them.
+#[derive(Default)]
+pub struct IndexFilter {
+    /// Term codes like "20185".
+    pub terms: Vec<String>,
+    /// Subject codes like "COMP".
+    pub subjects: Vec<String>,
+}
+
+impl IndexFilter {
+    /// The terms out of those in the search form that are indexed, warning
+    /// about any the filter names that aren't there.
+    fn indexed_terms(&self, listed: Vec<String>) -> Vec<String> {
+        for code in &self.terms {
+            if !listed.contains(code) {
+                warn!("Term {} isn't in the search form and won't be indexed", code);
+            }
+        }
+
+        listed.into_iter()
+            .filter(|code| self.terms.is_empty() || self.terms.contains(code))
+            .collect()
+    }
+}
+
+/// Outcome of checking the portal layout with `Scraper::validate`.
+pub struct Validation {
+    /// Number of courses that scraped successfully.
+    pub checked: usize,
+    /// The first error encountered, if any.
+    pub error: Option<Error>,
+}
+
+/// Where the course search and the staff directory are, which can be pointed
+/// at a mirror for testing, and how hard to try reaching them.
+#[derive(Clone)]
+pub struct Portal {
+    pub search_url: String,
+    pub directory_url: String,
+    pub directory_search_url: String,
+    /// Times a request that failed on the portal's end is tried again.
+    pub retries: u32,
+    /// Milliseconds before the first retry, doubling for each one after up
+    /// to a minute.
+    pub retry_delay_ms: u64,
+    /// Minutes a scraped course is reused before it's scraped again.
+    pub cache_minutes: u64,
+    /// Most requests made at once while indexing.
+    pub max_requests: usize,
+    /// Seconds a request can take to connect, send, or read before it's
+    /// given up on and tried again.
+    pub timeout_secs: u64,
+}
+
+impl Default for Portal {
+    fn default() -> Self {
+        Portal {
+            search_url: SEARCH_URL.to_owned(),
+            directory_url: DIRECTORY_SERVICES.to_owned(),
+            directory_search_url: DIRECTORY_SEARCH.to_owned(),
+            retries: DEFAULT_RETRIES,
+            retry_delay_ms: DEFAULT_RETRY_DELAY_MS,
+            cache_minutes: DEFAULT_CACHE_MINUTES,
+            max_requests: DEFAULT_MAX_REQUESTS,
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+        }
+    }
+}
+
+/// Scraped courses by term and code, each reused for a while after it was
+/// scraped.
+struct CourseCache {
+    ttl: Duration,
+    courses: Mutex<HashMap<(String, String), (Instant, Course)>>,
+}
+
+impl CourseCache {
+    fn new(minutes: u64) -> Self {
+        CourseCache {
+            ttl: Duration::from_secs(minutes * 60),
+            courses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A course if it was scraped recently enough to reuse.
+    fn get(&self, term: &str, full_code: &str) -> Option<Course> {
+        self.courses.lock().unwrap()
+            .get(&(term.to_owned(), full_code.to_owned()))
+            .filter(|&&(scraped, _)| scraped.elapsed() < self.ttl)
+            .map(|&(_, ref course)| course.clone())
+    }
+
+    /// Whether a course was scraped recently enough to reuse.
+    fn contains(&self, term: &str, full_code: &str) -> bool {
+        self.courses.lock().unwrap()
+            .get(&(term.to_owned(), full_code.to_owned()))
+            .map_or(false, |&(scraped, _)| scraped.elapsed() < self.ttl)
+    }
+
+    /// Keep a scraped course, dropping any courses that have expired.
+    fn keep(&self, term: &str, full_code: &str, course: Course) {
+        let mut courses = self.courses.lock().unwrap();
+        courses.retain(|_, &mut (scraped, _)| scraped.elapsed() < self.ttl);
+        courses.insert((term.to_owned(), full_code.to_owned()), (Instant::now(), course));
+    }
+
+    /// Scrape courses with a function on a pool and keep them. Gives the
+    /// number that could be scraped.
+    fn prefetch<F>(&self, pool: &ThreadPool, courses: Vec<(String, String)>, scrape: F) -> usize
+        where F: Fn(&str, &str) -> Result<Course, Error> + Send + Sync
+    {
+        let scraped = limited(pool, courses, |(term, code)| {
+            match scrape(&term, &code) {
+                Ok(course) => {
+                    self.keep(&term, &code, course);
+                    true
+                }
+                Err(e) => {
+                    warn!("Couldn't prefetch {}: {}", code, e);
+                    false
+                }
+            }
+        });
+
+        scraped.into_iter().filter(|&ok| ok).count()
+    }
+}
+
+/// Scrapes the portal, keeping the subject list once it has been scraped and
+/// courses for a few minutes after they are. Reindexing starts a new scraper,
+/// so nothing scraped before it is reused.
+pub struct Scraper {
+    client: Client,
+    /// Subject codes and names, once they've been scraped or loaded.
+    subjects: Mutex<Option<Vec<(String, String)>>>,
+    /// The file subjects are kept in across restarts, if there's an index
+    /// to keep them next to.
+    subjects_path: Option<PathBuf>,
+    portal: Portal,
+    cache: CourseCache,
+    /// Threads scrapes of many courses are run on, as many as the portal
+    /// is sent requests at once.
+    pool: ThreadPool,
+    /// Times each course was looked up by term and code, to tell which are
+    /// worth scraping ahead of time.
+    lookups: Mutex<HashMap<(String, String), usize>>,
+}
+
+impl Key for Scraper {
+    type Value = Self;
+}
+
+impl Scraper {
+    /// A scraper for an index kept in a directory, which keeps the subjects
+    /// there too.
+    pub fn for_index(portal: Portal, path: &Path) -> Self {
+        Scraper {
+            subjects_path: Some(path.join(SUBJECTS_FILE)),
+            ..Scraper::new(portal)
+        }
+    }
+
+    /// A scraper that keeps the subjects only until it's dropped.
+    pub fn new(portal: Portal) -> Self {
+        let client = Client::builder()
+            .redirect(RedirectPolicy::custom(|attempt| {
+                // Stop at a login page so `check_session` can report it
+                // instead of the login page being scraped.
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.too_many_redirects()
+                } else if is_login_url(attempt.url()) {
+                    attempt.stop()
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .timeout(Duration::from_secs(portal.timeout_secs))
+            .build()
+            .expect("Couldn't build HTTP client.");
+
+        Scraper {
+            client: client,
+            subjects: Mutex::new(None),
+            subjects_path: None,
+            cache: CourseCache::new(portal.cache_minutes),
+            pool: request_pool(portal.max_requests),
+            portal: portal,
+            lookups: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Scrape all information for a course, reusing a scrape from the last
+    /// `cache_minutes` if there is one.
+    pub fn scrape_cached(&self, term: &str, full_code: &str) -> Result<Course, Error> {
+        match self.cache.get(term, full_code) {
+            Some(course) => Ok(course),
+            None => self.scrape_fresh(term, full_code),
+        }
+    }
+
+    /// Whether `scrape_cached` can reuse a recent scrape of a course.
+    pub fn is_cached(&self, term: &str, full_code: &str) -> bool {
+        self.cache.contains(term, full_code)
+    }
+
+    /// Scrape all information for a course from the portal and keep it for
+    /// `scrape_cached`, dropping any courses that have expired.
+    pub fn scrape_fresh(&self, term: &str, full_code: &str) -> Result<Course, Error> {
+        let course = self.scrape_full(term, full_code)
+            .map_err(|e| ScrapeError {
+                term: term.to_owned(),
+                code: full_code.to_owned(),
+                reason: e.to_string(),
+            })?;
+
+        self.cache.keep(term, full_code, course.clone());
+        Ok(course)
+    }
+
+    /// Count a course being looked up by a user.
+    fn count_lookup(&self, term: &str, full_code: &str) {
+        *self.lookups.lock().unwrap()
+            .entry((term.to_owned(), full_code.to_owned()))
+            .or_insert(0) += 1;
+    }
+
+    /// Times each course was looked up, by term and code.
+    pub fn lookups(&self) -> HashMap<(String, String), usize> {
+        self.lookups.lock().unwrap().clone()
+    }
+
+    /// Add to the lookups counted so far, like those of the scraper of an
+    /// index that was rebuilt.
+    pub fn add_lookups(&self, lookups: HashMap<(String, String), usize>) {
+        let mut counted = self.lookups.lock().unwrap();
+
+        for (course, times) in lookups {
+            *counted.entry(course).or_insert(0) += times;
+        }
+    }
+
+    /// The term and code of up to `max` of the most looked up courses, most
+    /// looked up first.
+    pub fn popular(&self, max: usize) -> Vec<(String, String)> {
+        most_looked_up(&self.lookups.lock().unwrap(), max)
+    }
+
+    /// Scrape courses into the cache ahead of time, as many at once as while
+    /// indexing, so the first lookups of them are quick. Gives the number
+    /// that could be scraped.
+    pub fn prefetch(&self, courses: Vec<(String, String)>) -> usize {
+        self.cache.prefetch(&self.pool, courses, |term, code| self.scrape_full(term, code))
+    }
+
+    /// Send a request to the portal and read the page it answers with,
+    /// trying again with exponential backoff when the request times out,
+    /// can't connect, or fails on the portal's end. Other failures, like a
+    /// 4xx status or a login page, aren't tried again.
+    /// Gives the URL the page ended up at along with its HTML.
+    fn get_with_retry<F>(&self, mut send: F) -> Result<(String, String), Error>
+        where F: FnMut(&Client) -> ::reqwest::Result<Response>
+    {
+        let mut attempt = 0;
+
+        loop {
+            let result = send(&self.client)
+                .map_err(Error::from)
+                .and_then(|resp| {
+                    let status = resp.status();
+
+                    if status.is_client_error() || status.is_server_error() {
+                        return Err(StatusError(status.as_u16()).into());
+                    }
+
+                    let mut resp = check_session(resp)?;
+                    Ok((resp.url().to_string(), resp.text()?))
+                });
+
+            let e = match result {
+                Ok(text) => return Ok(text),
+                Err(e) => e,
+            };
+
+            if is_timeout(&e) {
+                warn!("Portal request timed out after {}s", self.portal.timeout_secs);
+            }
+
+            if attempt >= self.portal.retries || !is_transient(&e) {
+                return Err(e);
+            }
+
+            let delay = retry_delay(self.portal.retry_delay_ms, attempt);
+            attempt += 1;
+
+            warn!("Portal request failed, trying again in {}ms ({}/{}): {}", delay, attempt, self.portal.retries, e);
+            thread::sleep(Duration::from_millis(delay));
+        }
+    }
+
+    /// Returns the subjects in the search form as code and name pairs. They
+    /// are scraped once and kept in a file in the index directory after that.
+    pub fn subjects(&self) -> Result<Vec<(String, String)>, Error> {
+        if let Some(ref subjects) = *self.subjects.lock().unwrap() {
+            return Ok(subjects.clone());
+        }
+
+        let path = match self.subjects_path {
+            Some(ref path) => path,
+            None => return self.refresh_subjects(),
+        };
+
+        let subjects: Vec<(String, String)> = match File::open(path) {
+            Ok(file) => ::serde_json::from_reader(file)?,
+            Err(ref e) if e.kind() == ::std::io::ErrorKind::NotFound => return self.refresh_subjects(),
+            Err(e) => return Err(e.into()),
+        };
+
+        *self.subjects.lock().unwrap() = Some(subjects.clone());
+
+        Ok(subjects)
+    }
+
+    /// Scrape the subjects again, replacing the kept ones.
+    pub fn refresh_subjects(&self) -> Result<Vec<(String, String)>, Error> {
+        let subjects = self.scrape_subjects()?;
+
+        if let Some(ref path) = self.subjects_path {
+            let tmp = path.with_extension("json.tmp");
+            ::serde_json::to_writer_pretty(File::create(&tmp)?, &subjects)?;
+            fs::rename(tmp, path)?;
+        }
+
+        *self.subjects.lock().unwrap() = Some(subjects.clone());
+
+        Ok(subjects)
+    }
+
+    /// Scrape the code and name of every subject in the search form.
+    fn scrape_subjects(&self) -> Result<Vec<(String, String)>, Error> {
+        let (url, resp) = self.get_with_retry(|client| {
+            client.get(&self.portal.search_url)
+                .query(BASE_QUERY)
+                .send()
+        })?;
+
+        let doc = Document::from(resp.as_ref());
+
+        let subjects = doc.find({
+                And(Name("select"), Attr("name", "courseSearchForm.subject"))
+            })
+            .next()
+            .ok_or_else(|| ParseError::at(ParseField::SubjectList, &url, &resp))?
+            .children()
+            .filter(|node| node.is(Name("option")))
+            .filter_map(|node| {
+                // The option for every subject has a blank value.
+                let code = node.attr("value")?.trim();
+
+                let name = node.find(Text)
+                    .flat_map(|node| node.as_text())
+                    .flat_map(str::split_whitespace)
+                    .join(" ");
+
+                if code.is_empty() || name.is_empty() {
+                    None
+                } else {
+                    Some((code.to_owned(), name))
+                }
+            })
+            .collect();
+
+        Ok(subjects)
+    }
+
+    /// Scrape every term and subject the filter allows, leaving out terms
+    /// that have no courses published.
+    fn scrape(&self, filter: &IndexFilter) -> Result<Vec<(String, Vec<IndexedCourse>)>, Error> {
+        let mut data = vec![];
+        let mut skipped = vec![];
+
+        for code in self.scrape_indexed_terms(filter)? {
+            let courses = self.scrape_courses(&code, &filter.subjects)?;
+
+            if courses.is_empty() {
+                skipped.push(code);
+            } else {
+                data.push((code, courses));
+            }
+        }
+
+        // Logged so a scraper that quietly finds nothing gets noticed.
+        if !skipped.is_empty() {
+            warn!("Skipping terms with no courses listed: {}", skipped.join(", "));
+        }
+
+        Ok(data)
+    }
+
+    /// Scrape the codes of the terms in the search form that the filter lets
+    /// through.
+    fn scrape_indexed_terms(&self, filter: &IndexFilter) -> Result<Vec<String>, Error> {
+        let terms = self.scrape_terms()?
+            .into_iter()
+            .map(|(code, _)| code)
+            .collect();
+
+        Ok(filter.indexed_terms(terms))
+    }
+
+    /// Compare the courses listed in each term of the search form to the
+    /// codes already indexed, only scraping the courses that are new. Terms
+    /// that aren't in the search form anymore are left alone.
+    pub fn scrape_changes(&self, filter: &IndexFilter, indexed: &HashMap<String, HashSet<String>>) -> Result<IndexChanges, Error> {
+        let mut changes = IndexChanges {
+            added: vec![],
+            removed: vec![],
+        };
+
+        let none = HashSet::new();
+
+        for term in self.scrape_indexed_terms(filter)? {
+            let listed = self.scrape_listed(&term, &filter.subjects)?;
+            let indexed = indexed.get(&term).unwrap_or(&none);
+
+            let added = listed.iter()
+                .filter(|&&(ref code, _)| !indexed.contains(code))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let added = self.scrape_listed_courses(&term, added)?;
+            changes.added.extend(added.into_iter().map(|course| (term.clone(), course)));
+
+            changes.removed.extend({
+                indexed.iter()
+                    .filter(|&code| !listed.iter().any(|&(ref listed, _)| listed == code))
+                    .map(|code| (term.clone(), code.clone()))
+            });
+        }
+
+        Ok(changes)
+    }
+
+    /// Scrape the code and name of every term in the search form.
+    pub fn scrape_terms(&self) -> Result<Vec<(String, String)>, Error> {
+        let (url, resp) = self.get_with_retry(|client| {
+            client.get(&self.portal.search_url)
+                .query(BASE_QUERY)
+                .send()
+        })?;
+
+        let doc = Document::from(resp.as_ref());
+
+        let select = doc.find({
+                And(Name("select"), Attr("id", "ExecuteCourseSearch_acadtermCode"))
+            })
+            .next();
+
+        let select = match select {
+            Some(select) => select,
+            None => {
+                let title = doc.find(Name("title"))
+                    .next()
+                    .map(|node| node.text().split_whitespace().join(" "))
+                    .unwrap_or_default();
+
+                return Err(FormError {
+                    title: title,
+                }.into());
+            }
+        };
+
+        select.children()
+            .filter(|node| node.is(Name("option")))
+            .map(|node| {
+                let code = node.attr("value")
+                    .ok_or_else(|| ParseError::at(ParseField::TermCodeValue, &url, &node.html()))?;
+
+                let name = node.find(Text)
+                    .flat_map(|node| node.as_text())
+                    .flat_map(str::split_whitespace)
+                    .join(" ");
+
+                if name.is_empty() {
+                    return Err(ParseError::at(ParseField::TermCodeName, &url, &node.html()).into());
+                }
+
+                Ok((code.to_owned(), name))
+            })
+            .collect::<Result<Vec<_>, Error>>()
+    }
+
+    /// Scrape all courses for a term, or only those in `subjects` if it
+    /// isn't empty.
+    /// Courses are listed a subject at a time so each knows its subject,
+    /// unless there is no subject list to go by.
+    fn scrape_courses(&self, term: &str, subjects: &[String]) -> Result<Vec<IndexedCourse>, Error> {
+        let codes = self.scrape_listed(term, subjects)?;
+        self.scrape_listed_courses(term, codes)
+    }
+
+    /// Scrape the code of every course in a term, or only those in
+    /// `subjects` if it isn't empty, with the subject each was listed under.
+    fn scrape_listed(&self, term: &str, subjects: &[String]) -> Result<Vec<(String, Option<String>)>, Error> {
+        let subjects = if subjects.is_empty() {
+            self.subjects()
+                .map(|subjects| subjects.into_iter().map(|(code, _)| code).collect())
+                .unwrap_or_else(|e| {
+                    warn!("Listing courses without their subjects: {}", e);
+                    vec![]
+                })
+        } else {
+            subjects.to_vec()
+        };
+
+        let mut codes = Vec::new();
+
+        if subjects.is_empty() {
+            codes.extend(self.scrape_codes(term, " ")?.into_iter().map(|code| (code, None)));
+        } else {
+            for subject in subjects {
+                let listed = self.scrape_codes(term, &subject)?;
+                codes.extend(listed.into_iter().map(|code| (code, Some(subject.clone()))));
+            }
+        }
+
+        Ok(codes)
+    }
+
+    /// Scrape the courses listed by `scrape_listed`.
+    fn scrape_listed_courses(&self, term: &str, codes: Vec<(String, Option<String>)>) -> Result<Vec<IndexedCourse>, Error> {
+        limited(&self.pool, codes, |(code, subject)| {
+                let mut course = self.scrape_basic(term, &code)?;
+                course.subject = subject;
+                Ok(course)
+            })
+            .into_iter()
+            .collect()
+    }
+
+    /// Scrape every section of a term in a batch, a few at a time, reusing
+    /// any scraped in the last `cache_minutes`. The portal has no page with
+    /// the seats of many sections at once, so each is still its own request.
+    /// A section that can't be scraped gets its error instead.
+    pub fn scrape_sections(&self, term: &str, codes: Vec<String>) -> Vec<(String, Result<Course, Error>)> {
+        limited(&self.pool, codes, |code| {
+            let course = self.scrape_cached(term, &code);
+            (code, course)
+        })
+    }
+
+    /// Scrape the list of course codes for a term. A blank subject lists
+    /// every subject.
+    fn scrape_codes(&self, term: &str, subject: &str) -> Result<Vec<String>, Error> {
+        check_shutdown()?;
+
+        let query = [
+            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_struts.portlet.action", "/courseSearch/ExecuteCourseSearch"),
+        ];
+
+        let form = [
+            ("acadtermCode", term),
+            ("advancedSearch", "false"),
+            ("courseSearchForm.acadLevel", ""),
+            ("courseSearchForm.courseNumber", ""),
+            ("courseSearchForm.searchBy", "Course"),
+            ("courseSearchForm.subject", subject),
+        ];
+
+        let (url, resp) = self.get_with_retry(|client| {
+            client.post(&self.portal.search_url)
+                .query(BASE_QUERY)
+                .query(&query)
+                .form(&form)
+                .send()
+        })?;
+
+        let doc = Document::from(resp.as_ref());
+
+        let results = doc.find({
+                Attr("id", "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_CourseResults")
+            })
+            .next()
+            .ok_or_else(|| ParseError::at(ParseField::CourseList, &url, &resp))?;
+
+        // The results are there without a table body when nothing is
+        // published for the term or subject yet.
+        let rows = match results.find(Name("table").child(Name("tbody"))).next() {
+            Some(rows) => rows,
+            None => return Ok(vec![]),
+        };
+
+        let codes = rows.children()
+            .filter(|node| node.is(Name("tr")))
+            .filter_map(|node| {
+                // We only want the list of course codes.
+                let text = node.children()
+                    .filter(|node| node.is(Name("td")))
+                    .next()?
+                    .find(Text)
+                    .flat_map(|node| node.as_text())
+                    .join(" ");
+
+                let code = extract_code(&text);
+
+                if code.is_none() {
+                    warn!("Skipping course with malformed code {:?}", text);
+                }
+
+                code
+            })
+            .collect::<Vec<_>>();
+
+        Ok(codes)
+    }
+
+    /// Check that the portal still has the layout the scraper expects without
+    /// touching the index. This scrapes the term list and then up to `sample`
+    /// courses from the first term that has any.
+    pub fn validate(&self, sample: usize) -> Validation {
+        let mut checked = 0;
+
+        let result = self.scrape_terms()
+            .and_then(|terms| {
+                for (term, _) in terms {
+                    let codes = self.scrape_codes(&term, " ")?;
+
+                    if codes.is_empty() {
+                        continue;
+                    }
+
+                    for code in codes.iter().take(sample) {
+                        self.scrape_basic(&term, code)?;
+                        self.scrape_full(&term, code)?;
+                        checked += 1;
+                    }
+
+                    return Ok(());
+                }
+
+                Err(ParseError::new(ParseField::CourseList).into())
+            });
+
+        Validation {
+            checked: checked,
+            error: result.err(),
+        }
+    }
+
+    /// Scrape the title, description, breadth, and instructors for a given course code for a given term.
+    /// This information is used to build the intial search index.
+    fn scrape_basic(&self, term: &str, full_code: &str) -> Result<IndexedCourse, Error> {
+        check_shutdown()?;
+        let (code, section) = CourseCode::split_section(full_code)?;
+
+        let details_query = [
+            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.acadtermCode", term),
+            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.activityCode", &code),
+            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.sectionNo", &section),
+        ];
+
+        let (url, resp) = self.get_with_retry(|client| {
+            client.get(&self.portal.search_url)
+                .query(BASE_QUERY)
+                .query(&details_query)
+                .query(&[
+                    ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_struts.portlet.action", "/courseSearch/viewCourseDetails"),
+                ])
+                .send()
+        })?;
+
+        let doc = Document::from(resp.as_ref());
+
+        let title = doc.find({
+                Name("body")
+                    .child(Name("h1"))
+            })
+            .next()
+            .ok_or_else(|| ParseError::at(ParseField::CourseTitle, &url, &resp))?
+            .find(Text)
+            .flat_map(|node| node.as_text())
+            .flat_map(str::split_whitespace)
+            .join(" ");
+
+        let details = doc.find({
+                Attr("id", "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_tabs-details")
+            })
+            .next()
+            .ok_or_else(|| ParseError::at(ParseField::CourseDetails, &url, &resp))?;
+
+        let description = scrape_description(details);
+
+        let instructors = self.scrape_instructors(&details_query)?;
+
+        let note = scrape_note(details);
+
+        let program_restricted = note.as_ref()
+            .map_or(false, |text| parse_restrictions(text).iter().any(|r| r.starts_with("Program")));
+
+        let availability = section_info(details, "courseSectionInfo_sectionAvailability");
+
+        let open_seats = availability.as_ref()
+            .map_or(false, |text| Availability::parse(text).has_open_seats());
+
+        let drops_close = section_info(details, "dateDropsCloseFormatted")
+            .and_then(|text| parse_date(&text));
+
+        Ok(IndexedCourse {
+            code: full_code.to_owned(),
+            subject: None,
+            title: title,
+            description: description,
+            note: note,
+            breadth: scrape_breadth(details),
+            delivery: scrape_delivery(details),
+            campus: section_info(details, "courseSectionInfo_campus"),
+            instructors: instructors,
+            meets: scrape_meets(details).map(|meets| meets.iter().map(|meet| &meet.text).join(" ")),
+            starts: section_info(details, "dateSessionStartsFormatted"),
+            ends: section_info(details, "dateSessionEndsFormatted"),
+            availability: availability,
+            course_value: section_info(details, "courseSectionInfo_courseValue"),
+            open_seats: open_seats,
+            program_restricted: program_restricted,
+            drops_close: drops_close,
+        })
+    }
+
+    /// Scrape full course information for a given course when requested.
+    pub fn scrape_full(&self, term: &str, full_code: &str) -> Result<Course, Error> {
+        let _in_flight = InFlight::start()?;
+        let (code, section) = CourseCode::split_section(full_code)?;
+
+        let details_query = [
+            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.acadtermCode", term),
+            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.activityCode", &code),
+            ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_courseDetailsForm.sectionNo", &section),
+        ];
+
+        //
+        // Main Query
+        //
+        let (url, resp) = self.get_with_retry(|client| {
+            client.get(&self.portal.search_url)
+                .query(BASE_QUERY)
+                .query(&details_query)
+                .query(&[
+                   ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_struts.portlet.action", "/courseSearch/viewCourseDetails"),
+                ])
+                .send()
+        })?;
+
+        let doc = Document::from(resp.as_ref());
+
+        let title = doc.find({
+                Name("body")
+                    .child(Name("h1"))
+            })
+            .next()
+            .ok_or_else(|| ParseError::at(ParseField::CourseTitle, &url, &resp))?
+            .find(Text)
+            .flat_map(|node| node.as_text())
+            .flat_map(str::split_whitespace)
+            .join(" ");
+
+        let details = doc.find({
+                Attr("id", "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_tabs-details")
+            })
+            .next()
+            .ok_or_else(|| ParseError::at(ParseField::CourseDetails, &url, &resp))?;
+
+        let meets = scrape_meets(details)
+            .ok_or_else(|| ParseError::at(ParseField::Meets, &url, &details.html()))?;
+
+        let f = |id: &str| section_info(details, id);
+
+        let starts = f("dateSessionStartsFormatted")
+            .ok_or_else(|| ParseError::at(ParseField::Starts, &url, &details.html()))?;
+
+        let ends = f("dateSessionEndsFormatted")
+            .ok_or_else(|| ParseError::at(ParseField::Ends, &url, &details.html()))?;
+
+        let campus = f("courseSectionInfo_campus")
+            .ok_or_else(|| ParseError::at(ParseField::Campus, &url, &details.html()))?;
+
+        let availability = f("courseSectionInfo_sectionAvailability")
+            .map(|text| Availability::parse(&text))
+            .ok_or_else(|| ParseError::at(ParseField::Availability, &url, &details.html()))?;
+
+        let course_value = f("courseSectionInfo_courseValue")
+            .ok_or_else(|| ParseError::at(ParseField::CourseValue, &url, &details.html()))?;
+
+        let date_drops_close = f("dateDropsCloseFormatted")
+            .ok_or_else(|| ParseError::at(ParseField::DateDropsClose, &url, &details.html()))?;
+
+        let note = scrape_note(details);
+
+        let restrictions = note.as_ref()
+            .map(|text| parse_restrictions(text))
+            .unwrap_or_default();
+
+        let breadth = scrape_breadth(details);
+
+        let delivery = scrape_delivery(details);
+
+        let description = scrape_description(details);
+
+        let prereqs = scrape_list(&doc, "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_tabs-prerequistes");
+        let antireqs = scrape_list(&doc, "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_tabs-antirequisites");
+        let materials = scrape_list(&doc, "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_tabs-textbooks");
+
+        // Exam dates written without a year are in the year of the term.
+        let year = TermCode::from_code(term)
+            .map_or_else(|| TermCode::today().year, |term| term.year) as i32;
+
+        let exams = doc.find({
+                Attr("id", "_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_tabs-exams")
+                    .descendant(Name("tr"))
+            })
+            .skip(1) // Skip title
+            .map(|node| {
+                let mut columns = node.find(Name("td").descendant(Text))
+                    .flat_map(|n| n.as_text())
+                    .map(str::trim)
+                    .map(str::to_owned);
+
+                let ty = columns.next()?;
+                let slot = columns.next();
+                let date_text = columns.next();
+                let time_text = columns.next();
+
+                Some(Exam {
+                    ty: ty,
+                    slot: slot,
+                    date: date_text.as_ref().and_then(|date| parse_exam_date(date, year)),
+                    date_text: date_text,
+                    time: time_text.as_ref().and_then(|time| parse_time_range(time)),
+                    time_text: time_text,
+                    building: columns.next(),
+                    room: columns.next(),
+                    area: columns.next(),
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| ParseError::at(ParseField::CourseExam, &url, &resp))?;
+
+        let instructors = self.scrape_instructors(&details_query)?;
+
+        Ok(Course {
+            code: full_code.to_string(),
+            title: title,
+            meets: meets,
+            starts: starts,
+            ends: ends,
+            campus: campus,
+            delivery: delivery,
+            availability: availability,
+            course_value: course_value,
+            date_drops_close: date_drops_close,
+            description: description,
+            note: note,
+            restrictions: restrictions,
+            prereqs: prereqs,
+            antireqs: antireqs,
+            materials: materials,
+            breadth: breadth,
+            exams: exams,
+            instructors: instructors,
+        })
+    }
+
+    /// Scrape the instructors of a course given the query identifying it.
+    fn scrape_instructors(&self, details_query: &[(&str, &str)]) -> Result<Vec<Instructor>, Error> {
+        let (url, resp) = self.get_with_retry(|client| {
+            client.get(&self.portal.search_url)
+                .query(BASE_QUERY)
+                .query(details_query)
+                .query(&[
+                    ("_uwinregistrationcoursesearch_WAR_uwinregistrationtoolsportlet_struts.portlet.action", "/courseSearch/viewCourseDetailsInstructors"),
+                ])
+                .send()
+        })?;
+
+        let doc = Document::from(resp.as_ref());
+
+        let instructors = doc.find({
+                And(Name("ul"), Class("uwinListView"))
+            })
+            .next()
+            .ok_or_else(|| ParseError::at(ParseField::CourseInstructors, &url, &resp))?;
+
+        instructors.children()
+            .filter(|node| node.is(Name("li")))
+            .map(|node| {
+                let name = node.find({
+                        Name("b")
+                    })
+                    .next()
+                    .map(|node| {
+                        let text = node.find(Text)
+                            .flat_map(|node| node.as_text())
+                            .join(" ");
+
+                        normalize_name(&text)
+                    })
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| ParseError::at(ParseField::InstructorName, &url, &node.html()))?;
+
+                let mut info = node.find({
+                        Name("div")
+                            .child(Class("wwctrl"))
+                    })
+                    .map(|node| {
+                        node.find(Text)
+                            .flat_map(|s| s.as_text())
+                            .flat_map(str::split_whitespace)
+                            .join(" ")
+                    })
+                    .map(|text| if text.is_empty() { None } else { Some(text) });
+
+                Ok(Instructor {
+                    name: name,
+                    title: info.next().and_then(|text| text),
+                    department: info.next().and_then(|text| text),
+                    phone: info.next().and_then(|text| text),
+                    email: info.next().and_then(|text| text),
+                    directory: self.portal.directory_url.clone(),
+                    directory_search: self.portal.directory_search_url.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()
+    }
+}
+
+/// Register the tokenizers the index's fields are set to use.
+fn register_tokenizers(index: &Index) {
+    index.tokenizers()
+        .register("ngram", {
+            NgramTokenizer::new(NGRAM_LEN, NGRAM_LEN, false)
+                .filter(RemoveLongFilter::limit(40))
+                .filter(LowerCaser)
+        });
+
+    // Descriptions are stemmed so "computing" finds "computer". The query
+    // parser tokenizes queries against the field the same way.
+    index.tokenizers()
+        .register("description", {
+            SimpleTokenizer
+                .filter(RemoveLongFilter::limit(40))
+                .filter(LowerCaser)
+                .filter(StopWordFilter::remove(STOP_WORDS.iter().map(|word| word.to_string()).collect()))
+                .filter(Stemmer::new())
+        });
+}
+
+/// A path next to another, like `./index.new` for `./index`.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(suffix);
+    PathBuf::from(sibling)
+}
+
+/// Work a shutdown waits for, counted for as long as the guard is kept.
+struct InFlight;
+
+impl InFlight {
+    /// Start work unless the bot is shutting down.
+    fn start() -> Result<InFlight, Error> {
+        // Counted before checking so a shutdown starting meanwhile still
+        // waits for it.
+        IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlight;
+
+        check_shutdown()?;
+        Ok(guard)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Fail if the bot is shutting down, to stop long scrapes partway.
+fn check_shutdown() -> Result<(), Error> {
+    if SHUTTING_DOWN.load(Ordering::SeqCst) {
+        Err(ShutdownError.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Refuse new scrapes and index writes, and wait up to the timeout for those
+/// underway to finish. Returns whether they all did.
+pub fn shut_down(timeout: Duration) -> bool {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+
+    let started = Instant::now();
+
+    while IN_FLIGHT.load(Ordering::SeqCst) > 0 {
+        if started.elapsed() >= timeout {
+            return false;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    true
+}
+
+/// The code of a section without the section number, like `0360141` for
+/// `036014101` or `COMP1000` for `COMP100001`. Codes without a section are
+/// already a course's code and are returned as they are.
+pub fn base_code(code: &str) -> String {
+    match CourseCode::parse(code) {
+        Ok(parsed) => parsed.activity,
+        Err(_) => code.to_owned(),
+    }
+}
+
+/// The subject and course number in a code, like COMP and 1000 for
+/// `COMP100001` or 60 and 140 for `036014001`.
+fn code_parts(code: &str) -> Option<(String, String)> {
+    let letters = code.chars()
+        .take_while(char::is_ascii_alphabetic)
+        .collect::<String>();
+
+    if letters.is_empty() {
+        if code.len() < 7 || !code.is_char_boundary(7) {
+            return None;
+        }
+
+        return Some((code[2..4].to_owned(), code[4..7].to_owned()));
+    }
+
+    let number = code[letters.len()..].chars()
+        .take_while(char::is_ascii_digit)
+        .take(4)
+        .collect::<String>();
+
+    if number.is_empty() {
+        None
+    } else {
+        Some((letters.to_uppercase(), number))
+    }
+}
+
+/// The subject and number of a course's code as they're indexed, like COMP
+/// and 1000 for `COMP1000` or 60 and 140 for `60140` and `0360140`.
+fn lookup_parts(activity: &str) -> Option<(String, String)> {
+    if activity.len() == 5 && activity.chars().all(|c| c.is_ascii_digit()) {
+        return Some((activity[..2].to_owned(), activity[2..].to_owned()));
+    }
+
+    code_parts(activity)
+}
+
+/// Key to sort course codes by, so courses are in order by subject, then
+/// number, then section. Numbers are compared as numbers so `COMP-205`
+/// comes before `COMP-1000`.
+fn code_sort_key(code: &str) -> (String, u32, String) {
+    let code = code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .flat_map(char::to_uppercase)
+        .collect::<String>();
+
+    let subject = code.chars()
+        .take_while(char::is_ascii_alphabetic)
+        .collect::<String>();
+
+    // Codes with a subject name have four digit numbers, the rest have the
+    // faculty, subject, and number as seven digits.
+    let number_len = if subject.is_empty() { 7 } else { 4 };
+
+    let number = code[subject.len()..].chars()
+        .take_while(char::is_ascii_digit)
+        .take(number_len)
+        .collect::<String>();
+
+    let section = code[subject.len() + number.len()..].to_owned();
+
+    (subject, number.parse().unwrap_or(0), section)
+}
+
+/// The key identifying the document of a course in a term.
+fn course_key(term: &str, code: &str) -> String {
+    format!("{}:{}", term, code)
+}
+
+/// Fail if the portal sent us to a login or session expired page instead of
+/// the page asked for. Those pages parse like an empty search, so scraping
+/// them would quietly give nothing.
+fn check_session(resp: Response) -> Result<Response, Error> {
+    if resp.status().is_redirection() {
+        let location = resp.headers()
+            .get::<Location>()
+            .map(|location| location.to_string())
+            .unwrap_or_default();
+
+        return Err(SessionError(location).into());
+    }
+
+    if is_login_url(resp.url()) {
+        return Err(SessionError(resp.url().to_string()).into());
+    }
+
+    Ok(resp)
+}
+
+/// Milliseconds to wait before a retry, doubling the first delay for each
+/// retry before it up to `MAX_RETRY_DELAY_MS`.
+fn retry_delay(first_ms: u64, attempt: u32) -> u64 {
+    let factor = 1u64.checked_shl(attempt)
+        .unwrap_or(u64::max_value());
+
+    first_ms.saturating_mul(factor)
+        .min(MAX_RETRY_DELAY_MS)
+}
+
+/// Whether a failed portal request might work if it's tried again. Errors on
+/// the portal's end and connection problems like timeouts might, while the
+/// portal rejecting the request or asking us to log in won't.
+fn is_transient(e: &Error) -> bool {
+    if let Some(&StatusError(status)) = e.downcast_ref::<StatusError>() {
+        return status >= 500;
+    }
+
+    match e.downcast_ref::<::reqwest::Error>() {
+        Some(e) => !e.is_redirect() && !e.is_serialization() && !e.is_client_error(),
+        None => false,
+    }
+}
+
+/// A section matching a query, as `CourseIndex::matching` finds them.
+struct Match {
+    code: String,
+    score: f32,
+    address: DocAddress,
+    /// The number of sections of the course it stands for once grouped.
+    sections: usize,
+}
+
+/// Collects the code, score, and address of every section a query matches
+/// from the code fast field, leaving out any scoring under the minimum.
+struct MatchCollector {
+    field: Field,
+    scored: bool,
+    min_score: Option<f32>,
+    segment: SegmentLocalId,
+    codes: Option<BytesFastFieldReader>,
+    matches: Vec<Match>,
+}
+
+impl MatchCollector {
+    fn new(field: Field, scored: bool, min_score: Option<f32>) -> MatchCollector {
+        MatchCollector {
+            field: field,
+            scored: scored,
+            min_score: min_score,
+            segment: 0,
+            codes: None,
+            matches: vec![],
+        }
+    }
+}
+
+impl Collector for MatchCollector {
+    fn set_segment(&mut self, segment: SegmentLocalId, reader: &SegmentReader) -> tantivy::Result<()> {
+        self.segment = segment;
+        self.codes = Some(reader.bytes_fast_field_reader(self.field)?);
+        Ok(())
+    }
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        if self.min_score.map_or(false, |min| score < min) {
+            return;
+        }
+
+        let code = match self.codes {
+            Some(ref codes) => String::from_utf8_lossy(codes.get_val(doc)).into_owned(),
+            None => return,
+        };
+
+        self.matches.push(Match {
+            code: code,
+            score: score,
+            address: DocAddress(self.segment, doc),
+            sections: 1,
+        });
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.scored
+    }
+}
+
+/// The term and code of up to `max` of the most looked up courses, most
+/// looked up first.
+fn most_looked_up(lookups: &HashMap<(String, String), usize>, max: usize) -> Vec<(String, String)> {
+    lookups.iter()
+        .sorted_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)))
+        .into_iter()
+        .take(max)
+        .map(|(course, _)| course.clone())
+        .collect()
+}
+
+/// A pool of at most `max_requests` threads to scrape with, so only that
+/// many requests are made to the portal at once.
+fn request_pool(max_requests: usize) -> ThreadPool {
+    ThreadPoolBuilder::new()
+        .num_threads(cmp::max(max_requests, 1))
+        .build()
+        .expect("Couldn't build scraping thread pool.")
+}
+
+/// Run a scrape for each item on a pool made by `request_pool`.
+fn limited<T, R, F>(pool: &ThreadPool, items: Vec<T>, scrape: F) -> Vec<R>
+    where T: Send, R: Send, F: Fn(T) -> R + Send + Sync
+{
+    pool.install(|| items.into_par_iter().map(scrape).collect())
+}
+
+/// The number of documents a query matches in a searcher.
+fn count_matches(searcher: &Searcher, query: &Query) -> Result<usize, Error> {
+    let mut count = CountCollector::default();
+    searcher.search(query, &mut count)?;
+
+    Ok(count.count())
+}
+
+/// Whether a request failed by taking longer than the client's timeout.
+fn is_timeout(e: &Error) -> bool {
+    e.downcast_ref::<::reqwest::Error>()
+        .and_then(|e| e.get_ref())
+        .and_then(|e| e.downcast_ref::<::std::io::Error>())
+        .map_or(false, |e| {
+            e.kind() == ::std::io::ErrorKind::TimedOut || e.kind() == ::std::io::ErrorKind::WouldBlock
+        })
+}
+
+/// Whether a URL is for the portal's login or session expired pages.
+fn is_login_url(url: &Url) -> bool {
+    let path = url.path().to_lowercase();
+
+    ["login", "signin", "sign_in", "expired"].iter()
+        .any(|word| path.contains(word))
+        // The portal's login portlet.
+        || url.query_pairs().any(|(key, value)| key == "p_p_id" && value == "58")
+}
+
+/// Turn the text of a course code cell like `03-60-140-01` into a code like
+/// `036014001`. The cell can have extra spans after the code and odd spacing
+/// such as non-breaking or zero width spaces, which are all left out. Codes
+/// that are too short to have both a course and a section give nothing.
+fn extract_code(text: &str) -> Option<String> {
+    let text = text.replace(|c| c == '\u{200b}' || c == '\u{feff}', "");
+
+    // The code is the run of words made of letters, digits, and dashes at the
+    // start of the cell. Anything after, like "(Cancelled)", isn't part of it.
+    let code = text.split_whitespace()
+        .take_while(|word| word.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        .flat_map(|word| word.chars())
+        .filter(|&c| c != '-')
+        .flat_map(char::to_uppercase)
+        .collect::<String>();
+
+    if code.len() > 7 {
+        Some(code)
+    } else {
+        None
+    }
+}
+
+/// Tidy an instructor's name, collapsing whitespace and leaving out
+/// honorifics like "Dr." and credentials like "PhD".
+fn normalize_name(name: &str) -> String {
+    const HONORIFICS: &[&str] = &["dr", "prof", "professor", "mr", "mrs", "ms", "mx"];
+    const CREDENTIALS: &[&str] = &["phd", "ph.d", "msc", "m.sc", "mba", "peng", "p.eng"];
+
+    let bare = |word: &str| {
+        word.trim_matches(|c: char| c == '.' || c == ',')
+            .to_lowercase()
+    };
+
+    let mut words = name.split_whitespace()
+        .collect::<Vec<_>>();
+
+    while words.len() > 1 && HONORIFICS.contains(&bare(words[0]).as_str()) {
+        words.remove(0);
+    }
+
+    while words.len() > 1 && CREDENTIALS.contains(&bare(words[words.len() - 1]).as_str()) {
+        words.pop();
+    }
+
+    words.join(" ")
+        .trim_matches(|c: char| c == ',' || c == ';')
+        .to_owned()
+}
+
+/// Whether an instructor's name could be the one a user typed. Each typed
+/// word has to start a word of the name, and initials match either way, so
+/// "j smith" and "john smith" both match "John Smith" and "J. Smith".
+fn name_matches(query: &str, name: &str) -> bool {
+    let words = |text: &str| {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+    };
+
+    let name = words(name);
+
+    words(query).iter()
+        .all(|typed| {
+            name.iter().any(|word| {
+                word.starts_with(typed.as_str())
+                    || (word.chars().count() == 1 && typed.starts_with(word.as_str()))
+            })
+        })
+}
+
+/// The initial and last name of an instructor, like "j smith", which is the
+/// same for most spellings of their name.
+fn name_key(name: &str) -> String {
+    let words = name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+
+    match (words.first(), words.last()) {
+        (Some(first), Some(last)) if words.len() > 1 => {
+            format!("{} {}", first.chars().next().unwrap(), last)
+        }
+        _ => words.join(" "),
+    }
+}
+
+/// Group sections by the instructor teaching them, given as their name and
+/// email. The email id is the same however the name is spelled, so it's
+/// used when there is one. Sections without an email join the instructor
+/// with the same initial and last name that does have one, unless that
+/// could be more than one person. Groups are named with the longest
+/// spelling seen and sorted by it.
+fn group_instructors<T>(sections: Vec<(String, Option<String>, T)>) -> Vec<(String, Vec<T>)> {
+    let email_id = |email: &Option<String>| {
+        email.as_ref()
+            .and_then(|email| email.split('@').next())
+            .map(|id| id.trim().to_lowercase())
+            .filter(|id| !id.is_empty())
+    };
+
+    // The email id for each name key, or nothing if it's ambiguous.
+    let mut ids: HashMap<String, Option<String>> = HashMap::new();
+
+    for &(ref name, ref email, _) in &sections {
+        if let Some(id) = email_id(email) {
+            let known = ids.entry(name_key(name))
+                .or_insert_with(|| Some(id.clone()));
+
+            if known.as_ref() != Some(&id) {
+                *known = None;
+            }
+        }
+    }
+
+    let mut groups: BTreeMap<String, (String, Vec<T>)> = BTreeMap::new();
+
+    for (name, email, item) in sections {
+        let key = name_key(&name);
+
+        let group = email_id(&email)
+            .or_else(|| ids.get(&key).and_then(|id| id.clone()))
+            .map(|id| format!("email:{}", id))
+            .unwrap_or_else(|| format!("name:{}", key));
+
+        let entry = groups.entry(group)
+            .or_insert_with(|| (name.clone(), vec![]));
+
+        if name.len() > entry.0.len() {
+            entry.0 = name;
+        }
+
+        entry.1.push(item);
+    }
+
+    groups.into_iter()
+        .map(|(_, group)| group)
+        .sorted_by(|a, b| a.0.cmp(&b.0))
+}
+
+/// Scrape the items of the list in a tab of the course details, such as the
+/// prerequisites.
+fn scrape_list(doc: &Document, tab: &str) -> Vec<String> {
+    doc.find({
+            Attr("id", tab)
+                .child(Name("ul"))
+                .child(Name("li"))
+        })
+        .map(|node| {
+            node.find(Text)
+                .flat_map(|node| node.as_text())
+                .flat_map(str::split_whitespace)
+                .join(" ")
+        })
+        .collect()
+}
+
+/// Reject queries that would be expensive for tantivy to run before they get
+/// to the query parser. Wildcards match every document and ranges walk the
+/// whole term dictionary so neither make sense for course searches.
+fn check_complexity(query: &str) -> Result<(), QueryError> {
+    if query.chars().count() > MAX_QUERY_LEN {
+        return Err(QueryError::TooComplex("query is too long"));
+    }
+
+    if query.split_whitespace().count() > MAX_QUERY_WORDS {
+        return Err(QueryError::TooComplex("too many words"));
+    }
+
+    if query.contains('*') {
+        return Err(QueryError::TooComplex("wildcards are not supported"));
+    }
+
+    if query.contains(|c| c == '[' || c == '{') {
+        return Err(QueryError::TooComplex("ranges are not supported"));
+    }
+
+    let mut depth = 0;
+    for c in query.chars() {
+        match c {
+            '(' => {
+                depth += 1;
+
+                if depth > MAX_QUERY_DEPTH {
+                    return Err(QueryError::TooComplex("groups are nested too deeply"));
+                }
+            }
+            ')' if depth > 0 => depth -= 1,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// The code of the newest of some term codes, or of the term going on now if
+/// there are none.
+fn newest_term(terms: &[String]) -> String {
+    terms.iter()
+        .filter_map(|code| TermCode::from_code(code))
+        .max()
+        .unwrap_or_else(TermCode::today)
+        .code()
+}
+
+/// Returns the live terms that are newer than every indexed term. Any at all
+/// means the index is missing the latest courses.
+pub fn newer_terms(indexed: &[String], live: &[String]) -> Vec<String> {
+    let newest = indexed.iter()
+        .filter_map(|code| TermCode::from_code(code))
+        .max();
+
+    live.iter()
+        .filter(|code| {
+            match (TermCode::from_code(code), newest) {
+                (Some(term), Some(newest)) => term > newest,
+                (Some(_), None) => true,
+                (None, _) => false,
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+/// The meeting blocks of a section's details. The first block is always a
+/// meeting. Sections with a lab or tutorial follow it with more blocks before
+/// the section information, which we can tell apart since the information
+/// fields all have ids.
+fn scrape_meets(details: Node) -> Option<Vec<MeetingPattern>> {
+    let mut blocks = details.children()
+        .filter(|node| node.is(Name("div")));
+
+    let first = blocks.next()?;
+
+    let meets = iter::once(first)
+        .chain(blocks.take_while(|node| node.find(Attr("id", ())).next().is_none()))
+        .map(|node| {
+            let text = node.find(Text)
+                .flat_map(|n| n.as_text())
+                .flat_map(str::split_whitespace)
+                .join(" ");
+
+            MeetingPattern::parse(&text)
+        })
+        .collect();
+
+    Some(meets)
+}
+
+/// Scrape one of the section information fields from the details tab by id.
+fn section_info(details: Node, id: &str) -> Option<String> {
+    details.find({
+            Name("div")
+                .descendant(Attr("id", id))
+        })
+        .next()
+        .map(|node| {
+            node.find(Text)
+                .flat_map(|n| n.as_text())
+                .flat_map(str::split_whitespace)
+                .join(" ")
+        })
+}
+
+/// Whether a section's availability, like "Open" or "Full", means a student
+/// could still get a seat.
+fn has_open_seats(availability: &str) -> bool {
+    let lower = availability.to_lowercase();
+
+    !(lower.is_empty()
+        || lower.contains("full")
+        || lower.contains("closed")
+        || lower.contains("cancel")
+        || lower.starts_with("0 "))
+}
+
+/// Read a date in one of the formats the portal uses.
+pub fn parse_date(text: &str) -> Option<NaiveDate> {
+    DATE_FORMATS.iter()
+        .filter_map(|format| NaiveDate::parse_from_str(text.trim(), format).ok())
+        .next()
+}
+
+/// A date as a number like 20181107 that sorts in date order.
+fn date_number(date: NaiveDate) -> u64 {
+    use chrono::Datelike;
+
+    date.year() as u64 * 10_000 + date.month() as u64 * 100 + date.day() as u64
+}
+
+/// Parse the years of study a note restricts a course to, if it does.
+fn parse_years(note: &str) -> Option<Vec<u8>> {
+    let list = YEARS_REGEX.captures(note)?;
+
+    let mut years = YEAR_REGEX.find_iter(&list[1])
+        .map(|m| {
+            match m.as_str().to_lowercase().as_str() {
+                "first" => 1,
+                "second" => 2,
+                "third" => 3,
+                "fourth" => 4,
+                "fifth" => 5,
+                digit => digit.parse().unwrap_or(0),
+            }
+        })
+        .collect::<Vec<u8>>();
+
+    years.sort();
+    years.dedup();
+
+    if years.is_empty() { None } else { Some(years) }
+}
+
+/// Parse the restrictions in a note into short descriptions like
+/// "Years 3, 4" and "Program: Computer Science". Notes with none that are
+/// recognized give nothing, and are still shown as they are.
+fn parse_restrictions(note: &str) -> Vec<String> {
+    let mut restrictions = vec![];
+
+    if let Some(years) = parse_years(note) {
+        let label = if years.len() == 1 { "Year" } else { "Years" };
+        restrictions.push(format!("{} {}", label, years.iter().join(", ")));
+    }
+
+    for caps in PROGRAM_REGEX.captures_iter(note) {
+        if let Some(program) = caps.get(1).or_else(|| caps.get(2)) {
+            restrictions.push(format!("Program: {}", program.as_str()));
+        }
+    }
+
+    restrictions
+}
+
+/// Scrape the note about restrictions or special permission from the details
+/// tab.
+fn scrape_note(details: Node) -> Option<String> {
+    details.find({
+            And(Name("p"), Class("uwinNoteText"))
+        })
+        .next()
+        .map(|node| {
+            node.find(Text)
+                .flat_map(|node| node.as_text())
+                .flat_map(str::split_whitespace)
+                .join(" ")
+        })
+}
+
+/// Scrape the description from the details tab, keeping its paragraphs
+/// apart. The note and the section information are paragraphs in the tab too,
+/// but the note has a class and the information fields are in elements with
+/// ids, so those are left out.
+fn scrape_description(details: Node) -> String {
+    let in_field = |node: Node| {
+        let mut node = Some(node);
+
+        while let Some(n) = node {
+            if n.index() == details.index() {
+                return false;
+            }
+
+            if n.attr("id").is_some() {
+                return true;
+            }
+
+            node = n.parent();
+        }
+
+        false
+    };
+
+    details.find(Name("p"))
+        .filter(|node| node.attr("class").is_none() && !in_field(*node))
+        .map(|node| {
+            node.find(Text)
+                .flat_map(|node| node.as_text())
+                .flat_map(str::split_whitespace)
+                .join(" ")
+        })
+        .filter(|paragraph| !paragraph.is_empty())
+        .join("\n")
+}
+
+/// Scrape the breadth (distribution) categories a course counts towards from
+/// the details tab. Most courses don't have any.
+fn scrape_breadth(details: Node) -> Vec<String> {
+    details.find({
+            Attr("id", "courseSectionInfo_breadthRequirement")
+                .descendant(Text)
+        })
+        .flat_map(|node| node.as_text())
+        .flat_map(|s| s.split(','))
+        .map(|s| s.split_whitespace().join(" "))
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Scrape how a section is delivered from the details tab, naming the mode
+/// the same way regardless of how the portal words it. Older terms don't
+/// list a delivery mode at all.
+fn scrape_delivery(details: Node) -> Option<String> {
+    let text = details.find({
+            Attr("id", "courseSectionInfo_deliveryMode")
+                .descendant(Text)
+        })
+        .flat_map(|node| node.as_text())
+        .flat_map(str::split_whitespace)
+        .join(" ");
+
+    if text.is_empty() {
+        return None;
+    }
+
+    let lower = text.to_lowercase();
+
+    // HyFlex sections are also hybrid, so check for them first.
+    let mode = if lower.contains("hyflex") || lower.contains("hybrid flexible") {
+        "HyFlex"
+    } else if lower.contains("hybrid") || lower.contains("blended") {
+        "Hybrid"
+    } else if lower.contains("online") || lower.contains("remote") || lower.contains("virtual") {
+        "Online"
+    } else if lower.contains("person") || lower.contains("face to face") || lower.contains("face-to-face") {
+        "In Person"
+    } else {
+        return Some(text);
+    };
+
+    Some(mode.to_owned())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn limited_stays_under_the_request_cap() {
+        // Scrapes running now and the most that ever ran at once.
+        let running = Mutex::new((0, 0));
+
+        let done = limited(&request_pool(3), (0..20).collect(), |i: usize| {
+                {
+                    let mut running = running.lock().unwrap();
+                    running.0 += 1;
+                    running.1 = cmp::max(running.0, running.1);
+                }
+
+                thread::sleep(Duration::from_millis(10));
+                running.lock().unwrap().0 -= 1;
+                i
+            });
+
+        assert_eq!(done, (0..20).collect::<Vec<_>>());
+
+        let most = running.lock().unwrap().1;
+        assert!(most <= 3 && most > 1);
+    }
+
+    pub fn course(code: &str) -> Course {
+        Course {
+            code: code.to_owned(),
+            title: "Key Concepts in Computer Science".to_owned(),
+            meets: vec![MeetingPattern::parse("MWF 10:00 AM - 11:20 AM Erie Hall 1120")],
+            starts: "Sep 6, 2018".to_owned(),
+            ends: "Dec 4, 2018".to_owned(),
+            campus: "Main".to_owned(),
+            delivery: None,
+            availability: Availability::parse("0 of 90"),
+            course_value: "3.00".to_owned(),
+            date_drops_close: "Nov 7, 2018".to_owned(),
+            description: "An introduction to computer science.".to_owned(),
+            note: None,
+            restrictions: vec![],
+            prereqs: vec![],
+            antireqs: vec![],
+            materials: vec![],
+            breadth: vec![],
+            exams: vec![],
+            instructors: vec![],
+        }
+    }
+
+    /// An exam in Erie Hall, on a date and at a time if they're known.
+    pub fn exam(ty: &str, date: Option<NaiveDate>, time: Option<(NaiveTime, NaiveTime)>) -> Exam {
+        Exam {
+            ty: ty.to_owned(),
+            slot: None,
+            date: date,
+            date_text: None,
+            time: time,
+            time_text: None,
+            building: Some("Erie Hall".to_owned()),
+            room: Some("1120".to_owned()),
+            area: None,
+        }
+    }
+
+    /// A course as scraped for the index, with the title and description of
+    /// `course`.
+    fn indexed(code: &str) -> IndexedCourse {
+        IndexedCourse {
+            code: code.to_owned(),
+            subject: None,
+            title: "Key Concepts in Computer Science".to_owned(),
+            description: "An introduction to computer science.".to_owned(),
+            note: None,
+            breadth: vec![],
+            delivery: None,
+            campus: Some("Main".to_owned()),
+            instructors: vec![],
+            meets: None,
+            starts: None,
+            ends: None,
+            availability: None,
+            course_value: None,
+            open_seats: false,
+            program_restricted: false,
+            drops_close: None,
+        }
+    }
+
+    /// An index in memory with courses, by term.
+    fn index_with(courses: &[(&str, IndexedCourse)]) -> CourseIndex {
+        let index = CourseIndex::with_index(Index::create_in_ram(CourseIndex::schema()), Path::new(""), &Portal::default());
+
+        {
+            let mut writer = index.index.writer(10_000_000).unwrap();
+
+            for &(term, ref course) in courses {
+                index.add_course(&mut writer, term, course);
+            }
+
+            writer.commit().unwrap();
+        }
+
+        index.index.load_searchers().unwrap();
+        index
+    }
+
+    /// An index in memory with courses in the Fall 2018 term.
+    pub fn index_of(codes: &[&str]) -> CourseIndex {
+        let courses = codes.iter()
+            .map(|code| ("20185", indexed(code)))
+            .collect::<Vec<_>>();
+
+        index_with(&courses)
+    }
+
+    /// The codes of the courses a query finds.
+    fn found(index: &CourseIndex, query: &str, options: &QueryOptions) -> Vec<String> {
+        index.query("20185", query, options)
+            .unwrap()
+            .hits
+            .into_iter()
+            .map(|hit| hit.preview.code)
+            .collect()
+    }
+
+    /// A scraper that has recently scraped courses in the Fall 2018 term.
+    pub fn scraper_with(codes: &[&str]) -> Scraper {
+        let scraper = Scraper::new(Portal::default());
+
+        for code in codes {
+            scraper.cache.keep("20185", code, course(code));
+        }
+
+        scraper
+    }
+
+    pub fn preview<'a>(scraper: &'a Scraper, code: &str) -> CoursePreview<'a> {
+        CoursePreview {
+            scraper: scraper,
+            term: "20185".to_owned(),
+            code: code.to_owned(),
+            title: "Key Concepts in Computer Science".to_owned(),
+            campus: None,
+            sections: 1,
+        }
+    }
+
+    /// An empty directory for a test to write to, under the system's
+    /// temporary directory.
+    pub fn temp_dir(name: &str) -> PathBuf {
+        let dir = ::std::env::temp_dir().join(format!("uwinsearch-{}-{}", name, ::std::process::id()));
+
+        if dir.is_dir() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn popular_courses_come_first() {
+        let mut lookups = HashMap::new();
+
+        for code in &["COMP100001", "COMP200001", "COMP200001", "COMP300001", "COMP200001", "COMP300001"] {
+            *lookups.entry(("20189".to_owned(), code.to_string())).or_insert(0) += 1;
+        }
+
+        let codes = most_looked_up(&lookups, 2)
+            .into_iter()
+            .map(|(_, code)| code)
+            .collect::<Vec<_>>();
+
+        assert_eq!(codes, vec!["COMP200001", "COMP300001"]);
+    }
+
+    #[test]
+    fn prefetch_fills_the_cache() {
+        let cache = CourseCache::new(15);
+
+        let courses = vec![
+            ("20189".to_owned(), "COMP100001".to_owned()),
+            ("20189".to_owned(), "COMP200001".to_owned()),
+        ];
+
+        let scraped = cache.prefetch(&request_pool(2), courses, |_, code| {
+                if code == "COMP100001" {
+                    Ok(course(code))
+                } else {
+                    Err(::failure::err_msg("Not found"))
+                }
+            });
+
+        assert_eq!(scraped, 1);
+
+        assert!(cache.get("20189", "COMP100001").is_some());
+        assert!(cache.get("20189", "COMP200001").is_none());
+    }
+
+    #[test]
+    fn lecture_and_lab_blocks_are_kept() {
+        let doc = Document::from(r#"
+            <div id="details">
+                <div>MWF 10:00 AM - 11:20 AM  Erie Hall 1120</div>
+                <div>T  2:30 PM - 5:20 PM Lambton Tower 3105</div>
+                <div><label>Starts</label> <span id="dateSessionStartsFormatted">Sep 6, 2018</span></div>
+                <div><label>Campus</label> <span id="courseSectionInfo_campus">Main</span></div>
+            </div>
+        "#);
+
+        let details = doc.find(Attr("id", "details")).next().unwrap();
+        let meets = scrape_meets(details).unwrap();
+
+        assert_eq!(meets.iter().map(|meet| &meet.text[..]).collect::<Vec<_>>(), vec![
+            "MWF 10:00 AM - 11:20 AM Erie Hall 1120",
+            "T 2:30 PM - 5:20 PM Lambton Tower 3105",
+        ]);
+        assert_eq!(section_info(details, "courseSectionInfo_campus"), Some("Main".to_owned()));
+    }
+
+    #[test]
+    fn single_meeting_block_is_kept() {
+        let doc = Document::from(r#"
+            <div id="details">
+                <div>TBA</div>
+                <div><span id="courseSectionInfo_campus">Main</span></div>
+            </div>
+        "#);
+
+        let details = doc.find(Attr("id", "details")).next().unwrap();
+        let meets = scrape_meets(details).unwrap();
+
+        assert_eq!(meets.len(), 1);
+        assert_eq!(meets[0].text, "TBA");
+        assert!(meets[0].times.is_empty());
+
+        let doc = Document::from(r#"<div id="details"></div>"#);
+        assert!(scrape_meets(doc.find(Attr("id", "details")).next().unwrap()).is_none());
+    }
+
+    #[test]
+    fn pathological_queries_are_rejected() {
+        let long = "a".repeat(MAX_QUERY_LEN + 1);
+        let words = vec!["comp"; MAX_QUERY_WORDS + 1].join(" ");
+
+        for query in &[&long[..], &words[..], "*", "comp*", "number:[0 TO 9999]", "title:{a TO z}", "((((comp))))"] {
+            match check_complexity(query) {
+                Err(QueryError::TooComplex(_)) => {}
+                _ => panic!("{} was not rejected", query),
+            }
+        }
+
+        assert!(check_complexity("(comp OR (math AND stat))").is_ok());
+        assert!(check_complexity("intro to computing)))").is_ok());
+    }
+
+    pub fn instructor(email: &str) -> Instructor {
+        Instructor {
+            name: "Zoë Ménard".to_owned(),
+            title: None,
+            department: None,
+            phone: None,
+            email: Some(email.to_owned()),
+            directory: DIRECTORY_SERVICES.to_owned(),
+            directory_search: DIRECTORY_SEARCH.to_owned(),
+        }
+    }
+
+    #[test]
+    fn accented_emails_are_encoded() {
+        let url = instructor("zoë.ménard@uwindsor.ca").directory_url().unwrap();
+        assert_eq!(url, format!("{}?q=zo%C3%AB.m%C3%A9nard", DIRECTORY_SERVICES));
+
+        let url = instructor("o'brien(cs)@uwindsor.ca").directory_url().unwrap();
+        assert!(Url::parse(&url).is_ok());
+        assert!(!url.contains('(') && !url.contains(')'));
+    }
+
+    #[test]
+    fn unusual_emails_get_no_link() {
+        for email in &["", "nobody", "a@b@uwindsor.ca", "@uwindsor.ca", "name@localhost", "first last@uwindsor.ca"] {
+            assert_eq!(instructor(email).directory_url(), None, "{}", email);
+        }
+    }
+
+    #[test]
+    fn codes_are_extracted_from_messy_cells() {
+        assert_eq!(extract_code("03-60-140-01"), Some("036014001".to_owned()));
+        assert_eq!(extract_code("  03-60-140-01 \n"), Some("036014001".to_owned()));
+        assert_eq!(extract_code("03-60-140\u{a0}01"), Some("036014001".to_owned()));
+        assert_eq!(extract_code("03-60\u{200b}-140-01"), Some("036014001".to_owned()));
+        assert_eq!(extract_code("\u{feff}comp-1000-01"), Some("COMP100001".to_owned()));
+        assert_eq!(extract_code("03-60-140-01 (Cancelled)"), Some("036014001".to_owned()));
+        assert_eq!(extract_code("03-60"), None);
+        assert_eq!(extract_code(""), None);
+    }
+
+    #[test]
+    fn descriptions_match_stemmed_queries() {
+        let mut schema_builder = SchemaBuilder::default();
+        let description = schema_builder.add_text_field("description", {
+            TextOptions::default()
+                .set_indexing_options(
+                    TextFieldIndexing::default()
+                        .set_tokenizer("description")
+                        .set_index_option(IndexRecordOption::WithFreqsAndPositions)
+                )
+        });
+
+        let index = Index::create_in_ram(schema_builder.build());
+        register_tokenizers(&index);
+
+        let mut writer = index.writer(10_000_000).unwrap();
+        for text in &["An introduction to computers and programming.", "Accounting principles for managers."] {
+            let mut doc = tantivy::Document::default();
+            doc.add_text(description, text);
+            writer.add_document(doc);
+        }
+        writer.commit().unwrap();
+        index.load_searchers().unwrap();
+
+        let searcher = index.searcher();
+        let parser = QueryParser::for_index(&index, vec![description]);
+
+        let count = |query: &str| {
+            let query = parser.parse_query(query).unwrap();
+            let mut count = CountCollector::default();
+            searcher.search(&*query, &mut count).unwrap();
+            count.count()
+        };
+
+        assert_eq!(count("computing"), 1);
+        assert_eq!(count("programs"), 1);
+        assert_eq!(count("introductions computing"), 1);
+        assert_eq!(count("managing"), 1);
+    }
+
+    #[test]
+    fn codes_sort_by_number() {
+        let mut codes = vec!["COMP-1000", "COMP-205", "COMP-100", "ACCT-1000", "COMP-1000-02", "COMP-1000-01"];
+        codes.sort_by_key(|code| code_sort_key(code));
+
+        assert_eq!(codes, vec!["ACCT-1000", "COMP-100", "COMP-205", "COMP-1000", "COMP-1000-01", "COMP-1000-02"]);
+    }
+
+    #[test]
+    fn short_codes_are_a_parse_error() {
+        let err = CourseCode::split_section("ABC").unwrap_err();
+
+        assert_eq!(err.field, ParseField::SectionCode);
+        assert_eq!(err.snippet, Some("ABC".to_owned()));
+
+        assert!(CourseCode::split_section("é").is_err());
+        assert!(CourseCode::split_section("").is_err());
+    }
+
+    #[test]
+    fn newest_term_is_picked_across_seasons() {
+        let terms = |codes: &[&str]| codes.iter().map(|code| code.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(newest_term(&terms(&["20185", "20181", "20182"])), "20185");
+        assert_eq!(newest_term(&terms(&["20182", "20181"])), "20182");
+        assert_eq!(newest_term(&terms(&["20185", "20191", "20182"])), "20191");
+        assert_eq!(newest_term(&terms(&["20175", "junk", "20189"])), "20175");
+        assert_eq!(newest_term(&[]), TermCode::today().code());
+    }
+
+    #[test]
+    fn exam_dates_and_times_are_read() {
+        assert_eq!(parse_exam_date("Dec 12, 2018", 2017), NaiveDate::from_ymd_opt(2018, 12, 12));
+        assert_eq!(parse_exam_date("December 12", 2018), NaiveDate::from_ymd_opt(2018, 12, 12));
+        assert_eq!(parse_exam_date("2018-12-12", 2017), NaiveDate::from_ymd_opt(2018, 12, 12));
+        assert_eq!(parse_exam_date("12/12", 2018), NaiveDate::from_ymd_opt(2018, 12, 12));
+
+        let time = |hour, minute| NaiveTime::from_hms(hour, minute, 0);
+
+        assert_eq!(parse_time_range("7:00 PM - 10:00 PM"), Some((time(19, 0), time(22, 0))));
+        assert_eq!(parse_time_range("7:00 - 10:00 PM"), Some((time(19, 0), time(22, 0))));
+        assert_eq!(parse_time_range("11:30 AM - 2:30 PM"), Some((time(11, 30), time(14, 30))));
+        assert_eq!(parse_time_range("19:00-22:00"), Some((time(19, 0), time(22, 0))));
+    }
+
+    #[test]
+    fn malformed_exam_dates_and_times_are_none() {
+        for text in &["", "TBA", "Smarch 40", "Dec 32, 2018", "13/45"] {
+            assert_eq!(parse_exam_date(text, 2018), None, "{}", text);
+        }
+
+        for text in &["", "TBA", "7 PM", "10:00 PM - 7:00 PM", "25:00 - 26:00"] {
+            assert_eq!(parse_time_range(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn field_stats_count_missing_fields() {
+        let scraped = IndexedCourse {
+            code: "COMP100001".to_owned(),
+            subject: None,
+            title: "Key Concepts in Computer Science".to_owned(),
+            description: " ".to_owned(),
+            note: None,
+            breadth: vec![],
+            delivery: None,
+            campus: Some("Main".to_owned()),
+            instructors: vec![instructor("ins@uwindsor.ca")],
+            meets: Some("MWF 10:00 AM - 11:20 AM".to_owned()),
+            starts: Some("Sep 6, 2018".to_owned()),
+            ends: None,
+            availability: Some("0 of 90".to_owned()),
+            course_value: Some(String::new()),
+            open_seats: false,
+            program_restricted: false,
+            drops_close: None,
+        };
+
+        let mut stats = FieldStats::default();
+        stats.record_course(&scraped);
+        stats.record_course(&IndexedCourse { ends: Some("Dec 4, 2018".to_owned()), ..scraped });
+
+        assert_eq!(stats.courses, 2);
+
+        let missing = stats.missing.iter()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(&field, &count)| (field, count))
+            .collect::<Vec<_>>();
+
+        assert_eq!(missing, vec![("course_value", 2), ("delivery", 2), ("description", 2), ("ends", 1)]);
+        assert_eq!(stats.missing["meets"], 0);
+        assert_eq!(stats.missing["availability"], 0);
+    }
+
+    #[test]
+    fn term_range_lists_every_term_between() {
+        let codes = |range| TermCode::range(range).map(|terms| terms.iter().map(TermCode::code).collect::<Vec<_>>());
+
+        assert_eq!(codes("f18-w19"), Some(vec!["20185".to_owned(), "20191".to_owned()]));
+        assert_eq!(codes("w18-f18"), Some(vec!["20181".to_owned(), "20182".to_owned(), "20185".to_owned()]));
+        assert_eq!(codes("s18-s18"), Some(vec!["20182".to_owned()]));
+        assert_eq!(codes("w19-f18"), None);
+        assert_eq!(codes("f18"), None);
+    }
+
+    #[test]
+    fn index_with_another_schema_is_unusable() {
+        let dir = temp_dir("unusable");
+        let schema = CourseIndex::schema();
+
+        assert!(CourseIndex::unusable(&dir.join("index"), &schema).unwrap().starts_with("No index found"));
+
+        let mut schema_builder = SchemaBuilder::default();
+        schema_builder.add_text_field("term", STRING | STORED);
+        schema_builder.add_text_field("code", STRING);
+        Index::create_in_dir(&dir, schema_builder.build()).unwrap();
+
+        assert_eq!(CourseIndex::unusable(&dir, &schema), Some("Index schema has changed".to_owned()));
+
+        // An index with our schema but no courses was cut off while building.
+        let empty = dir.join("empty");
+        fs::create_dir(&empty).unwrap();
+        Index::create_in_dir(&empty, schema.clone()).unwrap();
+
+        assert!(CourseIndex::unusable(&empty, &schema).unwrap().starts_with("Index is empty"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn instructor_filter_combines_with_the_query() {
+        let taught_by = |code: &str, title: &str, names: &[&str]| {
+            let mut course = indexed(code);
+            course.title = title.to_owned();
+            course.instructors = names.iter()
+                .map(|name| Instructor { name: name.to_string(), ..instructor("ins@uwindsor.ca") })
+                .collect();
+
+            ("20185", course)
+        };
+
+        let index = index_with(&[
+            taught_by("COMP100001", "Graph Theory", &["Jane Smith", "Li Wei"]),
+            taught_by("COMP200001", "Graph Algorithms", &["Omar Haddad"]),
+            taught_by("COMP300001", "Operating Systems", &["Jane Smith"]),
+        ]);
+
+        let by = |name: &str| QueryOptions {
+            instructor: Some(name.to_owned()),
+            ..QueryOptions::default()
+        };
+
+        assert_eq!(found(&index, "graph", &QueryOptions::default()).len(), 2);
+        assert_eq!(found(&index, "graph", &by("smith")), vec!["COMP100001"]);
+        // Any of a section's instructors matches.
+        assert_eq!(found(&index, "graph", &by("wei")), vec!["COMP100001"]);
+        assert_eq!(found(&index, "graph", &by("haddad")), vec!["COMP200001"]);
+        // The query matches, just not with that instructor.
+        assert!(found(&index, "graph", &by("nobody")).is_empty());
+    }
+
+    #[test]
+    fn note_words_find_the_course() {
+        let mut restricted = indexed("COMP100001");
+        restricted.note = Some("Requires permission of instructor.".to_owned());
+
+        let index = index_with(&[("20185", restricted), ("20185", indexed("COMP200001"))]);
+
+        assert_eq!(found(&index, "permission", &QueryOptions::default()), vec!["COMP100001"]);
+
+        let note = |text: &str| QueryOptions {
+            note: Some(text.to_owned()),
+            ..QueryOptions::default()
+        };
+
+        assert_eq!(found(&index, "computer", &note("permission")), vec!["COMP100001"]);
+        assert!(found(&index, "computer", &note("majors")).is_empty());
+    }
+
+    #[test]
+    fn codes_are_checked_against_the_index() {
+        let index = index_of(&["COMP100001", "COMP100002", "COMP105001"]);
+
+        let lookup = |code| match index.lookup("20185", code).unwrap() {
+            CodeLookup::Found(courses) => Ok(courses.into_iter().map(|course| course.code).collect::<Vec<_>>()),
+            CodeLookup::Missing(suggestions) => Err(suggestions),
+        };
+
+        assert_eq!(lookup("COMP-1000"), Ok(vec!["COMP100001".to_owned(), "COMP100002".to_owned()]));
+        assert_eq!(lookup("comp 1000-02"), Ok(vec!["COMP100002".to_owned()]));
+        // A typo gets the codes sharing the most of its number.
+        assert_eq!(lookup("COMP-1001"), Err(vec!["COMP100001".to_owned(), "COMP100002".to_owned()]));
+        assert_eq!(lookup("COMP-1900"), Err(vec!["COMP100001".to_owned(), "COMP100002".to_owned(), "COMP105001".to_owned()]));
+        assert_eq!(lookup("MATH-1000"), Err(vec![]));
+    }
+
+    #[test]
+    fn count_matches_a_full_query() {
+        let index = index_of(&["COMP100001", "COMP100002", "COMP200001", "COMP300001", "MATH100001"]);
+
+        for &sections in &[false, true] {
+            let every = QueryOptions {
+                sections: sections,
+                limit: ::std::usize::MAX,
+                ..QueryOptions::default()
+            };
+
+            let page = QueryOptions {
+                limit: 2,
+                ..every.clone()
+            };
+
+            let everything = index.query("20185", "computer", &every).unwrap();
+            let count = index.query_count("20185", "computer", &every).unwrap();
+
+            assert_eq!(count, everything.hits.len());
+            assert_eq!(everything.total, count);
+            assert_eq!(index.query("20185", "computer", &page).unwrap().total, count);
+        }
+
+        assert_eq!(index.query_count("20185", "computer", &QueryOptions::default()).unwrap(), 4);
+    }
+
+    #[test]
+    fn short_words_match_whole_title_words() {
+        let mut ai = indexed("COMP100001");
+        ai.title = "AI for Games".to_owned();
+
+        let mut c = indexed("COMP200001");
+        c.title = "Programming in C".to_owned();
+
+        let index = index_with(&[("20185", ai), ("20185", c), ("20185", indexed("COMP300001"))]);
+
+        assert_eq!(found(&index, "ai", &QueryOptions::default()), vec!["COMP100001"]);
+        assert_eq!(found(&index, "c", &QueryOptions::default()), vec!["COMP200001"]);
+    }
+
+    #[test]
+    fn enrollable_sections_pass_every_condition() {
+        let section = |code: &str, open_seats, program_restricted, drops_close| IndexedCourse {
+            open_seats: open_seats,
+            program_restricted: program_restricted,
+            drops_close: drops_close,
+            ..indexed(code)
+        };
+
+        let later = NaiveDate::from_ymd_opt(2999, 1, 1);
+        let passed = NaiveDate::from_ymd_opt(2000, 1, 1);
+
+        let index = index_with(&[
+            ("20185", section("COMP100001", true, false, later)),
+            ("20185", section("COMP200001", false, false, later)),
+            ("20185", section("COMP300001", true, true, later)),
+            ("20185", section("COMP400001", true, false, passed)),
+            // A drop date that couldn't be read isn't taken to have passed.
+            ("20185", section("COMP500001", true, false, None)),
+        ]);
+
+        let enrollable = QueryOptions {
+            enrollable: true,
+            ..QueryOptions::default()
+        };
+
+        assert_eq!(found(&index, "computer", &enrollable), vec!["COMP100001", "COMP500001"]);
+        assert_eq!(found(&index, "computer", &QueryOptions::default()).len(), 5);
+    }
+
+    #[test]
+    fn parse_errors_name_the_field_readably_and_by_code() {
+        let fields = [
+            ParseField::TermCodeValue, ParseField::TermCodeName, ParseField::SubjectList, ParseField::CourseList,
+            ParseField::CourseTitle, ParseField::CourseDetails, ParseField::Meets, ParseField::Starts,
+            ParseField::Ends, ParseField::Campus, ParseField::Availability, ParseField::CourseValue,
+            ParseField::DateDropsClose, ParseField::CourseExam, ParseField::CourseInstructors,
+            ParseField::InstructorName, ParseField::StoredTerm, ParseField::StoredCode, ParseField::StoredTitle,
+            ParseField::SectionCode,
+        ];
+
+        // Codes are unique, and the description is the code in words.
+        assert_eq!(fields.iter().map(ParseField::code).collect::<HashSet<_>>().len(), fields.len());
+
+        for field in &fields {
+            assert_eq!(field.description().replace(' ', "_"), field.code());
+        }
+
+        assert_eq!(ParseError::new(ParseField::Meets).to_string(), "Error parsing HTML at meets (parse_error=meets)");
+        assert_eq!(
+            ParseError::at(ParseField::DateDropsClose, "https://example.com/course", "<div>\n  <p>Nothing</p>\n</div>").to_string(),
+            "Error parsing HTML at date drops close (parse_error=date_drops_close) from https://example.com/course in \"<div> <p>Nothing</p> </div>\""
+        );
+    }
+
+    #[test]
+    fn filter_limits_the_terms_indexed() {
+        let listed = || vec!["20181".to_owned(), "20182".to_owned(), "20185".to_owned()];
+
+        assert_eq!(IndexFilter::default().indexed_terms(listed()), listed());
+
+        let filter = IndexFilter {
+            terms: vec!["20185".to_owned(), "20191".to_owned()],
+            subjects: vec![],
+        };
+
+        assert_eq!(filter.indexed_terms(listed()), vec!["20185"]);
+    }
+
+    #[test]
+    fn spellings_of_an_instructor_are_grouped() {
+        let section = |name: &str, email: Option<&str>, code: &'static str| {
+            (name.to_owned(), email.map(str::to_owned), code)
+        };
+
+        let groups = group_instructors(vec![
+            section("J. Smith", Some("jsmith@uwindsor.ca"), "COMP100001"),
+            section("John Smith", Some("JSmith@uwindsor.ca"), "COMP200001"),
+            // Without an email the name is matched to the one with an email.
+            section("J Smith", None, "COMP300001"),
+            section("Jane Doe", Some("jdoe@uwindsor.ca"), "COMP400001"),
+        ]);
+
+        assert_eq!(groups, vec![
+            ("Jane Doe".to_owned(), vec!["COMP400001"]),
+            ("John Smith".to_owned(), vec!["COMP100001", "COMP200001", "COMP300001"]),
+        ]);
+
+        // Two people with the same initial and last name are kept apart, and
+        // a section naming only the initial can't be put with either.
+        let groups = group_instructors(vec![
+            section("John Smith", Some("jsmith@uwindsor.ca"), "COMP100001"),
+            section("Jane Smith", Some("jasmith@uwindsor.ca"), "COMP200001"),
+            section("J. Smith", None, "COMP300001"),
+        ]);
+
+        assert_eq!(groups.len(), 3);
+        assert_eq!(normalize_name("Dr. John  Smith, PhD"), "John Smith");
+    }
+
+    #[test]
+    fn retry_delays_double_without_overflowing() {
+        assert_eq!(retry_delay(500, 0), 500);
+        assert_eq!(retry_delay(500, 3), 4000);
+        assert_eq!(retry_delay(500, 10), MAX_RETRY_DELAY_MS);
+        assert_eq!(retry_delay(500, 64), MAX_RETRY_DELAY_MS);
+        assert_eq!(retry_delay(u64::max_value(), 1), MAX_RETRY_DELAY_MS);
+        assert_eq!(retry_delay(0, 100), 0);
+    }
+
+    #[test]
+    fn scraper_requests_the_configured_search_url() {
+        use std::io::{ Read, Write };
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // Answer one request with a search form, giving back what was asked for.
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4096];
+            let read = stream.read(&mut request).unwrap();
+
+            let html = "<select id=\"ExecuteCourseSearch_acadtermCode\"><option value=\"20185\">Fall 2018</option></select>";
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", html.len(), html).unwrap();
+
+            String::from_utf8_lossy(&request[..read]).into_owned()
+        });
+
+        let scraper = Scraper::new(Portal {
+            search_url: format!("http://127.0.0.1:{}/mirror/search", port),
+            retries: 0,
+            ..Portal::default()
+        });
+
+        assert_eq!(scraper.scrape_terms().unwrap(), vec![("20185".to_owned(), "Fall 2018".to_owned())]);
+        assert!(server.join().unwrap().starts_with("GET /mirror/search?"));
+    }
+
+    #[test]
+    fn excluded_words_remove_courses() {
+        let course = |code: &str, title: &str, description: &str| IndexedCourse {
+            title: title.to_owned(),
+            description: description.to_owned(),
+            ..indexed(code)
+        };
+
+        let index = index_with(&[
+            ("20185", course("COMP100001", "Programming Lab", "Weekly exercises.")),
+            ("20185", course("COMP200001", "Programming in Java", "The syllabus covers objects.")),
+            ("20185", course("COMP300001", "Systems Programming", "Includes a project.")),
+        ]);
+
+        let excluding = |words: &[&str]| QueryOptions {
+            exclude: words.iter().map(|word| word.to_string()).collect(),
+            ..QueryOptions::default()
+        };
+
+        // A word is only excluded whole, so "Syllabus" stays.
+        assert_eq!(found(&index, "programming", &excluding(&["lab"])), vec!["COMP200001", "COMP300001"]);
+        assert_eq!(found(&index, "programming", &excluding(&["lab", "project"])), vec!["COMP200001"]);
+        assert_eq!(found(&index, "programming", &excluding(&[])).len(), 3);
+    }
+
+    #[test]
+    fn hits_come_with_descending_scores_and_matched_fields() {
+        let course = |code: &str, title: &str, description: &str| IndexedCourse {
+            title: title.to_owned(),
+            description: description.to_owned(),
+            ..indexed(code)
+        };
+
+        let index = index_with(&[
+            ("20185", course("COMP100001", "Key Concepts", "Includes a graph or two.")),
+            ("20185", course("COMP200001", "Graph Theory", "Graph algorithms and graph colouring.")),
+            ("20185", course("COMP300001", "Databases", "Tables and queries.")),
+        ]);
+
+        let hits = index.query("20185", "graph", &QueryOptions::default()).unwrap().hits;
+        let codes = hits.iter().map(|hit| hit.preview.code.as_str()).collect::<Vec<_>>();
+
+        assert_eq!(codes, vec!["COMP200001", "COMP100001"]);
+        assert!(hits.iter().all(|hit| hit.score > 0.0));
+        assert!(hits[0].score > hits[1].score);
+
+        assert!(hits[0].matched_fields.contains(&"title"));
+        assert!(hits[0].matched_fields.contains(&"description"));
+        assert_eq!(hits[1].matched_fields, vec!["description"]);
+    }
+
+    #[test]
+    fn sampled_examples_are_indexed_courses() {
+        let codes = ["COMP100001", "COMP200001", "MATH172001", "PHYS221001"];
+        let index = index_of(&codes);
+
+        let sample = index.sample("20185", 3).unwrap();
+        assert_eq!(sample.len(), 3);
+
+        for course in &sample {
+            assert!(codes.contains(&course.code.as_str()), "{}", course.code);
+
+            // Both ways `~course --help-examples` shows to search find it.
+            assert!(found(&index, &course.code[..7], &QueryOptions::default()).contains(&course.code));
+            assert!(found(&index, &course.title.to_lowercase(), &QueryOptions::default()).contains(&course.code));
+        }
+
+        assert!(index.sample("20191", 3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn subject_browse_lists_the_whole_subject_in_order() {
+        let mut open = indexed("COMP200001");
+        open.open_seats = true;
+
+        let index = index_with(&[
+            ("20185", indexed("COMP300001")),
+            ("20185", indexed("COMP100001")),
+            ("20185", open),
+            ("20185", indexed("COMP100002")),
+            ("20185", indexed("MATH172001")),
+            ("20191", indexed("COMP400001")),
+        ]);
+
+        let subject = |offset, limit| QueryOptions {
+            subject: Some("comp".to_owned()),
+            offset: offset,
+            limit: limit,
+            ..QueryOptions::default()
+        };
+
+        // Sections of a course are listed once.
+        assert_eq!(found(&index, "", &subject(0, 10)), vec!["COMP100001", "COMP200001", "COMP300001"]);
+        assert_eq!(index.query("20185", "", &subject(0, 10)).unwrap().total, 3);
+        assert_eq!(found(&index, "", &subject(2, 2)), vec!["COMP300001"]);
+
+        let open_only = QueryOptions {
+            open: true,
+            ..subject(0, 10)
+        };
+
+        assert_eq!(found(&index, "", &open_only), vec!["COMP200001"]);
+    }
+
+    #[test]
+    fn adding_a_course_again_replaces_it() {
+        let mut renamed = indexed("COMP100001");
+        renamed.title = "Renamed Course".to_owned();
+
+        // Twice in one commit, and once more in the next.
+        let index = index_with(&[("20185", indexed("COMP100001")), ("20185", indexed("COMP100001"))]);
+
+        {
+            let mut writer = index.index.writer(10_000_000).unwrap();
+            index.add_course(&mut writer, "20185", &renamed);
+            writer.commit().unwrap();
+        }
+
+        index.index.load_searchers().unwrap();
+
+        let sections = QueryOptions {
+            sections: true,
+            ..QueryOptions::default()
+        };
+
+        assert_eq!(found(&index, "", &QueryOptions { subject: Some("COMP".to_owned()), ..sections.clone() }), vec!["COMP100001"]);
+        assert_eq!(found(&index, "renamed", &sections), vec!["COMP100001"]);
+        assert!(found(&index, "concepts", &sections).is_empty());
     }
 }
@@ -0,0 +1,176 @@
+use std::path::{ Path, PathBuf };
+
+use failure::Error;
+use itertools::Itertools;
+use typemap::Key;
+
+use json_store;
+
+const WATCH_PATH: &str = "./watches.json";
+
+/// Names the portal lists in place of an instructor who isn't assigned yet.
+const UNASSIGNED: &[&str] = &["tba", "tbd", "to be announced", "staff"];
+
+/// A section a user asked to be told about with `~watch` once it has open
+/// seats, or if its instructors change meanwhile.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Watch {
+    pub user: u64,
+    pub term: String,
+    pub code: String,
+    /// The instructors of the section when it was last checked. Watches
+    /// saved before these were kept have none until the next check.
+    #[serde(default)]
+    pub instructors: Option<Vec<String>>,
+}
+
+/// Every section being watched, kept across restarts.
+#[derive(Default, Serialize, Deserialize)]
+pub struct WatchList {
+    watches: Vec<Watch>,
+    /// Where the watches are saved.
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Key for WatchList {
+    type Value = WatchList;
+}
+
+impl WatchList {
+    /// Load the watch file, starting fresh if there isn't one yet.
+    pub fn load() -> Result<WatchList, Error> {
+        WatchList::load_from(Path::new(WATCH_PATH))
+    }
+
+    /// Load watches kept somewhere other than the usual file.
+    pub fn load_from(path: &Path) -> Result<WatchList, Error> {
+        let mut loaded: WatchList = json_store::load(path)?;
+        loaded.path = path.to_owned();
+        Ok(loaded)
+    }
+
+    /// Write the watch file.
+    fn save(&self) -> Result<(), Error> {
+        json_store::save(&self.path, self)
+    }
+
+    /// Every watch, for checking them all.
+    pub fn all(&self) -> Vec<Watch> {
+        self.watches.clone()
+    }
+
+    /// The watches of a user.
+    pub fn of_user(&self, user: u64) -> Vec<&Watch> {
+        self.watches.iter()
+            .filter(|watch| watch.user == user)
+            .collect()
+    }
+
+    /// Start watching a section with its current instructors for a user and
+    /// save the change. Returns false if they were already watching it.
+    pub fn add(&mut self, user: u64, term: &str, code: &str, instructors: Vec<String>) -> Result<bool, Error> {
+        if self.watches.iter().any(|watch| watch.user == user && watch.term == term && watch.code == code) {
+            return Ok(false);
+        }
+
+        self.watches.push(Watch {
+            user: user,
+            term: term.to_owned(),
+            code: code.to_owned(),
+            instructors: Some(instructors),
+        });
+
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Stop watching a section for a user and save the change. Returns false
+    /// if they weren't watching it.
+    pub fn remove(&mut self, user: u64, term: &str, code: &str) -> Result<bool, Error> {
+        let before = self.watches.len();
+
+        self.watches.retain(|watch| !(watch.user == user && watch.term == term && watch.code == code));
+
+        if self.watches.len() == before {
+            return Ok(false);
+        }
+
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Remember the instructors a section was last seen with for everyone
+    /// watching it and save the change.
+    pub fn set_instructors(&mut self, term: &str, code: &str, instructors: &[String]) -> Result<(), Error> {
+        for watch in self.watches.iter_mut().filter(|watch| watch.term == term && watch.code == code) {
+            watch.instructors = Some(instructors.to_vec());
+        }
+
+        self.save()
+    }
+}
+
+/// How the instructors of a section changed, like "Instructor changed from
+/// TBA to Jane Smith", or nothing if they're the same people. A section with
+/// no instructors or only a placeholder like TBA is unassigned.
+pub fn instructor_change(before: &[String], after: &[String]) -> Option<String> {
+    let before = assigned(before);
+    let after = assigned(after);
+
+    if before == after {
+        return None;
+    }
+
+    let names = |names: &[String]| if names.is_empty() { "TBA".to_owned() } else { names.join(", ") };
+
+    Some(format!("Instructor changed from {} to {}", names(&before), names(&after)))
+}
+
+/// The instructors actually assigned, in order without repeats.
+fn assigned(names: &[String]) -> Vec<String> {
+    names.iter()
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty() && !UNASSIGNED.contains(&name.to_lowercase().as_str()))
+        .map(str::to_owned)
+        .sorted()
+        .into_iter()
+        .dedup()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn same_instructors_are_no_change() {
+        assert_eq!(instructor_change(&names(&["Jane Smith"]), &names(&["Jane Smith"])), None);
+        assert_eq!(instructor_change(&names(&["A B", "C D"]), &names(&["C D", "A B"])), None);
+        assert_eq!(instructor_change(&names(&[]), &names(&["TBA"])), None);
+    }
+
+    #[test]
+    fn new_instructor_is_a_change() {
+        assert_eq!(
+            instructor_change(&names(&["Jane Smith"]), &names(&["John Doe"])),
+            Some("Instructor changed from Jane Smith to John Doe".to_owned())
+        );
+    }
+
+    #[test]
+    fn assigning_an_instructor_is_a_change() {
+        assert_eq!(
+            instructor_change(&names(&["TBA"]), &names(&["Jane Smith"])),
+            Some("Instructor changed from TBA to Jane Smith".to_owned())
+        );
+        assert_eq!(
+            instructor_change(&names(&[]), &names(&["Jane Smith"])),
+            Some("Instructor changed from TBA to Jane Smith".to_owned())
+        );
+    }
+}